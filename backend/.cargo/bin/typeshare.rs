@@ -28,7 +28,7 @@ pub fn main() {
         //     "backend/entity.d.ts",
         //     vec!["./crates/entity"],
         // ),
-        ("backend/api.ts", vec!["./crates/api"]),
+        ("backend/api.ts", vec!["./crates/api", "./crates/file-watcher"]),
     ]);
 
     for (output, inputs) in mapping {
@@ -0,0 +1,545 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use entity::{file_data, files, files_tags};
+use file_watcher::data::FileData;
+use sea_orm::{
+    prelude::*,
+    sea_query::{LikeExpr, Query, SelectStatement, SimpleExpr},
+    Condition, DbBackend,
+};
+use serde::Serialize;
+use typeshare::typeshare;
+
+#[derive(Debug, Serialize, Default, FromFormField, Clone, Copy, PartialEq, Eq)]
+#[typeshare]
+pub enum TagMode {
+    /// Files need to have all of the tags
+    #[default]
+    All,
+    /// Files need to have at least one of the tags
+    Any,
+}
+
+/// Filters for file listings. All of the given filters have to match.
+///
+/// Dates can be given as `2023`, `2023-06`, `2023-06-01` or a full RFC 3339 timestamp
+/// and cover the whole period, so `modified_to=2023` includes all of 2023.
+#[derive(Debug, Clone, Serialize, Default, FromForm)]
+#[typeshare]
+#[serde(rename_all = "camelCase")]
+pub struct Filter {
+    /// IDs of tags the files need to have
+    pub tags: Vec<i32>,
+    pub tag_mode: Option<TagMode>,
+    /// IDs of tags the files must not have
+    pub exclude_tags: Vec<i32>,
+    /// MIME types or type prefixes (eg. `video/`). Files need to match one of them.
+    pub types: Vec<String>,
+    #[typeshare(serialized_as = "Option<String>")]
+    pub min_size: Option<u64>,
+    #[typeshare(serialized_as = "Option<String>")]
+    pub max_size: Option<u64>,
+    pub modified_from: Option<String>,
+    pub modified_to: Option<String>,
+    pub created_from: Option<String>,
+    pub created_to: Option<String>,
+    pub min_width: Option<u32>,
+    pub max_width: Option<u32>,
+    pub min_height: Option<u32>,
+    pub max_height: Option<u32>,
+    /// In seconds
+    pub min_duration: Option<f64>,
+    /// In seconds
+    pub max_duration: Option<f64>,
+    /// Container format names (eg. `mp4`). Files need to match one of them.
+    pub containers: Vec<String>,
+    /// Codec names as reported by ffprobe (eg. `h264`). Files need to match one of them.
+    pub video_codecs: Vec<String>,
+    /// Codec names as reported by ffprobe (eg. `aac`). Files need to match one of them.
+    pub audio_codecs: Vec<String>,
+    pub min_frame_rate: Option<f64>,
+    pub max_frame_rate: Option<f64>,
+    /// In bits per second
+    #[typeshare(serialized_as = "Option<String>")]
+    pub min_bit_rate: Option<u64>,
+    /// In bits per second
+    #[typeshare(serialized_as = "Option<String>")]
+    pub max_bit_rate: Option<u64>,
+    /// Degrees the videos are turned by (0, 90, 180 or 270). Files need to match one of them.
+    pub rotations: Vec<i32>,
+    /// Pixel formats as reported by ffprobe (eg. `yuv420p`). Files need to match one of them.
+    pub pixel_formats: Vec<String>,
+    pub has_audio: Option<bool>,
+}
+
+impl Filter {
+    pub fn tag_mode(&self) -> TagMode {
+        self.tag_mode.unwrap_or_default()
+    }
+
+    /// Condition over the `files` table. Fails on invalid dates.
+    pub fn condition(&self, backend: DbBackend) -> Result<Condition> {
+        let mut condition = Condition::all();
+
+        if !self.tags.is_empty() {
+            condition = match self.tag_mode() {
+                TagMode::All => self.tags.iter().fold(condition, |acc, x| {
+                    acc.add(files::Column::Id.in_subquery(files_with_tags(&[*x])))
+                }),
+                TagMode::Any => {
+                    condition.add(files::Column::Id.in_subquery(files_with_tags(&self.tags)))
+                }
+            };
+        }
+
+        if !self.exclude_tags.is_empty() {
+            condition = condition
+                .add(files::Column::Id.not_in_subquery(files_with_tags(&self.exclude_tags)));
+        }
+
+        if !self.types.is_empty() {
+            condition = condition.add(self.types.iter().fold(Condition::any(), |acc, x| {
+                if x.ends_with('/') {
+                    acc.add(column_like(
+                        files::Column::FileType,
+                        format!("{}%", escape_like(x)),
+                    ))
+                } else {
+                    acc.add(files::Column::FileType.eq(x))
+                }
+            }));
+        }
+
+        if let Some(min_size) = self.min_size {
+            condition = condition.add(files::Column::FileSize.gte(min_size));
+        }
+
+        if let Some(max_size) = self.max_size {
+            condition = condition.add(files::Column::FileSize.lte(max_size));
+        }
+
+        condition = condition
+            .add_option(date_from(
+                files::Column::FileMtime,
+                self.modified_from.as_deref(),
+            )?)
+            .add_option(date_to(
+                files::Column::FileMtime,
+                self.modified_to.as_deref(),
+            )?)
+            .add_option(date_from(
+                files::Column::FileCtime,
+                self.created_from.as_deref(),
+            )?)
+            .add_option(date_to(
+                files::Column::FileCtime,
+                self.created_to.as_deref(),
+            )?);
+
+        if let Some(dimensions) = self.dimensions_condition(backend) {
+            let subquery = Query::select()
+                .column(file_data::Column::FileId)
+                .from(file_data::Entity)
+                .and_where(file_data::Column::Key.eq(FileData::MEDIA_DIMENSIONS))
+                .cond_where(dimensions)
+                .to_owned();
+
+            condition = condition.add(files::Column::Id.in_subquery(subquery));
+        }
+
+        if let Some(media_info) = self.media_info_condition(backend) {
+            let subquery = Query::select()
+                .column(file_data::Column::FileId)
+                .from(file_data::Entity)
+                .and_where(file_data::Column::Key.eq(FileData::MEDIA_INFO))
+                .cond_where(media_info)
+                .to_owned();
+
+            condition = condition.add(files::Column::Id.in_subquery(subquery));
+        }
+
+        Ok(condition)
+    }
+
+    fn dimensions_condition(&self, backend: DbBackend) -> Option<Condition> {
+        let bounds = [
+            ("width", ">=", self.min_width),
+            ("width", "<=", self.max_width),
+            ("height", ">=", self.min_height),
+            ("height", "<=", self.max_height),
+        ];
+
+        let condition = bounds
+            .into_iter()
+            .filter_map(|(field, op, value)| Some(meta_number(backend, field, op, value?)))
+            .fold(Condition::all(), Condition::add);
+
+        if condition.is_empty() {
+            None
+        } else {
+            Some(condition)
+        }
+    }
+
+    fn media_info_condition(&self, backend: DbBackend) -> Option<Condition> {
+        let number = |field, op, value: Option<Value>| {
+            Some(meta_compare(backend, field, MetaType::Number, op, value?))
+        };
+
+        let bounds = [
+            number("duration", ">=", self.min_duration.map(Into::into)),
+            number("duration", "<=", self.max_duration.map(Into::into)),
+            number("frameRate", ">=", self.min_frame_rate.map(Into::into)),
+            number("frameRate", "<=", self.max_frame_rate.map(Into::into)),
+            number("bitRate", ">=", self.min_bit_rate.map(Into::into)),
+            number("bitRate", "<=", self.max_bit_rate.map(Into::into)),
+            self.has_audio
+                .map(|x| meta_compare(backend, "hasAudio", MetaType::Bool, "=", i32::from(x))),
+        ];
+
+        let one_of = |field, values: &[String]| {
+            (!values.is_empty()).then(|| {
+                values.iter().fold(Condition::any(), |acc, x| {
+                    acc.add(meta_compare(
+                        backend,
+                        field,
+                        MetaType::Text,
+                        "=",
+                        x.to_lowercase(),
+                    ))
+                })
+            })
+        };
+
+        let lists = [
+            one_of("videoCodec", &self.video_codecs),
+            one_of("audioCodec", &self.audio_codecs),
+            one_of("pixelFormat", &self.pixel_formats),
+            self.containers_condition(backend),
+            (!self.rotations.is_empty()).then(|| {
+                self.rotations.iter().fold(Condition::any(), |acc, x| {
+                    acc.add(meta_compare(
+                        backend,
+                        "rotation",
+                        MetaType::Number,
+                        "=",
+                        x.rem_euclid(360),
+                    ))
+                })
+            }),
+        ];
+
+        let mut condition = bounds
+            .into_iter()
+            .flatten()
+            .fold(Condition::all(), Condition::add);
+
+        for list in lists.into_iter().flatten() {
+            condition = condition.add(list);
+        }
+
+        if condition.is_empty() {
+            None
+        } else {
+            Some(condition)
+        }
+    }
+
+    /// ffprobe names containers by all the formats they can be, eg. `matroska,webm`, so any of
+    /// the names matches
+    fn containers_condition(&self, backend: DbBackend) -> Option<Condition> {
+        if self.containers.is_empty() {
+            return None;
+        }
+
+        let condition = self.containers.iter().fold(Condition::any(), |acc, x| {
+            let name = x.to_lowercase();
+            let escaped = escape_like(&name);
+
+            [
+                format!("{escaped},%"),
+                format!("%,{escaped}"),
+                format!("%,{escaped},%"),
+            ]
+            .into_iter()
+            .fold(
+                acc.add(meta_compare(
+                    backend,
+                    "container",
+                    MetaType::Text,
+                    "=",
+                    name,
+                )),
+                |acc, pattern| acc.add(meta_like(backend, "container", pattern)),
+            )
+        });
+
+        Some(condition)
+    }
+}
+
+/// How a field in the JSON of `file_data.meta` is read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetaType {
+    Number,
+    Text,
+    /// As 1 or 0
+    Bool,
+}
+
+/// SQL reading the field of the JSON in `file_data.meta` as the type. `NULL` for rows with
+/// invalid JSON.
+pub fn meta_field(backend: DbBackend, field: &str, kind: MetaType) -> String {
+    match (backend, kind) {
+        (DbBackend::Sqlite, _) => format!(r#"json_extract("meta", '$.{field}')"#),
+        (DbBackend::Postgres, kind) => {
+            let cast = match kind {
+                MetaType::Number => "::numeric",
+                MetaType::Text => "",
+                MetaType::Bool => "::boolean::int",
+            };
+
+            format!(r#"(CASE WHEN "meta" LIKE '{{%' THEN ("meta"::jsonb ->> '{field}'){cast} END)"#)
+        }
+        (DbBackend::MySql, kind) => {
+            let value = match kind {
+                MetaType::Number => format!("JSON_EXTRACT(`meta`, '$.{field}')"),
+                MetaType::Text => format!("JSON_UNQUOTE(JSON_EXTRACT(`meta`, '$.{field}'))"),
+                MetaType::Bool => {
+                    format!("JSON_EXTRACT(`meta`, '$.{field}') = CAST('true' AS JSON)")
+                }
+            };
+
+            format!("(CASE WHEN JSON_VALID(`meta`) THEN {value} END)")
+        }
+    }
+}
+
+/// Compare a field in the JSON of `file_data.meta`, eg.
+/// `meta_compare(backend, "width", MetaType::Number, ">=", 1000)`. Rows with invalid JSON don't
+/// match.
+pub fn meta_compare(
+    backend: DbBackend,
+    field: &str,
+    kind: MetaType,
+    op: &str,
+    value: impl Into<Value>,
+) -> SimpleExpr {
+    Expr::cust_with_values(
+        format!(
+            "{} {op} {}",
+            meta_field(backend, field, kind),
+            placeholder(backend)
+        ),
+        [value.into()],
+    )
+}
+
+/// Match a text in the JSON of `file_data.meta` against a `LIKE` pattern. Escape user input
+/// in the pattern with [`escape_like`].
+pub fn meta_like(backend: DbBackend, field: &str, pattern: String) -> SimpleExpr {
+    Expr::cust_with_values(
+        format!(
+            "{} LIKE {} ESCAPE '{LIKE_ESCAPE}'",
+            meta_field(backend, field, MetaType::Text),
+            placeholder(backend)
+        ),
+        [pattern],
+    )
+}
+
+/// Match a column against a `LIKE` pattern. Escape user input in the pattern with
+/// [`escape_like`].
+pub fn column_like(column: impl ColumnTrait, pattern: String) -> SimpleExpr {
+    Expr::col((column.entity_name(), column)).like(LikeExpr::new(pattern).escape(LIKE_ESCAPE))
+}
+
+/// Escapes the wildcards in `LIKE` patterns. Not a backslash, as MySQL reads that as an escape
+/// in string literals too.
+const LIKE_ESCAPE: char = '!';
+
+/// Make the text match only itself in a `LIKE` pattern, see [`meta_like`]
+pub fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        if matches!(c, '%' | '_' | LIKE_ESCAPE) {
+            escaped.push(LIKE_ESCAPE);
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+fn placeholder(backend: DbBackend) -> &'static str {
+    match backend {
+        DbBackend::Postgres => "$1",
+        DbBackend::Sqlite | DbBackend::MySql => "?",
+    }
+}
+
+/// Compare a number in the JSON of `file_data.meta`, eg. `meta_number(backend, "width", ">=", 1000)`.
+/// Rows with invalid JSON don't match.
+pub fn meta_number(
+    backend: DbBackend,
+    field: &str,
+    op: &str,
+    value: impl Into<Value>,
+) -> SimpleExpr {
+    meta_compare(backend, field, MetaType::Number, op, value)
+}
+
+fn files_with_tags(tag_ids: &[i32]) -> SelectStatement {
+    Query::select()
+        .column(files_tags::Column::FileId)
+        .from(files_tags::Entity)
+        .and_where(files_tags::Column::TagId.is_in(tag_ids.iter().copied()))
+        .to_owned()
+}
+
+fn date_from(column: files::Column, value: Option<&str>) -> Result<Option<SimpleExpr>> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+
+    let (start, _) = parse_period(value)?;
+
+    Ok(Some(column.gte(start)))
+}
+
+fn date_to(column: files::Column, value: Option<&str>) -> Result<Option<SimpleExpr>> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+
+    let (_, end) = parse_period(value)?;
+
+    Ok(Some(column.lt(end)))
+}
+
+/// Start and (exclusive) end of the period described by the value
+pub fn parse_period(value: &str) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
+    if let Ok(x) = DateTime::parse_from_rfc3339(value) {
+        let x = x.with_timezone(&Utc);
+        return Ok((x, x + Duration::milliseconds(1)));
+    }
+
+    let invalid = || anyhow!("Invalid date: {:?}", value);
+
+    let parts = value
+        .split('-')
+        .map(str::parse::<u32>)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid())?;
+
+    let (start, end) = match parts[..] {
+        [year] => {
+            let year = i32::try_from(year)?;
+            let start = NaiveDate::from_ymd_opt(year, 1, 1).ok_or_else(invalid)?;
+            let end = NaiveDate::from_ymd_opt(year + 1, 1, 1).ok_or_else(invalid)?;
+            (start, end)
+        }
+        [year, month] => {
+            let year = i32::try_from(year)?;
+            let start = NaiveDate::from_ymd_opt(year, month, 1).ok_or_else(invalid)?;
+            let end = if month == 12 {
+                NaiveDate::from_ymd_opt(year + 1, 1, 1)
+            } else {
+                NaiveDate::from_ymd_opt(year, month + 1, 1)
+            }
+            .ok_or_else(invalid)?;
+            (start, end)
+        }
+        [year, month, day] => {
+            let start =
+                NaiveDate::from_ymd_opt(i32::try_from(year)?, month, day).ok_or_else(invalid)?;
+            let end = start.succ_opt().ok_or_else(invalid)?;
+            (start, end)
+        }
+        _ => return Err(invalid()),
+    };
+
+    Ok((
+        start.and_time(NaiveTime::MIN).and_utc(),
+        end.and_time(NaiveTime::MIN).and_utc(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use migration::MigratorTrait;
+    use sea_orm::{ActiveValue::Set, Database, QueryOrder};
+
+    use super::*;
+
+    #[test]
+    fn escapes_like_wildcards() {
+        assert_eq!(escape_like("mp4"), "mp4");
+        assert_eq!(escape_like("a_b%c!d"), "a!_b!%c!!d");
+    }
+
+    #[tokio::test]
+    async fn containers_match_names_literally() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        migration::Migrator::up(&db, None).await.unwrap();
+
+        for (i, container) in ["mp4", "mov,mp4,m4a", "mpeg_4", "m%4,avi!"]
+            .into_iter()
+            .enumerate()
+        {
+            let file = files::ActiveModel {
+                ulid: Set(format!("ulid-{i}")),
+                path: Set(format!("{i}.mp4")),
+                hash: Set(format!("hash-{i}")),
+                created_at: Set(Utc::now()),
+                ..Default::default()
+            }
+            .insert(&db)
+            .await
+            .unwrap();
+
+            file_data::ActiveModel {
+                file_id: Set(file.id),
+                key: Set(FileData::MEDIA_INFO.to_string()),
+                value: Set(String::new()),
+                meta: Set(serde_json::json!({ "container": container }).to_string()),
+                created_at: Set(Utc::now()),
+                ..Default::default()
+            }
+            .insert(&db)
+            .await
+            .unwrap();
+        }
+
+        let matching = |containers: &[&str]| {
+            let filter = Filter {
+                containers: containers.iter().map(ToString::to_string).collect(),
+                ..Default::default()
+            };
+            let condition = filter.condition(DbBackend::Sqlite).unwrap();
+            let db = &db;
+
+            async move {
+                files::Entity::find()
+                    .filter(condition)
+                    .order_by_asc(files::Column::Id)
+                    .all(db)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|x| x.path)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(matching(&["MP4"]).await, ["0.mp4", "1.mp4"]);
+        assert_eq!(matching(&["mpeg_4"]).await, ["2.mp4"]);
+        assert_eq!(matching(&["avi!"]).await, ["3.mp4"]);
+        assert!(matching(&["m_4"]).await.is_empty());
+        assert!(matching(&["%"]).await.is_empty());
+        assert_eq!(matching(&["m%4"]).await, ["3.mp4"]);
+    }
+}
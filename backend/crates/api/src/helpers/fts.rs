@@ -0,0 +1,157 @@
+//! Helpers for the `files_fts` full-text search table.
+//!
+//! The table is kept in sync with `files.path` and text-bearing `file_data`
+//! by triggers, and its rowid is the id of the file.
+//!
+//! SQLite uses FTS5, Postgres a `tsvector` column and MySQL a `FULLTEXT` index,
+//! so the queries are built differently for each.
+
+use sea_orm::{prelude::*, sea_query::SimpleExpr, DbBackend, Order};
+
+/// Marks the start of a match in snippets
+const MATCH_START: char = '\u{1}';
+/// Marks the end of a match in snippets
+const MATCH_END: char = '\u{2}';
+
+/// Turn user input into a query that matches all of the words as prefixes.
+///
+/// Returns `None` if there is nothing to search for.
+pub fn match_query(backend: DbBackend, text: &str) -> Option<String> {
+    let terms = match backend {
+        DbBackend::Sqlite => text
+            .split_whitespace()
+            .map(|x| format!("\"{}\"*", x.replace('"', "\"\"")))
+            .collect::<Vec<_>>(),
+        DbBackend::Postgres => words(text).map(|x| format!("{x}:*")).collect(),
+        DbBackend::MySql => words(text).map(|x| format!("+{x}*")).collect(),
+    };
+
+    all_of(backend, &terms)
+}
+
+/// Turn user input into a query that matches it as an exact phrase
+pub fn phrase_query(backend: DbBackend, text: &str) -> Option<String> {
+    let text = text.trim();
+
+    if text.is_empty() {
+        return None;
+    }
+
+    match backend {
+        DbBackend::Sqlite => Some(format!("\"{}\"", text.replace('"', "\"\""))),
+        DbBackend::Postgres => {
+            Some(words(text).collect::<Vec<_>>().join(" <-> ")).filter(|x| !x.is_empty())
+        }
+        DbBackend::MySql => Some(words(text).collect::<Vec<_>>().join(" "))
+            .filter(|x| !x.is_empty())
+            .map(|x| format!("+\"{x}\"")),
+    }
+}
+
+/// Combine queries so that all of them have to match
+pub fn all_of(backend: DbBackend, queries: &[String]) -> Option<String> {
+    if queries.is_empty() {
+        return None;
+    }
+
+    Some(match backend {
+        DbBackend::Sqlite | DbBackend::MySql => queries.join(" "),
+        DbBackend::Postgres => queries
+            .iter()
+            .map(|x| format!("({x})"))
+            .collect::<Vec<_>>()
+            .join(" & "),
+    })
+}
+
+/// The words of user input, without anything that has a meaning in the query syntax
+/// of Postgres or MySQL
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|x: char| !x.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Condition over `files_fts` for the rows matching the query
+pub fn matches(backend: DbBackend, query: &str) -> SimpleExpr {
+    let sql = match backend {
+        DbBackend::Sqlite => r#""files_fts" MATCH ?"#,
+        DbBackend::Postgres => r#""document" @@ to_tsquery('simple', $1)"#,
+        DbBackend::MySql => "MATCH (`path`, `text`) AGAINST (? IN BOOLEAN MODE)",
+    };
+
+    Expr::cust_with_values(sql, [query])
+}
+
+/// Order of `files_fts` rows for the best matches of the query first.
+/// Matches in the path count more than in the text.
+pub fn rank(backend: DbBackend, query: &str) -> (SimpleExpr, Order) {
+    match backend {
+        DbBackend::Sqlite => (Expr::cust(r#"bm25("files_fts", 2.0, 1.0)"#), Order::Asc),
+        DbBackend::Postgres => (
+            Expr::cust_with_values(r#"ts_rank("document", to_tsquery('simple', $1))"#, [query]),
+            Order::Desc,
+        ),
+        DbBackend::MySql => (
+            Expr::cust_with_values(
+                "MATCH (`path`, `text`) AGAINST (? IN BOOLEAN MODE)",
+                [query],
+            ),
+            Order::Desc,
+        ),
+    }
+}
+
+/// SQL for a snippet of the best matching column of a `files_fts` row, with the matches
+/// marked by [`MATCH_START`] and [`MATCH_END`]. Use [`snippet_to_html`] on the result.
+///
+/// MySQL can't mark the matches, so there it's just the start of the text.
+pub fn snippet(backend: DbBackend, query: &str) -> SimpleExpr {
+    match backend {
+        DbBackend::Sqlite => Expr::cust(format!(
+            r#"snippet("files_fts", -1, char({}), char({}), '…', 16)"#,
+            u32::from(MATCH_START),
+            u32::from(MATCH_END),
+        )),
+        DbBackend::Postgres => Expr::cust_with_values(
+            r#"
+            ts_headline(
+                'simple',
+                CASE WHEN to_tsvector('simple', "text") @@ to_tsquery('simple', $1)
+                    THEN "text"
+                    ELSE regexp_replace("path", '[^[:alnum:]]+', ' ', 'g')
+                END,
+                to_tsquery('simple', $1),
+                $2
+            )
+            "#,
+            [
+                query.to_string(),
+                format!(
+                    "StartSel=\"{MATCH_START}\", StopSel=\"{MATCH_END}\", MaxWords=16, MinWords=8"
+                ),
+            ],
+        ),
+        DbBackend::MySql => Expr::cust("LEFT(IF(`text` = '', `path`, `text`), 120)"),
+    }
+}
+
+/// Escape the snippet and wrap the matches in `<mark>`
+pub fn snippet_to_html(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+
+    for c in snippet.chars() {
+        match c {
+            MATCH_START => html.push_str("<mark>"),
+            MATCH_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+
+    html
+}
@@ -1,3 +1,5 @@
+pub mod filter;
+pub mod fts;
 pub mod order;
 pub mod pagination;
 pub mod range_responder;
@@ -1,13 +1,52 @@
-use serde::Serialize;
+use anyhow::Result;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
 use typeshare::typeshare;
 
-#[derive(Debug, Clone, Copy, Serialize, Default, FromForm)]
+/// Either page based (`page`) or cursor based (`cursor`) pagination.
+///
+/// Responses include `next` and `prev` cursors in both modes, so a client can start
+/// on a page and continue with cursors. With a cursor the total is not counted.
+#[derive(Debug, Clone, Serialize, Default, FromForm)]
 #[typeshare]
 #[serde(rename_all = "camelCase")]
 pub struct Pagination {
     pub page: Option<u32>,
     pub per_page: Option<u32>,
     pub total_pages: Option<u32>,
+    pub cursor: Option<String>,
+    pub next: Option<String>,
+    pub prev: Option<String>,
+}
+
+/// Position in a list ordered by a key and then the file id
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    #[serde(rename = "k")]
+    pub key: CursorKey,
+    #[serde(rename = "i")]
+    pub id: i32,
+    /// Whether the items before the position are wanted instead of the ones after it
+    #[serde(rename = "b", default, skip_serializing_if = "std::ops::Not::not")]
+    pub backwards: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CursorKey {
+    Number(i64),
+    Text(String),
+    Float(f64),
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(value: &str) -> Result<Self> {
+        Ok(serde_json::from_slice(&URL_SAFE_NO_PAD.decode(value)?)?)
+    }
 }
 
 pub const DEFAULT_PAGE: u32 = 1;
@@ -32,8 +71,16 @@ impl Pagination {
         (self.page() - 1) * self.per_page()
     }
 
+    /// The decoded cursor, if the request uses one
+    pub fn cursor(&self) -> Result<Option<Cursor>> {
+        self.cursor.as_deref().map(Cursor::decode).transpose()
+    }
+
     pub fn with_defaults(mut self) -> Self {
-        self.page = self.page.or(Some(DEFAULT_PAGE));
+        self.page = self
+            .page
+            .or(Some(DEFAULT_PAGE))
+            .filter(|_| self.cursor.is_none());
         self.per_page = self.per_page.or(Some(DEFAULT_PER_PAGE));
 
         self
@@ -43,6 +90,11 @@ impl Pagination {
         self.total_pages = Some(self.calculate_total_pages(total_items).try_into().unwrap());
     }
 
+    pub fn set_cursors(&mut self, next: Option<&Cursor>, prev: Option<&Cursor>) {
+        self.next = next.map(Cursor::encode);
+        self.prev = prev.map(Cursor::encode);
+    }
+
     pub fn calculate_total_pages(&self, total_items: u64) -> u64 {
         (total_items / self.per_page()) + u64::from(total_items % self.per_page() > 0)
     }
@@ -55,3 +107,108 @@ pub struct PaginationResponse<T> {
     pub items: T,
     pub pagination: Pagination,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor(key: CursorKey, backwards: bool) -> Cursor {
+        Cursor {
+            key,
+            id: 42,
+            backwards,
+        }
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        let cursors = [
+            cursor(CursorKey::Number(-7), false),
+            cursor(CursorKey::Number(i64::MAX), true),
+            cursor(CursorKey::Text("2023-06-01T12:00:00Z".into()), false),
+            cursor(CursorKey::Text("h264 \"main\" ü".into()), true),
+            cursor(CursorKey::Text(String::new()), false),
+            cursor(CursorKey::Float(29.97), false),
+            cursor(CursorKey::Float(0.5), true),
+        ];
+
+        for cursor in cursors {
+            let encoded = cursor.encode();
+
+            assert!(
+                encoded
+                    .chars()
+                    .all(|x| x.is_ascii_alphanumeric() || x == '-' || x == '_'),
+                "{encoded}"
+            );
+            assert_eq!(Cursor::decode(&encoded).unwrap(), cursor);
+        }
+    }
+
+    #[test]
+    fn forward_cursors_leave_out_the_direction() {
+        let forward = cursor(CursorKey::Number(1), false).encode();
+        let backward = cursor(CursorKey::Number(1), true).encode();
+
+        assert_eq!(
+            URL_SAFE_NO_PAD.decode(forward).unwrap(),
+            br#"{"k":1,"i":42}"#
+        );
+        assert_eq!(
+            URL_SAFE_NO_PAD.decode(backward).unwrap(),
+            br#"{"k":1,"i":42,"b":true}"#
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_cursors() {
+        let encode = |x: &str| URL_SAFE_NO_PAD.encode(x);
+
+        let invalid = [
+            String::new(),
+            "not a cursor".into(),
+            "eyJrIjoxLCJpIjo0Mn0=".into(),
+            encode(r#"{"k":1,"i":42}"#)[1..].to_string(),
+            encode(r#"{"k":1}"#),
+            encode(r#"{"i":42}"#),
+            encode(r#"{"k":1,"i":"42"}"#),
+            encode(r#"{"k":1,"i":4294967296}"#),
+            encode(r#"{"k":[1],"i":42}"#),
+            encode(r#"{"k":null,"i":42}"#),
+            encode(r#"{"k":1,"i":42,"b":1}"#),
+        ];
+
+        for value in invalid {
+            assert!(Cursor::decode(&value).is_err(), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn pagination_decodes_its_cursor() {
+        let mut pagination = Pagination::default();
+        assert_eq!(pagination.cursor().unwrap(), None);
+
+        let expected = cursor(CursorKey::Float(1.5), true);
+        pagination.cursor = Some(expected.encode());
+        assert_eq!(pagination.cursor().unwrap(), Some(expected));
+
+        pagination.cursor = Some("%%%".into());
+        assert!(pagination.cursor().is_err());
+    }
+
+    #[test]
+    fn cursor_replaces_the_default_page() {
+        let pagination = Pagination {
+            cursor: Some(cursor(CursorKey::Number(1), false).encode()),
+            ..Default::default()
+        }
+        .with_defaults();
+
+        assert_eq!(pagination.page, None);
+        assert_eq!(pagination.per_page, Some(DEFAULT_PER_PAGE));
+        assert_eq!(
+            Pagination::default().with_defaults().page,
+            Some(DEFAULT_PAGE)
+        );
+    }
+}
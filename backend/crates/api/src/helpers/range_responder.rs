@@ -1,11 +1,11 @@
 use std::{
     fs::Metadata,
     io::{Cursor, SeekFrom},
-    ops::{RangeInclusive, Sub},
+    ops::RangeInclusive,
     path::{Path, PathBuf},
 };
 
-use chrono::{prelude::*, Duration};
+use chrono::prelude::*;
 use futures::executor;
 use rocket::{
     self,
@@ -214,6 +214,20 @@ impl<'r, R> RangeResponder<R> {
     }
 
     fn respond_to_cache_headers(&self, req_headers: &HeaderMap) -> Result<(), Status> {
+        // The ETag decides on its own when the client sent one
+        if let Some(if_none_match) = req_headers.get_one(header::IF_NONE_MATCH.as_str()) {
+            let etag = self.additional_headers.get_one(header::ETAG.as_str());
+
+            let matches = if_none_match == "*"
+                || etag.is_some_and(|etag| if_none_match.split(',').any(|x| x.trim() == etag));
+
+            return if matches {
+                Err(Status::NotModified)
+            } else {
+                Ok(())
+            };
+        }
+
         if let Some(metadata) = self.metadata.as_ref() {
             let req_time = req_headers
                 .get_one(header::IF_MODIFIED_SINCE.as_str())
@@ -222,29 +236,10 @@ impl<'r, R> RangeResponder<R> {
 
             if let Some(req_time) = req_time {
                 if let Ok(mtime) = metadata.modified() {
-                    let file_time: DateTime<Utc> = mtime.into();
-
-                    if file_time.sub(req_time) > Duration::milliseconds(10) {
-                        return Err(Status::NotModified);
-                    }
-                }
-            }
-        }
-
-        let etag = self.additional_headers.get_one(header::ETAG.as_str());
-
-        if let Some(etag) = etag {
-            let if_none_match = req_headers.get_one(header::IF_NONE_MATCH.as_str());
-
-            if let Some(if_none_match) = if_none_match {
-                if if_none_match == "*" {
-                    return Err(Status::NotModified);
-                }
-
-                let if_none_match = if_none_match.split(',').map(str::trim);
+                    // HTTP dates only have whole seconds
+                    let file_time = DateTime::<Utc>::from(mtime).trunc_subsecs(0);
 
-                for match_etag in if_none_match {
-                    if etag == match_etag {
+                    if file_time <= req_time {
                         return Err(Status::NotModified);
                     }
                 }
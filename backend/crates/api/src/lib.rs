@@ -1,4 +1,4 @@
-use std::{process::exit, sync::Arc, time::Duration};
+use std::{path::Path, process::exit, sync::Arc, time::Duration};
 
 use config::CONFIG;
 use file_watcher::FileWatcher;
@@ -13,6 +13,8 @@ extern crate rocket;
 
 mod fairings;
 mod helpers;
+mod library;
+mod query;
 mod routes;
 mod setup;
 
@@ -72,6 +74,38 @@ pub async fn run() -> Result<(), rocket::Error> {
 
     let instance = instance.ignite().await?;
 
+    if let Err(e) = fw.resume_jobs().await {
+        logger::error!("failed to resume jobs: {}", e);
+    }
+
+    {
+        let fw = fw.clone();
+        task::spawn(async move {
+            fw.run_job_workers().await;
+        });
+    }
+
+    {
+        let fw = fw.clone();
+        task::spawn(async move {
+            if let Err(e) = fw.watch().await {
+                logger::error!("failed to watch directory: {}", e);
+            }
+        });
+    }
+
+    if CONFIG.app.thumb_maintenance_interval > 0 {
+        let fw = fw.clone();
+        task::spawn(async move {
+            loop {
+                time::sleep(Duration::from_secs(CONFIG.app.thumb_maintenance_interval)).await;
+                if let Err(e) = fw.maintain_thumbs().await {
+                    logger::error!("failed to maintain thumbs: {}", e);
+                }
+            }
+        });
+    }
+
     task::spawn(async move {
         loop {
             logger::info!("starting file inspection");
@@ -81,7 +115,7 @@ pub async fn run() -> Result<(), rocket::Error> {
             } else {
                 logger::info!("finished file inspection");
             }
-            time::sleep(Duration::from_secs(60)).await;
+            time::sleep(Duration::from_secs(CONFIG.app.rescan_interval)).await;
         }
     });
 
@@ -89,3 +123,43 @@ pub async fn run() -> Result<(), rocket::Error> {
 
     Ok(())
 }
+
+/// Write an export of the library to the file, either as newline-delimited JSON or as an
+/// archive with a manifest and optionally the thumbnails
+///
+/// # Panics
+///
+/// If the async runtime can't be started
+#[tokio::main]
+pub async fn export(path: &Path, archive: bool, thumbnails: bool) -> anyhow::Result<()> {
+    let db = setup::setup_db().await?;
+
+    let count = if archive {
+        library::export::write_archive(&db, path, thumbnails).await?
+    } else {
+        library::export::write_to_file(&db, path).await?
+    };
+
+    logger::info!(count, path = ?path, "exported files");
+
+    Ok(())
+}
+
+/// Import an export into the library. The directory is indexed first so all files can be matched.
+///
+/// # Panics
+///
+/// If the async runtime can't be started
+#[tokio::main]
+pub async fn import(path: &Path) -> anyhow::Result<()> {
+    let db = Arc::new(setup::setup_db().await?);
+    let fw = FileWatcher::new(db).with_recursive(false);
+
+    fw.index_files().await?;
+
+    let summary = library::import::import_from_file(&fw, path).await?;
+
+    logger::info!(?summary, path = ?path, "imported files");
+
+    Ok(())
+}
@@ -0,0 +1,193 @@
+//! Files are read with a database cursor and their data and tags are loaded in chunks,
+//! so the whole index never has to fit in memory.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::Result;
+use chrono::Utc;
+use config::CONFIG;
+use entity::{file_data, files, files_tags, tags};
+use file_watcher::data::FileData;
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use sea_orm::{prelude::*, QueryOrder, QuerySelect};
+use tokio::{
+    fs::File,
+    io::{AsyncWriteExt, BufWriter},
+    task,
+};
+
+use super::{ExportRecord, Manifest, FILES_PATH, FORMAT, FORMAT_VERSION, MANIFEST_PATH};
+
+/// How many files to load the data and tags for at once
+const CHUNK_SIZE: usize = 256;
+
+/// All files in the index, in the order they were added
+pub(crate) fn records(
+    db: &DatabaseConnection,
+) -> impl Stream<Item = Result<ExportRecord, DbErr>> + Send + '_ {
+    stream::once(
+        files::Entity::find()
+            .order_by_asc(files::Column::Id)
+            .stream(db),
+    )
+    .try_flatten()
+    .try_chunks(CHUNK_SIZE)
+    .map_err(|e| e.1)
+    .and_then(move |files| load_records(db, files))
+    .map_ok(|records| stream::iter(records.into_iter().map(Ok)))
+    .try_flatten()
+}
+
+/// [`records`] as lines of JSON
+pub(crate) fn lines(db: &DatabaseConnection) -> impl Stream<Item = Result<String>> + Send + '_ {
+    records(db).map(|record| {
+        let mut line = serde_json::to_string(&record?)?;
+        line.push('\n');
+        Ok(line)
+    })
+}
+
+/// Write the export to a file as newline-delimited JSON, replacing it if it exists
+pub(crate) async fn write_to_file(db: &DatabaseConnection, path: &Path) -> Result<u64> {
+    let mut file = BufWriter::new(File::create(path).await?);
+    let mut lines = Box::pin(lines(db));
+    let mut count = 0;
+
+    while let Some(line) = lines.try_next().await? {
+        file.write_all(line.as_bytes()).await?;
+        count += 1;
+    }
+
+    file.flush().await?;
+
+    Ok(count)
+}
+
+/// Write an archive with a manifest and optionally the thumbnails, replacing it if it exists
+pub(crate) async fn write_archive(
+    db: &DatabaseConnection,
+    path: &Path,
+    thumbnails: bool,
+) -> Result<u64> {
+    let metadata_directory = Some(CONFIG.app.metadata_directory.as_path()).filter(|_| thumbnails);
+
+    archive(db, path, metadata_directory).await
+}
+
+/// Write an archive, including the generated files (thumbnails, sprites, …) from the
+/// metadata directory if one is given
+pub(super) async fn archive(
+    db: &DatabaseConnection,
+    path: &Path,
+    metadata_directory: Option<&Path>,
+) -> Result<u64> {
+    let tmp_dir = tempfile::tempdir()?;
+    let files_path = tmp_dir.path().join(FILES_PATH);
+
+    let count = write_to_file(db, &files_path).await?;
+
+    let manifest = Manifest {
+        format: FORMAT.to_string(),
+        version: FORMAT_VERSION,
+        exported_at: Utc::now().to_rfc3339(),
+        files: count,
+        thumbnails: metadata_directory.is_some(),
+    };
+
+    let data_files = match metadata_directory {
+        Some(dir) => data_file_paths(db)
+            .await?
+            .into_iter()
+            .map(|x| (dir.join(&x), x))
+            .collect(),
+        None => vec![],
+    };
+
+    let path = path.to_path_buf();
+
+    task::spawn_blocking(move || -> Result<()> {
+        let mut archive = tar::Builder::new(fs::File::create(path)?);
+
+        let manifest = serde_json::to_vec_pretty(&manifest)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(Utc::now().timestamp().unsigned_abs());
+        archive.append_data(&mut header, MANIFEST_PATH, manifest.as_slice())?;
+
+        archive.append_path_with_name(&files_path, FILES_PATH)?;
+
+        for (absolute_path, path) in data_files {
+            if absolute_path.is_file() {
+                archive.append_path_with_name(absolute_path, path)?;
+            }
+        }
+
+        archive.into_inner()?.sync_all()?;
+
+        Ok(())
+    })
+    .await??;
+
+    Ok(count)
+}
+
+/// Paths of all generated files, relative to the metadata directory
+async fn data_file_paths(db: &DatabaseConnection) -> Result<Vec<String>, DbErr> {
+    let paths = file_data::Entity::find()
+        .filter(FileData::file_key_condition())
+        .select_only()
+        .columns([file_data::Column::Key, file_data::Column::Value])
+        .into_tuple::<(String, String)>()
+        .all(db)
+        .await?
+        .into_iter()
+        .filter(|(key, _)| FileData::is_file_key(key))
+        .map(|(_, value)| value)
+        .collect();
+
+    Ok(paths)
+}
+
+async fn load_records(
+    db: &DatabaseConnection,
+    files: Vec<files::Model>,
+) -> Result<Vec<ExportRecord>, DbErr> {
+    let file_ids = files.iter().map(|x| x.id).collect::<Vec<_>>();
+
+    let mut file_data = file_data::Entity::find()
+        .filter(file_data::Column::FileId.is_in(file_ids.clone()))
+        .order_by_asc(file_data::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .fold(HashMap::new(), |mut acc: HashMap<i32, Vec<_>>, x| {
+            acc.entry(x.file_id).or_default().push(x.into());
+            acc
+        });
+
+    let mut file_tags = files_tags::Entity::find()
+        .filter(files_tags::Column::FileId.is_in(file_ids))
+        .find_also_related(tags::Entity)
+        .order_by_asc(files_tags::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .fold(HashMap::new(), |mut acc: HashMap<i32, Vec<_>>, (x, tag)| {
+            if let Some(tag) = tag {
+                acc.entry(x.file_id).or_default().push(tag.name);
+            }
+            acc
+        });
+
+    let records = files
+        .into_iter()
+        .map(|file| ExportRecord {
+            data: file_data.remove(&file.id).unwrap_or_default(),
+            tags: file_tags.remove(&file.id).unwrap_or_default(),
+            file,
+        })
+        .collect();
+
+    Ok(records)
+}
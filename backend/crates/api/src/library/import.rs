@@ -0,0 +1,502 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use config::CONFIG;
+use entity::{file_data, files};
+use file_watcher::{data::FileData, tags::normalize_tag_name, FileWatcher};
+use sea_orm::{prelude::*, QueryOrder, Set};
+use serde::Serialize;
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+    task,
+};
+
+use super::{
+    ExportError, ExportRecord, Manifest, FILES_PATH, FORMAT, FORMAT_VERSION, MANIFEST_PATH,
+};
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    /// Records matched to a file in the library
+    pub files: u64,
+    /// Records without a matching file
+    pub missing: u64,
+    /// Tags added to files
+    pub tags: u64,
+    /// `file_data` entries added to files
+    pub data: u64,
+}
+
+struct Importer<'a> {
+    fw: &'a FileWatcher,
+    /// Where the generated files of the export are, if they were included
+    files_root: Option<PathBuf>,
+    /// Where to copy the generated files to
+    metadata_directory: &'a Path,
+    /// Tag ids by their lowercase name
+    tag_ids: HashMap<String, i32>,
+    summary: ImportSummary,
+}
+
+/// Import an export made with [`super::export`], either plain NDJSON or an archive
+pub(crate) async fn import_from_file(fw: &FileWatcher, path: &Path) -> Result<ImportSummary> {
+    import(fw, path, &CONFIG.app.metadata_directory).await
+}
+
+async fn import(fw: &FileWatcher, path: &Path, metadata_directory: &Path) -> Result<ImportSummary> {
+    let mut first_byte = [0; 1];
+    File::open(path).await?.read_exact(&mut first_byte).await?;
+
+    // Keep the extracted archive around until the import is done
+    let mut tmp_dir = None;
+
+    let (files_path, files_root) = if first_byte[0] == b'{' {
+        (path.to_path_buf(), None)
+    } else {
+        let dir = tempfile::tempdir()?;
+        let archive_path = path.to_path_buf();
+        let unpack_path = dir.path().to_path_buf();
+
+        task::spawn_blocking(move || {
+            tar::Archive::new(fs::File::open(archive_path)?).unpack(unpack_path)
+        })
+        .await?
+        .context("Failed to extract archive")?;
+
+        let manifest: Manifest =
+            serde_json::from_slice(&tokio::fs::read(dir.path().join(MANIFEST_PATH)).await?)
+                .context("Failed to read manifest")?;
+
+        if manifest.format != FORMAT {
+            bail!("Not an export: {:?}", manifest.format);
+        }
+
+        if manifest.version > FORMAT_VERSION {
+            bail!(
+                "The export is from a newer version ({}) than supported ({})",
+                manifest.version,
+                FORMAT_VERSION
+            );
+        }
+
+        let paths = (
+            dir.path().join(FILES_PATH),
+            Some(dir.path().to_path_buf()).filter(|_| manifest.thumbnails),
+        );
+        tmp_dir = Some(dir);
+
+        paths
+    };
+
+    let mut importer = Importer {
+        fw,
+        files_root,
+        metadata_directory,
+        tag_ids: HashMap::new(),
+        summary: ImportSummary::default(),
+    };
+
+    let mut lines = BufReader::new(File::open(files_path).await?).lines();
+    let mut line_number = 0;
+
+    while let Some(line) = lines.next_line().await? {
+        line_number += 1;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Ok(ExportError { error }) = serde_json::from_str(&line) {
+            bail!("The export failed on line {line_number}: {error}");
+        }
+
+        let record: ExportRecord = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid record on line {line_number}"))?;
+
+        importer
+            .import(record)
+            .await
+            .with_context(|| format!("Failed to import the record on line {line_number}"))?;
+    }
+
+    drop(tmp_dir);
+
+    Ok(importer.summary)
+}
+
+impl Importer<'_> {
+    async fn import(&mut self, record: ExportRecord) -> Result<()> {
+        let Some(file) = self.find_file(&record.file).await? else {
+            logger::debug!(path = ?record.file.path, "No matching file for record");
+            self.summary.missing += 1;
+            return Ok(());
+        };
+
+        self.summary.files += 1;
+
+        let mut tag_ids = vec![];
+        for name in &record.tags {
+            if let Some(id) = self.tag_id(name).await? {
+                tag_ids.push(id);
+            }
+        }
+
+        self.summary.tags += self.fw.add_tags(&[file.id], &tag_ids).await?;
+
+        // The data is about the contents, so it only carries over to the same contents
+        if file.hash != record.file.hash {
+            return Ok(());
+        }
+
+        let mut keys = file_data::Entity::find()
+            .filter(file_data::Column::FileId.eq(file.id))
+            .all(self.fw.db())
+            .await?
+            .into_iter()
+            .map(|x| x.key)
+            .collect::<HashSet<_>>();
+
+        for data in record.data {
+            if keys.contains(&data.key) {
+                continue;
+            }
+
+            let mut meta = data.meta;
+
+            let value = if FileData::is_file_key(&data.key) {
+                let Some(value) = self.copy_data_file(&data.value, &file).await? else {
+                    continue;
+                };
+
+                // The payload has the path too
+                if let Some(path) = meta.as_mut().and_then(|x| x.get_mut("path")) {
+                    *path = value.clone().into();
+                }
+
+                value
+            } else {
+                data.value
+            };
+
+            file_data::ActiveModel {
+                file_id: Set(file.id),
+                key: Set(data.key.clone()),
+                value: Set(value),
+                meta: Set(meta.map(|x| x.to_string()).unwrap_or_default()),
+                ..Default::default()
+            }
+            .insert(self.fw.db())
+            .await?;
+
+            keys.insert(data.key);
+            self.summary.data += 1;
+        }
+
+        Ok(())
+    }
+
+    /// The indexed file with the same path and hash, or else the same hash, or else the same path
+    async fn find_file(&self, file: &files::Model) -> Result<Option<files::Model>> {
+        let mut same_hash = files::Entity::find()
+            .filter(files::Column::Hash.eq(&file.hash))
+            .order_by_asc(files::Column::Id)
+            .all(self.fw.db())
+            .await?;
+
+        if let Some(i) = same_hash.iter().position(|x| x.path == file.path) {
+            return Ok(Some(same_hash.swap_remove(i)));
+        }
+
+        if !same_hash.is_empty() {
+            return Ok(Some(same_hash.swap_remove(0)));
+        }
+
+        let same_path = files::Entity::find()
+            .filter(files::Column::Path.eq(&file.path))
+            .one(self.fw.db())
+            .await?;
+
+        Ok(same_path)
+    }
+
+    async fn tag_id(&mut self, name: &str) -> Result<Option<i32>> {
+        let Some(name) = normalize_tag_name(name) else {
+            return Ok(None);
+        };
+
+        let key = name.to_lowercase();
+
+        if let Some(id) = self.tag_ids.get(&key) {
+            return Ok(Some(*id));
+        }
+
+        let tag = match self.fw.find_tag_by_name(&name).await? {
+            Some(tag) => tag,
+            None => self.fw.create_tag(&name).await?,
+        };
+
+        self.tag_ids.insert(key, tag.id);
+
+        Ok(Some(tag.id))
+    }
+
+    /// Copy a generated file (thumbnail, sprite, …) from the export, named after the file
+    /// it now belongs to. Returns its path relative to the metadata directory.
+    async fn copy_data_file(&self, value: &str, file: &files::Model) -> Result<Option<String>> {
+        let Some(root) = &self.files_root else {
+            return Ok(None);
+        };
+
+        let value = Path::new(value);
+
+        if !value
+            .components()
+            .all(|x| matches!(x, Component::Normal(_)))
+        {
+            return Err(anyhow!("Invalid file path: {:?}", value));
+        }
+
+        let source = root.join(value);
+
+        if !source.is_file() {
+            return Ok(None);
+        }
+
+        // Generated files are named like `{ulid}.{size}.{extension}` or `{ulid}.sprite.jpg`,
+        // older thumbnails `{file_id}.{size}.{extension}`
+        let file_name = value
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let suffix = file_name
+            .split_once('.')
+            .map_or(file_name.as_str(), |(_, x)| x);
+
+        let relative_path = value
+            .with_file_name(format!("{}.{suffix}", file.ulid.to_lowercase()))
+            .to_string_lossy()
+            .to_string();
+        let target = self.metadata_directory.join(&relative_path);
+
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        tokio::fs::copy(&source, &target).await?;
+
+        Ok(Some(relative_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use entity::{files_tags, tags};
+    use migration::MigratorTrait;
+    use sea_orm::{Database, DatabaseConnection};
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::library::export;
+
+    async fn setup_db() -> DatabaseConnection {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        migration::Migrator::up(&db, None).await.unwrap();
+        db
+    }
+
+    async fn insert_file(db: &DatabaseConnection, ulid: &str, path: &str, hash: &str) -> i32 {
+        files::ActiveModel {
+            ulid: Set(ulid.to_string()),
+            path: Set(path.to_string()),
+            hash: Set(hash.to_string()),
+            created_at: Set(Utc::now()),
+            file_type: Set(Some("video/mp4".to_string())),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap()
+        .id
+    }
+
+    async fn insert_data(db: &DatabaseConnection, file_id: i32, key: &str, value: &str) {
+        file_data::ActiveModel {
+            file_id: Set(file_id),
+            key: Set(key.to_string()),
+            value: Set(value.to_string()),
+            meta: Set(json!({ "path": value, "v": 1 }).to_string()),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap();
+    }
+
+    /// A library with a tagged video, its generated files and a file the other library lacks
+    async fn source() -> (DatabaseConnection, TempDir) {
+        let db = setup_db().await;
+        let metadata_directory = tempfile::tempdir().unwrap();
+        std::fs::create_dir(metadata_directory.path().join("thumbs")).unwrap();
+
+        let file_id = insert_file(&db, "SOURCE", "videos/cat.mp4", "cat-hash").await;
+        insert_file(&db, "OTHER", "other.mp4", "other-hash").await;
+
+        for (key, name) in [
+            ("poster", "source.poster.jpeg"),
+            ("poster.webp", "source.poster.webp"),
+            ("sprite", "source.sprite.jpg"),
+            ("sprite-track", "source.sprite.vtt"),
+        ] {
+            let value = format!("thumbs/{name}");
+            std::fs::write(metadata_directory.path().join(&value), name).unwrap();
+            insert_data(&db, file_id, key, &value).await;
+        }
+
+        insert_data(&db, file_id, "blurhash", "LKO2?U%2Tw=w").await;
+
+        let tag = tags::ActiveModel {
+            name: Set("Cat".to_string()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        files_tags::ActiveModel {
+            file_id: Set(file_id),
+            tag_id: Set(tag.id),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        (db, metadata_directory)
+    }
+
+    async fn data(db: &DatabaseConnection, file_id: i32) -> HashMap<String, (String, String)> {
+        file_data::Entity::find()
+            .filter(file_data::Column::FileId.eq(file_id))
+            .all(db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|x| (x.key, (x.value, x.meta)))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn archive_round_trip_copies_generated_files() {
+        let (source_db, source_metadata) = source().await;
+        let export_dir = tempfile::tempdir().unwrap();
+        let archive_path = export_dir.path().join("export.tar");
+
+        let count = export::archive(&source_db, &archive_path, Some(source_metadata.path()))
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+
+        // The same video, moved and indexed again
+        let db = setup_db().await;
+        let file_id = insert_file(&db, "TARGET", "cat.mp4", "cat-hash").await;
+        let metadata_directory = tempfile::tempdir().unwrap();
+
+        let fw = FileWatcher::new(db.clone());
+        let summary = import(&fw, &archive_path, metadata_directory.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            (summary.files, summary.missing, summary.tags, summary.data),
+            (1, 1, 1, 5)
+        );
+
+        let data = data(&db, file_id).await;
+
+        for (key, name) in [
+            ("poster", "target.poster.jpeg"),
+            ("poster.webp", "target.poster.webp"),
+            ("sprite", "target.sprite.jpg"),
+            ("sprite-track", "target.sprite.vtt"),
+        ] {
+            let (value, meta) = &data[key];
+            let meta: serde_json::Value = serde_json::from_str(meta).unwrap();
+
+            assert_eq!(value, &format!("thumbs/{name}"));
+            assert_eq!(meta["path"], json!(value));
+            assert_eq!(
+                std::fs::read_to_string(metadata_directory.path().join(value)).unwrap(),
+                name.replace("target", "source")
+            );
+        }
+
+        assert_eq!(data["blurhash"].0, "LKO2?U%2Tw=w");
+
+        let tags = fw.get_files_tags(&[file_id]).await.unwrap();
+        assert_eq!(
+            tags[&file_id]
+                .iter()
+                .map(|x| x.name.as_str())
+                .collect::<Vec<_>>(),
+            ["Cat"]
+        );
+    }
+
+    #[tokio::test]
+    async fn ndjson_round_trip_skips_generated_files() {
+        let (source_db, _source_metadata) = source().await;
+        let export_dir = tempfile::tempdir().unwrap();
+        let export_path = export_dir.path().join("export.ndjson");
+
+        export::write_to_file(&source_db, &export_path)
+            .await
+            .unwrap();
+
+        let db = setup_db().await;
+        let file_id = insert_file(&db, "TARGET", "videos/cat.mp4", "cat-hash").await;
+        let metadata_directory = tempfile::tempdir().unwrap();
+
+        let fw = FileWatcher::new(db.clone());
+        let summary = import(&fw, &export_path, metadata_directory.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            (summary.files, summary.missing, summary.tags, summary.data),
+            (1, 1, 1, 1)
+        );
+        assert_eq!(
+            data(&db, file_id).await.into_keys().collect::<Vec<_>>(),
+            ["blurhash"]
+        );
+        assert!(std::fs::read_dir(metadata_directory.path())
+            .unwrap()
+            .next()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_failed_exports() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.ndjson");
+        std::fs::write(&path, "{\"error\":\"Failed to export files\"}\n").unwrap();
+
+        let fw = FileWatcher::new(setup_db().await);
+        let error = import(&fw, &path, dir.path()).await.unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "The export failed on line 1: Failed to export files"
+        );
+    }
+}
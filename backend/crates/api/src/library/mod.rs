@@ -0,0 +1,97 @@
+//! Exporting a library and importing it into another one.
+//!
+//! An export is either plain newline-delimited JSON, one [`ExportRecord`] per line (what
+//! `/export` streams), or an uncompressed tar archive containing:
+//!
+//! - `manifest.json`, a [`Manifest`] with the format version
+//! - `files.ndjson`, the records
+//! - optionally the thumbnails, sprites and other generated files, at the paths used in the
+//!   `file_data` of the records
+//!
+//! A record looks like
+//!
+//! ```json
+//! {
+//!   "file": { "ulid": "…", "path": "cats/cat.png", "hash": "…", "fileType": "image/png", … },
+//!   "data": [{ "id": 1, "key": "poster", "value": "thumbs/01hgz6cr3ayq5w0xscbj1k3sv7.poster.jpeg", "meta": { … }, "createdAt": "…" }],
+//!   "tags": ["cat", "funny"]
+//! }
+//! ```
+//!
+//! If streaming the export fails part way, it ends with an [`ExportError`] line instead of
+//! the remaining records, and importing it fails.
+//!
+//! File paths are relative to the watched directory and thumbnail paths to the metadata
+//! directory, so an export works with any location of either. [`FORMAT_VERSION`] is bumped
+//! whenever the records change in a way older versions can't read.
+//!
+//! Importing matches each record to an indexed file with the same path and hash, or else
+//! the same hash, or else the same path. Tags are added to the matched file. `file_data`
+//! is only copied when the contents are the same, ie. the hashes match, and never replaces
+//! the data the file already has. Records without a matching file are skipped.
+
+use chrono::{DateTime, Utc};
+use entity::files;
+use serde::{Deserialize, Serialize};
+
+pub(crate) mod export;
+pub(crate) mod import;
+
+pub const FORMAT: &str = "meme-watcher";
+pub const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_PATH: &str = "manifest.json";
+const FILES_PATH: &str = "files.ndjson";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    /// Always [`FORMAT`]
+    pub format: String,
+    pub version: u32,
+    pub exported_at: String,
+    /// Number of records
+    pub files: u64,
+    /// Whether the thumbnails and other generated files are included
+    pub thumbnails: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportRecord {
+    pub file: files::Model,
+    #[serde(default)]
+    pub data: Vec<ExportRecordData>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Last line of an export that failed part way
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExportError {
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportRecordData {
+    pub id: i32,
+    pub key: String,
+    pub value: String,
+    pub meta: Option<serde_json::Value>,
+    #[serde(deserialize_with = "entity::timestamp::deserialize")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<entity::file_data::Model> for ExportRecordData {
+    fn from(x: entity::file_data::Model) -> Self {
+        Self {
+            id: x.id,
+            key: x.key,
+            value: x.value,
+            meta: serde_json::from_str(&x.meta).ok(),
+            created_at: x.created_at,
+        }
+    }
+}
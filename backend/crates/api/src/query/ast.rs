@@ -0,0 +1,272 @@
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+
+/// A parsed search query. All terms have to match.
+///
+/// Serializes to JSON for saved searches, and [`Display`] renders it back
+/// into a query string that parses to the same AST.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchQuery {
+    pub terms: Vec<Term>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Term {
+    /// Whether the term was prefixed with `-`, ie. must not match
+    #[serde(default)]
+    pub negated: bool,
+    pub predicate: Predicate,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Predicate {
+    /// A word in the path or extracted text. Matches as a prefix.
+    Text { value: String },
+    /// An exact phrase in the path or extracted text
+    Phrase { value: String },
+    /// `tag:name`, case-insensitive
+    Tag { name: String },
+    /// `type:video`, `type:gif` or `type:image/png`
+    Type { value: String },
+    /// `width>1000`, `size<5mb`
+    Number {
+        field: NumberField,
+        op: CompareOp,
+        value: u64,
+    },
+    /// `modified>2023-06`
+    Date {
+        field: DateField,
+        op: CompareOp,
+        value: String,
+    },
+    /// `modified:2023-01..2023-06`. Either end can be left open.
+    DateRange {
+        field: DateField,
+        from: Option<String>,
+        to: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NumberField {
+    Width,
+    Height,
+    Size,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DateField {
+    Modified,
+    Created,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CompareOp {
+    Eq,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl NumberField {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "width" => Some(Self::Width),
+            "height" => Some(Self::Height),
+            "size" => Some(Self::Size),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Width => "width",
+            Self::Height => "height",
+            Self::Size => "size",
+        }
+    }
+}
+
+impl DateField {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "modified" => Some(Self::Modified),
+            "created" => Some(Self::Created),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Modified => "modified",
+            Self::Created => "created",
+        }
+    }
+}
+
+impl CompareOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Eq => ":",
+            Self::Gt => ">",
+            Self::Gte => ">=",
+            Self::Lt => "<",
+            Self::Lte => "<=",
+        }
+    }
+
+    /// The operator in SQL
+    pub fn sql(self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::Gt => ">",
+            Self::Gte => ">=",
+            Self::Lt => "<",
+            Self::Lte => "<=",
+        }
+    }
+}
+
+impl SearchQuery {
+    /// Words and phrases that have to be in the path or extracted text
+    pub fn text_terms(&self) -> impl Iterator<Item = &Predicate> {
+        self.terms
+            .iter()
+            .filter(|x| !x.negated)
+            .map(|x| &x.predicate)
+            .filter(|x| matches!(x, Predicate::Text { .. } | Predicate::Phrase { .. }))
+    }
+}
+
+impl Display for SearchQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, term) in self.terms.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+
+            write!(f, "{term}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.negated {
+            write!(f, "-")?;
+        }
+
+        write!(f, "{}", self.predicate)
+    }
+}
+
+impl Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text { value } => write!(f, "{value}"),
+            Self::Phrase { value } => write!(f, "\"{value}\""),
+            Self::Tag { name } => write!(f, "tag:{}", quote_value(name)),
+            Self::Type { value } => write!(f, "type:{}", quote_value(value)),
+            Self::Number { field, op, value } => {
+                write!(f, "{}{}{}", field.name(), op.symbol(), value)
+            }
+            Self::Date { field, op, value } => {
+                write!(f, "{}{}{}", field.name(), op.symbol(), value)
+            }
+            Self::DateRange { field, from, to } => write!(
+                f,
+                "{}:{}..{}",
+                field.name(),
+                from.as_deref().unwrap_or_default(),
+                to.as_deref().unwrap_or_default(),
+            ),
+        }
+    }
+}
+
+fn quote_value(value: &str) -> String {
+    if value.contains(char::is_whitespace) {
+        format!("\"{value}\"")
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::parse;
+
+    #[test]
+    fn display_parses_back_to_the_same_query() {
+        let queries = [
+            "",
+            "cat",
+            "cat -dog \"black bird\" -\"a b\"",
+            "tag:cat -tag:\"big cat\" type:video type:image/png",
+            "width:1 width>2 width>=3 height<4 height<=5 size:6",
+            "modified:2023 modified>2023-06 created<=2023-06-01",
+            "modified:2023-01..2023-06 created:2023.. created:..2024",
+        ];
+
+        for query in queries {
+            let parsed = parse(query).unwrap();
+
+            assert_eq!(parsed.to_string(), query);
+            assert_eq!(parse(&parsed.to_string()).unwrap(), parsed);
+        }
+    }
+
+    #[test]
+    fn display_normalizes_the_query() {
+        let cases = [
+            ("  a   b ", "a b"),
+            ("TAG=Cat", "tag:Cat"),
+            ("type:VIDEO", "type:video"),
+            ("size<5mb", "size<5242880"),
+            ("\" spaced \"", "\"spaced\""),
+        ];
+
+        for (query, normalized) in cases {
+            let parsed = parse(query).unwrap();
+
+            assert_eq!(parsed.to_string(), normalized, "{query:?}");
+            assert_eq!(parse(normalized).unwrap(), parsed, "{query:?}");
+        }
+    }
+
+    #[test]
+    fn display_round_trips_built_queries() {
+        let query = SearchQuery {
+            terms: vec![
+                Term {
+                    negated: true,
+                    predicate: Predicate::Tag {
+                        name: "two\twords".into(),
+                    },
+                },
+                Term {
+                    negated: false,
+                    predicate: Predicate::DateRange {
+                        field: DateField::Created,
+                        from: Some("2020".into()),
+                        to: None,
+                    },
+                },
+            ],
+        };
+
+        assert_eq!(parse(&query.to_string()).unwrap(), query);
+    }
+}
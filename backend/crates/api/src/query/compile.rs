@@ -0,0 +1,290 @@
+use anyhow::{anyhow, Result};
+use entity::{file_data, files, files_tags, tags};
+use file_watcher::data::FileData;
+use sea_orm::{
+    prelude::*,
+    sea_query::{Alias, Func, Query, SelectStatement, SimpleExpr},
+    Condition, DbBackend,
+};
+
+use super::ast::{CompareOp, DateField, NumberField, Predicate, SearchQuery, Term};
+use crate::helpers::{
+    filter::{column_like, escape_like, meta_number, parse_period},
+    fts,
+};
+
+impl SearchQuery {
+    /// Condition over the `files` table that matches all of the terms
+    pub fn condition(&self, backend: DbBackend) -> Result<Condition> {
+        self.terms.iter().try_fold(
+            Condition::all(),
+            |acc, x| Ok(acc.add(x.condition(backend)?)),
+        )
+    }
+
+    /// Full-text query for ranking the results by the words and phrases of the query
+    pub fn match_query(&self, backend: DbBackend) -> Option<String> {
+        let terms = self
+            .text_terms()
+            .filter_map(|x| match x {
+                Predicate::Text { value } => fts::match_query(backend, value),
+                Predicate::Phrase { value } => fts::phrase_query(backend, value),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        fts::all_of(backend, &terms)
+    }
+}
+
+impl Term {
+    fn condition(&self, backend: DbBackend) -> Result<Condition> {
+        let condition = Condition::all().add(self.predicate.condition(backend)?);
+
+        Ok(if self.negated {
+            condition.not()
+        } else {
+            condition
+        })
+    }
+}
+
+impl Predicate {
+    fn condition(&self, backend: DbBackend) -> Result<Condition> {
+        let condition = match self {
+            Self::Text { value } => {
+                let query = fts::match_query(backend, value)
+                    .ok_or_else(|| anyhow!("Empty search term: {:?}", value))?;
+
+                Condition::all().add(files::Column::Id.in_subquery(files_matching(backend, &query)))
+            }
+            Self::Phrase { value } => {
+                let query = fts::phrase_query(backend, value)
+                    .ok_or_else(|| anyhow!("Empty search phrase: {:?}", value))?;
+
+                Condition::all().add(files::Column::Id.in_subquery(files_matching(backend, &query)))
+            }
+            Self::Tag { name } => {
+                let subquery = Query::select()
+                    .column((files_tags::Entity, files_tags::Column::FileId))
+                    .from(files_tags::Entity)
+                    .inner_join(
+                        tags::Entity,
+                        Expr::col((tags::Entity, tags::Column::Id))
+                            .equals((files_tags::Entity, files_tags::Column::TagId)),
+                    )
+                    .and_where(
+                        Expr::expr(Func::lower(Expr::col((tags::Entity, tags::Column::Name))))
+                            .eq(name.to_lowercase()),
+                    )
+                    .to_owned();
+
+                Condition::all().add(files::Column::Id.in_subquery(subquery))
+            }
+            Self::Type { value } => {
+                let escaped = escape_like(value);
+
+                if value.contains('/') {
+                    if value.ends_with('/') {
+                        Condition::all()
+                            .add(column_like(files::Column::FileType, format!("{escaped}%")))
+                    } else {
+                        Condition::all().add(files::Column::FileType.eq(value))
+                    }
+                } else {
+                    // Either the type (`video`) or the subtype (`gif`)
+                    Condition::any()
+                        .add(column_like(files::Column::FileType, format!("{escaped}/%")))
+                        .add(column_like(files::Column::FileType, format!("%/{escaped}")))
+                }
+            }
+            Self::Number {
+                field: NumberField::Size,
+                op,
+                value,
+            } => Condition::all().add(compare(files::Column::FileSize, *op, *value)),
+            Self::Number { field, op, value } => {
+                let subquery = Query::select()
+                    .column(file_data::Column::FileId)
+                    .from(file_data::Entity)
+                    .and_where(file_data::Column::Key.eq(FileData::MEDIA_DIMENSIONS))
+                    .and_where(meta_number(backend, field.name(), op.sql(), *value))
+                    .to_owned();
+
+                Condition::all().add(files::Column::Id.in_subquery(subquery))
+            }
+            Self::Date { field, op, value } => {
+                let column = date_column(*field);
+                let (start, end) = parse_period(value)?;
+
+                // The value covers a whole period, eg. a day
+                match op {
+                    CompareOp::Eq => Condition::all().add(column.gte(start)).add(column.lt(end)),
+                    CompareOp::Gt => Condition::all().add(column.gte(end)),
+                    CompareOp::Gte => Condition::all().add(column.gte(start)),
+                    CompareOp::Lt => Condition::all().add(column.lt(start)),
+                    CompareOp::Lte => Condition::all().add(column.lt(end)),
+                }
+            }
+            Self::DateRange { field, from, to } => {
+                let column = date_column(*field);
+                let mut condition = Condition::all();
+
+                if let Some(from) = from {
+                    condition = condition.add(column.gte(parse_period(from)?.0));
+                }
+
+                if let Some(to) = to {
+                    condition = condition.add(column.lt(parse_period(to)?.1));
+                }
+
+                condition
+            }
+        };
+
+        Ok(condition)
+    }
+}
+
+fn compare(column: files::Column, op: CompareOp, value: u64) -> SimpleExpr {
+    match op {
+        CompareOp::Eq => column.eq(value),
+        CompareOp::Gt => column.gt(value),
+        CompareOp::Gte => column.gte(value),
+        CompareOp::Lt => column.lt(value),
+        CompareOp::Lte => column.lte(value),
+    }
+}
+
+fn date_column(field: DateField) -> files::Column {
+    match field {
+        DateField::Modified => files::Column::FileMtime,
+        DateField::Created => files::Column::FileCtime,
+    }
+}
+
+fn files_matching(backend: DbBackend, match_query: &str) -> SelectStatement {
+    Query::select()
+        .expr(Expr::col(Alias::new("rowid")))
+        .from(Alias::new("files_fts"))
+        .and_where(fts::matches(backend, match_query))
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{sea_query::SqliteQueryBuilder, QuerySelect, QueryTrait};
+
+    use super::*;
+    use crate::query::parse;
+
+    fn sql(query: &str) -> String {
+        let condition = parse(query).unwrap().condition(DbBackend::Sqlite).unwrap();
+
+        files::Entity::find()
+            .select_only()
+            .column(files::Column::Id)
+            .filter(condition)
+            .into_query()
+            .to_string(SqliteQueryBuilder)
+    }
+
+    fn condition(query: &str) -> String {
+        let sql = sql(query);
+        let (_, condition) = sql.split_once(" WHERE ").unwrap();
+        condition.to_string()
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        assert_eq!(sql(""), r#"SELECT "files"."id" FROM "files" WHERE TRUE"#);
+    }
+
+    #[test]
+    fn compiles_text() {
+        assert_eq!(
+            condition("cat \"black dog\""),
+            r#""files"."id" IN (SELECT "rowid" FROM "files_fts" WHERE "files_fts" MATCH '"cat"*') AND "files"."id" IN (SELECT "rowid" FROM "files_fts" WHERE "files_fts" MATCH '"black dog"')"#
+        );
+    }
+
+    #[test]
+    fn compiles_negation() {
+        assert_eq!(
+            condition("-tag:Cat"),
+            r#"NOT ("files"."id" IN (SELECT "files_tags"."file_id" FROM "files_tags" INNER JOIN "tags" ON "tags"."id" = "files_tags"."tag_id" WHERE LOWER("tags"."name") = 'cat'))"#
+        );
+    }
+
+    #[test]
+    fn compiles_types() {
+        assert_eq!(
+            condition("type:video"),
+            r#""files"."file_type" LIKE 'video/%' ESCAPE '!' OR "files"."file_type" LIKE '%/video' ESCAPE '!'"#
+        );
+        assert_eq!(
+            condition("type:image/"),
+            r#""files"."file_type" LIKE 'image/%' ESCAPE '!'"#
+        );
+        assert_eq!(
+            condition("type:im_ge/"),
+            r#""files"."file_type" LIKE 'im!_ge/%' ESCAPE '!'"#
+        );
+        assert_eq!(
+            condition("type:%"),
+            r#""files"."file_type" LIKE '!%/%' ESCAPE '!' OR "files"."file_type" LIKE '%/!%' ESCAPE '!'"#
+        );
+        assert_eq!(
+            condition("type:image/png"),
+            r#""files"."file_type" = 'image/png'"#
+        );
+    }
+
+    #[test]
+    fn compiles_numbers() {
+        assert_eq!(condition("size<=5kb"), r#""files"."file_size" <= 5120"#);
+
+        let width = condition("width>1000");
+        assert!(width.starts_with(r#""files"."id" IN (SELECT "file_id" FROM "file_data" WHERE "file_data"."key" = 'media-dimensions' AND "#), "{width}");
+        assert!(width.contains("> 1000"), "{width}");
+    }
+
+    #[test]
+    fn compiles_dates() {
+        assert_eq!(
+            condition("modified:2023-06"),
+            r#""files"."file_mtime" >= '2023-06-01 00:00:00 +00:00' AND "files"."file_mtime" < '2023-07-01 00:00:00 +00:00'"#
+        );
+        assert_eq!(
+            condition("modified>2023"),
+            r#""files"."file_mtime" >= '2024-01-01 00:00:00 +00:00'"#
+        );
+        assert_eq!(
+            condition("created<=2023-12"),
+            r#""files"."file_ctime" < '2024-01-01 00:00:00 +00:00'"#
+        );
+        assert_eq!(
+            condition("created:2022..2023-02"),
+            r#""files"."file_ctime" >= '2022-01-01 00:00:00 +00:00' AND "files"."file_ctime" < '2023-03-01 00:00:00 +00:00'"#
+        );
+        assert_eq!(
+            condition("created:2022.."),
+            r#""files"."file_ctime" >= '2022-01-01 00:00:00 +00:00'"#
+        );
+    }
+
+    #[test]
+    fn match_query_ignores_negated_and_field_terms() {
+        let query = parse("cat -dog tag:x \"a b\"").unwrap();
+
+        assert_eq!(
+            query.match_query(DbBackend::Sqlite).as_deref(),
+            Some(r#""cat"* "a b""#)
+        );
+        assert_eq!(
+            query.match_query(DbBackend::Postgres).as_deref(),
+            Some("((cat:*)) & (a <-> b)")
+        );
+        assert_eq!(parse("-cat").unwrap().match_query(DbBackend::Sqlite), None);
+    }
+}
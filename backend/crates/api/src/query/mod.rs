@@ -0,0 +1,10 @@
+//! Search query language.
+//!
+//! Queries like `tag:cat -tag:nsfw type:video width>1000 size<5mb modified:2023-01..2023-06 "exact phrase"`
+//! are parsed into a [`SearchQuery`] and compiled to a sea-orm `Condition` over `files`.
+
+mod ast;
+mod compile;
+mod parser;
+
+pub use self::parser::parse;
@@ -0,0 +1,572 @@
+use std::{
+    fmt::{self, Display},
+    ops::Range,
+};
+
+use serde::Serialize;
+
+use super::ast::{CompareOp, DateField, NumberField, Predicate, SearchQuery, Term};
+use crate::helpers::filter::parse_period;
+
+const FIELDS: &str = "tag, type, width, height, size, modified, created";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParseError {
+    pub message: String,
+    /// Byte offsets of the offending part of the query
+    pub start: usize,
+    pub end: usize,
+}
+
+impl ParseError {
+    fn new(message: impl Into<String>, span: Range<usize>) -> Self {
+        Self {
+            message: message.into(),
+            start: span.start,
+            end: span.end,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at {}..{})", self.message, self.start, self.end)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse a query like `tag:cat -tag:nsfw type:video width>1000 "exact phrase"`
+pub fn parse(input: &str) -> Result<SearchQuery, ParseError> {
+    let mut parser = Parser { input, pos: 0 };
+    let mut terms = vec![];
+
+    loop {
+        parser.skip_whitespace();
+
+        if parser.peek().is_none() {
+            break;
+        }
+
+        terms.push(parser.term()?);
+    }
+
+    Ok(SearchQuery { terms })
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|x| x.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn at_term_end(&self) -> bool {
+        self.peek().is_none_or(char::is_whitespace)
+    }
+
+    /// Everything up to the next whitespace
+    fn bare(&mut self) -> &'a str {
+        let start = self.pos;
+
+        while !self.at_term_end() {
+            self.pos += self.peek().map_or(0, char::len_utf8);
+        }
+
+        &self.input[start..self.pos]
+    }
+
+    /// A `"quoted"` value, without the quotes
+    fn quoted(&mut self) -> Result<&'a str, ParseError> {
+        let start = self.pos;
+        self.eat('"');
+
+        let Some(len) = self.input[self.pos..].find('"') else {
+            return Err(ParseError::new(
+                "Unterminated quote",
+                start..self.input.len(),
+            ));
+        };
+
+        let value = &self.input[self.pos..self.pos + len];
+        self.pos += len + 1;
+
+        if !self.at_term_end() {
+            return Err(ParseError::new(
+                "Expected a space after the closing quote",
+                self.pos - 1..self.pos,
+            ));
+        }
+
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<Term, ParseError> {
+        let start = self.pos;
+        let negated = self.eat('-');
+
+        if negated && self.at_term_end() {
+            return Err(ParseError::new(
+                "Expected a term after `-`",
+                start..self.pos,
+            ));
+        }
+
+        if self.peek() == Some('"') {
+            let quote_start = self.pos;
+            let value = self.quoted()?.trim();
+
+            if value.is_empty() {
+                return Err(ParseError::new("Empty phrase", quote_start..self.pos));
+            }
+
+            return Ok(Term {
+                negated,
+                predicate: Predicate::Phrase {
+                    value: value.to_string(),
+                },
+            });
+        }
+
+        let name_start = self.pos;
+        let name_len = self.input[name_start..]
+            .find(|x: char| !x.is_ascii_alphabetic())
+            .unwrap_or(self.input.len() - name_start);
+        let name_end = name_start + name_len;
+
+        let has_operator = self.input[name_end..].starts_with([':', '>', '<', '=']);
+
+        if name_len == 0 || !has_operator {
+            let value = self.bare();
+
+            return Ok(Term {
+                negated,
+                predicate: Predicate::Text {
+                    value: value.to_string(),
+                },
+            });
+        }
+
+        let name = self.input[name_start..name_end].to_ascii_lowercase();
+        self.pos = name_end;
+
+        let op_start = self.pos;
+        let op = self.operator();
+        let op_span = op_start..self.pos;
+
+        let value_start = self.pos;
+        let value = if self.peek() == Some('"') {
+            self.quoted()?
+        } else {
+            self.bare()
+        };
+        let value_span = value_start..self.pos;
+
+        if value.is_empty() {
+            return Err(ParseError::new(
+                format!("Expected a value after `{}`", &self.input[start..self.pos]),
+                op_span,
+            ));
+        }
+
+        let predicate = predicate(&name, name_start..name_end, op, op_span, value, value_span)?;
+
+        Ok(Term { negated, predicate })
+    }
+
+    fn operator(&mut self) -> CompareOp {
+        if self.eat(':') || self.eat('=') {
+            CompareOp::Eq
+        } else if self.eat('>') {
+            if self.eat('=') {
+                CompareOp::Gte
+            } else {
+                CompareOp::Gt
+            }
+        } else {
+            self.eat('<');
+
+            if self.eat('=') {
+                CompareOp::Lte
+            } else {
+                CompareOp::Lt
+            }
+        }
+    }
+}
+
+fn predicate(
+    name: &str,
+    name_span: Range<usize>,
+    op: CompareOp,
+    op_span: Range<usize>,
+    value: &str,
+    value_span: Range<usize>,
+) -> Result<Predicate, ParseError> {
+    let require_eq = || {
+        if op == CompareOp::Eq {
+            Ok(())
+        } else {
+            Err(ParseError::new(
+                format!("`{name}` can only be compared with `:`"),
+                op_span.clone(),
+            ))
+        }
+    };
+
+    if name == "tag" {
+        require_eq()?;
+
+        return Ok(Predicate::Tag {
+            name: value.to_string(),
+        });
+    }
+
+    if name == "type" {
+        require_eq()?;
+
+        return Ok(Predicate::Type {
+            value: value.to_ascii_lowercase(),
+        });
+    }
+
+    if let Some(field) = NumberField::from_name(name) {
+        let value = match field {
+            NumberField::Size => parse_size(value),
+            _ => value.parse().ok(),
+        }
+        .ok_or_else(|| {
+            let message = match field {
+                NumberField::Size => "Invalid size, expected something like `500kb` or `5mb`",
+                _ => "Invalid number",
+            };
+
+            ParseError::new(message, value_span.clone())
+        })?;
+
+        return Ok(Predicate::Number { field, op, value });
+    }
+
+    if let Some(field) = DateField::from_name(name) {
+        let validate = |value: &str, span: Range<usize>| {
+            parse_period(value).map(|_| value.to_string()).map_err(|_| {
+                ParseError::new(
+                    "Invalid date, expected something like `2023`, `2023-06` or `2023-06-01`",
+                    span,
+                )
+            })
+        };
+
+        let Some((from, to)) = value.split_once("..") else {
+            return Ok(Predicate::Date {
+                field,
+                op,
+                value: validate(value, value_span)?,
+            });
+        };
+
+        require_eq()?;
+
+        if from.is_empty() && to.is_empty() {
+            return Err(ParseError::new(
+                "Expected a date on at least one side of `..`",
+                value_span,
+            ));
+        }
+
+        let to_start = value_span.start + from.len() + 2;
+
+        return Ok(Predicate::DateRange {
+            field,
+            from: Some(from)
+                .filter(|x| !x.is_empty())
+                .map(|x| validate(x, value_span.start..to_start - 2))
+                .transpose()?,
+            to: Some(to)
+                .filter(|x| !x.is_empty())
+                .map(|x| validate(x, to_start..value_span.end))
+                .transpose()?,
+        });
+    }
+
+    Err(ParseError::new(
+        format!(
+            "Unknown field `{name}`, expected one of {FIELDS}. Use quotes to search for the text"
+        ),
+        name_span,
+    ))
+}
+
+/// Parse a size like `500`, `500kb` or `1.5gb` into bytes
+fn parse_size(value: &str) -> Option<u64> {
+    let value = value.to_ascii_lowercase();
+    let unit_start = value
+        .find(|x: char| !x.is_ascii_digit() && x != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_start);
+
+    let multiplier: u64 = match unit {
+        "" | "b" => 1,
+        "k" | "kb" => 1 << 10,
+        "m" | "mb" => 1 << 20,
+        "g" | "gb" => 1 << 30,
+        "t" | "tb" => 1 << 40,
+        _ => return None,
+    };
+
+    if let Ok(number) = number.parse::<u64>() {
+        return number.checked_mul(multiplier);
+    }
+
+    let number = number.parse::<f64>().ok()?;
+
+    #[allow(clippy::cast_precision_loss)]
+    let (bytes, max) = ((number * multiplier as f64).round(), u64::MAX as f64);
+
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    (bytes.is_finite() && bytes >= 0.0 && bytes < max).then_some(bytes as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(query: &str) -> Term {
+        let mut terms = parse(query).unwrap().terms;
+        assert_eq!(terms.len(), 1, "{query:?}");
+        terms.remove(0)
+    }
+
+    fn error(query: &str) -> (String, Range<usize>) {
+        let error = parse(query).unwrap_err();
+        (error.message, error.start..error.end)
+    }
+
+    #[test]
+    fn parses_text_and_phrases() {
+        let query = parse("  cat  \"black dog\" -\"a b\"  -bird ").unwrap();
+
+        assert_eq!(
+            query.terms,
+            vec![
+                Term {
+                    negated: false,
+                    predicate: Predicate::Text {
+                        value: "cat".into()
+                    },
+                },
+                Term {
+                    negated: false,
+                    predicate: Predicate::Phrase {
+                        value: "black dog".into()
+                    },
+                },
+                Term {
+                    negated: true,
+                    predicate: Predicate::Phrase {
+                        value: "a b".into()
+                    },
+                },
+                Term {
+                    negated: true,
+                    predicate: Predicate::Text {
+                        value: "bird".into()
+                    },
+                },
+            ]
+        );
+        assert_eq!(parse(" ").unwrap(), SearchQuery::default());
+    }
+
+    #[test]
+    fn words_without_a_known_operator_are_text() {
+        for query in ["12:30", "a-b", "über", "http//x"] {
+            assert_eq!(
+                term(query).predicate,
+                Predicate::Text {
+                    value: query.into()
+                },
+                "{query:?}"
+            );
+        }
+
+        assert_eq!(
+            term("--x").predicate,
+            Predicate::Text { value: "-x".into() }
+        );
+    }
+
+    #[test]
+    fn parses_operators() {
+        let cases = [
+            ("width:100", CompareOp::Eq),
+            ("width=100", CompareOp::Eq),
+            ("width>100", CompareOp::Gt),
+            ("width>=100", CompareOp::Gte),
+            ("width<100", CompareOp::Lt),
+            ("width<=100", CompareOp::Lte),
+        ];
+
+        for (query, op) in cases {
+            assert_eq!(
+                term(query).predicate,
+                Predicate::Number {
+                    field: NumberField::Width,
+                    op,
+                    value: 100
+                },
+                "{query:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_fields() {
+        assert_eq!(
+            term("-TAG:\"Big Cat\"").predicate,
+            Predicate::Tag {
+                name: "Big Cat".into()
+            }
+        );
+        assert!(term("-tag:cat").negated);
+        assert_eq!(
+            term("Type:Image/PNG").predicate,
+            Predicate::Type {
+                value: "image/png".into()
+            }
+        );
+        assert_eq!(
+            term("height>=1080").predicate,
+            Predicate::Number {
+                field: NumberField::Height,
+                op: CompareOp::Gte,
+                value: 1080
+            }
+        );
+        assert_eq!(
+            term("modified>2023-06").predicate,
+            Predicate::Date {
+                field: DateField::Modified,
+                op: CompareOp::Gt,
+                value: "2023-06".into()
+            }
+        );
+        assert_eq!(
+            term("created:2023-01..2023-06-15").predicate,
+            Predicate::DateRange {
+                field: DateField::Created,
+                from: Some("2023-01".into()),
+                to: Some("2023-06-15".into())
+            }
+        );
+        assert_eq!(
+            term("created:..2023").predicate,
+            Predicate::DateRange {
+                field: DateField::Created,
+                from: None,
+                to: Some("2023".into())
+            }
+        );
+    }
+
+    #[test]
+    fn parses_sizes() {
+        let cases = [
+            ("size>500", 500),
+            ("size>500b", 500),
+            ("size>1k", 1024),
+            ("size>2KB", 2048),
+            ("size>5mb", 5 << 20),
+            ("size>1.5gb", 3 << 29),
+            ("size>1t", 1 << 40),
+        ];
+
+        for (query, value) in cases {
+            assert_eq!(
+                term(query).predicate,
+                Predicate::Number {
+                    field: NumberField::Size,
+                    op: CompareOp::Gt,
+                    value
+                },
+                "{query:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_errors_with_spans() {
+        let cases = [
+            ("cat \"dog", "Unterminated quote", 4..8),
+            ("\"dog\"s", "Expected a space after the closing quote", 4..5),
+            ("a - b", "Expected a term after `-`", 2..3),
+            ("\"  \"", "Empty phrase", 0..4),
+            ("x tag:", "Expected a value after `tag:`", 5..6),
+            ("-width>= b", "Expected a value after `-width>=`", 6..8),
+            ("tag>cat", "`tag` can only be compared with `:`", 3..4),
+            ("type<=video", "`type` can only be compared with `:`", 4..6),
+            (
+                "modified>2023..2024",
+                "`modified` can only be compared with `:`",
+                8..9,
+            ),
+            ("width>wide", "Invalid number", 6..10),
+            (
+                "size<5xb",
+                "Invalid size, expected something like `500kb` or `5mb`",
+                5..8,
+            ),
+            (
+                "modified:2023-13",
+                "Invalid date, expected something like `2023`, `2023-06` or `2023-06-01`",
+                9..16,
+            ),
+            (
+                "created:2023..nope",
+                "Invalid date, expected something like `2023`, `2023-06` or `2023-06-01`",
+                14..18,
+            ),
+            (
+                "created:..",
+                "Expected a date on at least one side of `..`",
+                8..10,
+            ),
+        ];
+
+        for (query, message, span) in cases {
+            assert_eq!(error(query), (message.to_string(), span), "{query:?}");
+        }
+
+        let (message, span) = error("a colour:red");
+        assert!(message.starts_with("Unknown field `colour`"), "{message}");
+        assert_eq!(span, 2..8);
+    }
+
+    #[test]
+    fn spans_are_byte_offsets() {
+        assert_eq!(
+            error("ünïcödé width>x"),
+            ("Invalid number".to_string(), 18..19)
+        );
+    }
+}
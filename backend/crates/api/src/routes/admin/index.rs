@@ -0,0 +1,27 @@
+use std::sync::Arc;
+
+use file_watcher::FileWatcher;
+use rocket::{http::Status, State};
+use serde_json::json;
+
+use crate::routes::RouteList;
+
+/// Verify the thumbnails, regenerating the broken ones, and delete the unused ones.
+/// `409 Conflict` if a maintenance is already running, eg. the scheduled one.
+#[post("/thumbs/maintenance")]
+pub async fn thumbs_maintenance(fw: &State<Arc<FileWatcher>>) -> Result<serde_json::Value, Status> {
+    let report = fw
+        .maintain_thumbs()
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to maintain thumbs");
+            Status::InternalServerError
+        })?
+        .ok_or(Status::Conflict)?;
+
+    Ok(json!(report))
+}
+
+pub fn get() -> RouteList {
+    vec![("/".into(), routes![thumbs_maintenance])]
+}
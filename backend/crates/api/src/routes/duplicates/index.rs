@@ -0,0 +1,157 @@
+use std::{path::Path, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use entity::files;
+use file_watcher::{
+    duplicates::{DuplicateFile, DuplicateGroup, DuplicateResolution},
+    FileWatcher,
+};
+use rocket::{http::Status, serde::json::Json, State};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use typeshare::typeshare;
+
+use crate::{helpers::pagination::Pagination, routes::RouteList};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+struct DuplicatesIndexFile {
+    id: String,
+    name: String,
+    path: String,
+    file_size: Option<String>,
+    #[typeshare(serialized_as = "Option<String>")]
+    modified: Option<DateTime<Utc>>,
+    linked: bool,
+}
+
+impl From<DuplicateFile> for DuplicatesIndexFile {
+    fn from(x: DuplicateFile) -> Self {
+        Self {
+            id: x.file.ulid.to_lowercase(),
+            name: Path::new(&x.file.path)
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            path: x.file.path,
+            file_size: x.file.file_size.map(|x| x.to_string()),
+            modified: x.file.file_mtime,
+            linked: x.linked,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+struct DuplicatesIndexGroup {
+    hash: String,
+    /// How much space would be freed by resolving the group
+    reclaimable_size: String,
+    files: Vec<DuplicatesIndexFile>,
+}
+
+impl From<DuplicateGroup> for DuplicatesIndexGroup {
+    fn from(x: DuplicateGroup) -> Self {
+        let size = x
+            .files
+            .first()
+            .and_then(|x| x.file.file_size)
+            .unwrap_or_default();
+        let copies = x.files.iter().skip(1).filter(|x| !x.linked).count();
+
+        Self {
+            hash: x.hash,
+            reclaimable_size: (size * i64::try_from(copies).unwrap_or_default()).to_string(),
+            files: x.files.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+struct DuplicatesIndex {
+    items: Vec<DuplicatesIndexGroup>,
+    pagination: Pagination,
+}
+
+#[get("/?<pagination>")]
+pub async fn index(
+    fw: &State<Arc<FileWatcher>>,
+    pagination: Option<Pagination>,
+) -> Result<serde_json::Value, Status> {
+    let mut pagination = pagination.unwrap_or_default().with_defaults();
+
+    let groups = fw
+        .get_duplicate_groups(pagination.per_page(), pagination.offset())
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to get duplicates");
+            Status::InternalServerError
+        })?;
+
+    let total_items = fw.count_duplicate_groups().await.map_err(|e| {
+        logger::error!(err = ?e, "failed to count duplicates");
+        Status::InternalServerError
+    })?;
+
+    pagination.set_total_pages(total_items);
+
+    Ok(json!(DuplicatesIndex {
+        items: groups.into_iter().map(Into::into).collect(),
+        pagination,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct DuplicatesResolveRequest {
+    /// ULID of the file to keep
+    keep: String,
+    action: DuplicateResolution,
+}
+
+#[post("/<hash>/resolve", data = "<request>")]
+pub async fn resolve(
+    fw: &State<Arc<FileWatcher>>,
+    hash: &str,
+    request: Json<DuplicatesResolveRequest>,
+) -> Result<(Status, serde_json::Value), Status> {
+    let result = fw
+        .resolve_duplicates(hash, &request.keep, request.action)
+        .await
+        .map_err(|e| {
+            logger::warn!(err = ?e, "failed to resolve duplicates");
+            Status::BadRequest
+        })?;
+
+    let ulids = |files: Vec<files::Model>| {
+        files
+            .into_iter()
+            .map(|x| x.ulid.to_lowercase())
+            .collect::<Vec<_>>()
+    };
+
+    // Some files may have been resolved before it failed
+    let status = match result.error {
+        Some(_) => Status::InternalServerError,
+        None => Status::Ok,
+    };
+
+    Ok((
+        status,
+        json!({
+            "resolved": ulids(result.resolved),
+            "skipped": ulids(result.skipped),
+            "error": result.error,
+        }),
+    ))
+}
+
+pub fn get() -> RouteList {
+    vec![("/".into(), routes![index, resolve])]
+}
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use futures::{future, StreamExt};
+use rocket::{
+    http::{ContentType, Header},
+    response::stream::TextStream,
+    State,
+};
+use sea_orm::DatabaseConnection;
+
+use crate::{
+    library::{export, ExportError},
+    routes::RouteList,
+};
+
+#[derive(Responder)]
+pub struct ExportResponse<T> {
+    inner: T,
+    content_type: ContentType,
+    content_disposition: Header<'static>,
+}
+
+/// The whole index as newline-delimited JSON, one file per line with its data and tags
+#[get("/")]
+pub fn index(
+    db: &State<Arc<DatabaseConnection>>,
+) -> ExportResponse<TextStream<impl futures::Stream<Item = String> + Send + '_>> {
+    // The response has already started, so an error can't change the status. Instead the
+    // export ends with an error line, which tells it apart from a complete one.
+    let lines = export::lines(db.as_ref()).scan(false, |failed, x| {
+        if *failed {
+            return future::ready(None);
+        }
+
+        let line = x.unwrap_or_else(|e| {
+            logger::error!(err = ?e, "failed to export files");
+            *failed = true;
+
+            let error = ExportError {
+                error: "Failed to export files".to_string(),
+            };
+
+            serde_json::to_string(&error).unwrap_or_default() + "\n"
+        });
+
+        future::ready(Some(line))
+    });
+
+    ExportResponse {
+        inner: TextStream(lines),
+        content_type: ContentType::new("application", "x-ndjson"),
+        content_disposition: Header::new(
+            "Content-Disposition",
+            "attachment; filename=\"export.ndjson\"",
+        ),
+    }
+}
+
+pub fn get() -> RouteList {
+    vec![("/".into(), routes![index])]
+}
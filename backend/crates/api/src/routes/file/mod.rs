@@ -1,11 +1,17 @@
 use super::{resolve_get, RouteList};
 
+mod poster;
 mod serve;
+mod similar;
+mod sprite;
 
 pub(super) fn get() -> RouteList {
     let mut joined = vec![];
 
+    joined.append(&mut resolve_get("/poster", poster::get()));
     joined.append(&mut resolve_get("/serve", serve::get()));
+    joined.append(&mut resolve_get("/similar", similar::get()));
+    joined.append(&mut resolve_get("/sprite", sprite::get()));
 
     joined
 }
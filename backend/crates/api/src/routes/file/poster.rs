@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use entity::files;
+use file_watcher::{poster::InvalidPosterFrame, FileWatcher};
+use rocket::{http::Status, serde::json::Json, State};
+use sea_orm::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use typeshare::typeshare;
+
+use crate::routes::RouteList;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct PosterRequest {
+    /// Seconds into the video to make the thumbs from, or none to select a frame automatically
+    timestamp: Option<f64>,
+}
+
+#[put("/<ulid>", data = "<request>")]
+pub async fn set_poster(
+    fw: &State<Arc<FileWatcher>>,
+    ulid: &str,
+    request: Json<PosterRequest>,
+) -> Result<serde_json::Value, Status> {
+    let db_file = files::Entity::find()
+        .filter(files::Column::Ulid.eq(ulid.to_uppercase()))
+        .one(fw.db())
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to get file");
+            Status::InternalServerError
+        })?
+        .ok_or(Status::NotFound)?;
+
+    let queued = fw
+        .set_poster_timestamp(&db_file, request.timestamp)
+        .await
+        .map_err(|e| {
+            if e.is::<InvalidPosterFrame>() {
+                logger::debug!(err = ?e, "invalid poster frame");
+                Status::BadRequest
+            } else {
+                logger::error!(err = ?e, "failed to set poster frame");
+                Status::InternalServerError
+            }
+        })?;
+
+    Ok(json!({ "queued": queued }))
+}
+
+pub fn get() -> RouteList {
+    vec![("/".into(), routes![set_poster])]
+}
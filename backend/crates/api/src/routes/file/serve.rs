@@ -1,8 +1,11 @@
 use config::CONFIG;
 use entity::files;
-use file_watcher::{thumb::ThumbDimensions, FileWatcher};
+use file_watcher::{
+    thumb::{ThumbDimensions, ThumbFormat},
+    FileWatcher,
+};
 use rocket::{
-    http::{hyper::header, Header, Status},
+    http::{hyper::header, Accept, Header, Status},
     request::FromParam,
     State,
 };
@@ -57,6 +60,7 @@ pub enum ThumbSize {
     Thumb,
     Poster,
     Specific(ThumbDimensions),
+    Preview,
 }
 
 impl<'r> FromParam<'r> for ThumbSize {
@@ -66,6 +70,7 @@ impl<'r> FromParam<'r> for ThumbSize {
         match param {
             "thumb" | "thumbnail" => Ok(ThumbSize::Thumb),
             "poster" => Ok(ThumbSize::Poster),
+            "preview" => Ok(ThumbSize::Preview),
             param => {
                 let items = param.split_once('x').and_then(|x| {
                     let (w, h) = x;
@@ -94,18 +99,60 @@ impl From<ThumbSize> for file_watcher::thumb::ThumbSize {
             ThumbSize::Thumb => file_watcher::thumb::ThumbSize::Thumb,
             ThumbSize::Poster => file_watcher::thumb::ThumbSize::Poster,
             ThumbSize::Specific(dimensions) => file_watcher::thumb::ThumbSize::Specific(dimensions),
+            ThumbSize::Preview => file_watcher::thumb::ThumbSize::Preview,
         }
     }
 }
 
+/// The format the client prefers out of the available ones, by the weights in its `Accept`
+/// header.
+///
+/// Formats other than JPEG have to be listed explicitly, as clients sending `image/*` or `*/*`
+/// don't necessarily support them. Between equally weighted formats the earlier (smaller) one
+/// wins, and if none is accepted the last one is used.
+fn preferred_format(accept: Option<&Accept>, formats: &[ThumbFormat]) -> ThumbFormat {
+    let fallback = formats.last().copied().unwrap_or_default();
+
+    let Some(accept) = accept else {
+        return fallback;
+    };
+
+    let weight = |format: ThumbFormat| {
+        let (top, sub) = format.mime_type().split_once('/').unwrap_or_default();
+
+        accept
+            .iter()
+            .filter(|x| {
+                let exact = x.top() == top && x.sub() == sub;
+                let wildcard = format == ThumbFormat::Jpeg
+                    && (x.top() == "*" || (x.top() == top && x.sub() == "*"));
+
+                exact || wildcard
+            })
+            .map(|x| x.weight_or(1.0))
+            .fold(0.0, f32::max)
+    };
+
+    formats
+        .iter()
+        .map(|x| (*x, weight(*x)))
+        .filter(|(_, weight)| *weight > 0.0)
+        .reduce(|best, x| if x.1 > best.1 { x } else { best })
+        .map_or(fallback, |(x, _)| x)
+}
+
 #[get("/<ulid>/<thumb_size>")]
 pub async fn get_thumbnail<'o>(
     fw: &State<std::sync::Arc<FileWatcher>>,
     ulid: &str,
     thumb_size: ThumbSize,
+    accept: Option<&Accept>,
 ) -> Result<RangeResponder<tokio::fs::File>, Status> {
+    let thumb_size: file_watcher::thumb::ThumbSize = thumb_size.into();
+    let format = preferred_format(accept, thumb_size.formats());
+
     let res_file = fw
-        .get_or_generate_thumb(ulid, thumb_size.into())
+        .request_thumb(ulid, thumb_size, format)
         .await
         .map_err(|e| {
             logger::warn!(err = ?e, "Failed to get thumb");
@@ -123,16 +170,15 @@ pub async fn get_thumbnail<'o>(
         Status::NotFound
     })?;
 
+    // Thumbs are made again at the same URL when eg. the poster frame changes, so they're
+    // revalidated against their hash every time
     responder
         .add_header(Header::new(
             header::ETAG.as_str(),
             format!("\"{}\"", res_file.meta.hash),
         ))
-        .add_header(Header::new(
-            header::CACHE_CONTROL.as_str(),
-            "public, max-age=31536000, immutable",
-        ))
-        .add_header(Header::new(header::PRAGMA.as_str(), "public"));
+        .add_header(Header::new(header::CACHE_CONTROL.as_str(), "no-cache"))
+        .add_header(Header::new(header::VARY.as_str(), "Accept"));
 
     Ok(responder)
 }
@@ -0,0 +1,63 @@
+use std::{path::Path, sync::Arc};
+
+use config::CONFIG;
+use file_watcher::{phash::SimilarFile, FileWatcher};
+use rocket::{http::Status, State};
+use serde::Serialize;
+use serde_json::json;
+use typeshare::typeshare;
+
+use crate::routes::RouteList;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+struct SimilarFileItem {
+    id: String,
+    name: String,
+    file_type: Option<String>,
+    /// Hamming distance between the perceptual hashes. Lower is more similar.
+    distance: u32,
+}
+
+impl From<SimilarFile> for SimilarFileItem {
+    fn from(x: SimilarFile) -> Self {
+        Self {
+            id: x.file.ulid.to_lowercase(),
+            name: Path::new(&x.file.path)
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            file_type: x.file.file_type,
+            distance: x.distance,
+        }
+    }
+}
+
+#[get("/<ulid>?<distance>")]
+pub async fn similar(
+    fw: &State<Arc<FileWatcher>>,
+    ulid: &str,
+    distance: Option<u32>,
+) -> Result<serde_json::Value, Status> {
+    let distance = distance
+        .unwrap_or(CONFIG.app.similarity_distance)
+        .min(u64::BITS);
+
+    let files = fw.find_similar(ulid, distance).await.map_err(|e| {
+        logger::warn!(err = ?e, "failed to find similar files");
+        Status::NotFound
+    })?;
+
+    Ok(json!({
+        "items": files
+            .into_iter()
+            .map(SimilarFileItem::from)
+            .collect::<Vec<_>>(),
+    }))
+}
+
+pub fn get() -> RouteList {
+    vec![("/".into(), routes![similar])]
+}
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use config::CONFIG;
+use file_watcher::FileWatcher;
+use rocket::{
+    http::{hyper::header, Header, Status},
+    State,
+};
+
+use crate::{helpers::range_responder::RangeResponder, routes::RouteList};
+
+/// Open the sprite file, relative to the metadata directory
+async fn respond(path: &str, hash: &str) -> Result<RangeResponder<tokio::fs::File>, Status> {
+    let file_path = CONFIG.app.metadata_directory_absolute(path);
+
+    let mut responder = RangeResponder::from_path(&file_path).await.map_err(|e| {
+        logger::error!(err = ?e, "Failed to open file");
+
+        Status::NotFound
+    })?;
+
+    responder
+        .add_header(Header::new(header::ETAG.as_str(), format!("\"{hash}\"")))
+        .add_header(Header::new(header::CACHE_CONTROL.as_str(), "no-cache"));
+
+    Ok(responder)
+}
+
+/// The sheet of frames of the video
+#[get("/<ulid>/sprite.jpg")]
+pub async fn get_sprite(
+    fw: &State<Arc<FileWatcher>>,
+    ulid: &str,
+) -> Result<RangeResponder<tokio::fs::File>, Status> {
+    let (sprite, _) = fw
+        .get_sprite(ulid)
+        .await
+        .map_err(|e| {
+            logger::warn!(err = ?e, "Failed to get sprite");
+
+            Status::NotFound
+        })?
+        .ok_or(Status::NotFound)?;
+
+    respond(&sprite.path, &sprite.hash).await
+}
+
+/// The WebVTT thumbnails track, which refers to the sheet next to it
+#[get("/<ulid>/thumbnails.vtt")]
+pub async fn get_sprite_track(
+    fw: &State<Arc<FileWatcher>>,
+    ulid: &str,
+) -> Result<RangeResponder<tokio::fs::File>, Status> {
+    let (_, track) = fw
+        .get_sprite(ulid)
+        .await
+        .map_err(|e| {
+            logger::warn!(err = ?e, "Failed to get sprite track");
+
+            Status::NotFound
+        })?
+        .ok_or(Status::NotFound)?;
+
+    let mut responder = respond(&track.path, &track.hash).await?;
+    responder.add_header(Header::new(header::CONTENT_TYPE.as_str(), "text/vtt"));
+
+    Ok(responder)
+}
+
+pub fn get() -> RouteList {
+    vec![("/".into(), routes![get_sprite, get_sprite_track])]
+}
@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use entity::jobs;
+use file_watcher::{jobs::JobStatus, FileWatcher};
+use rocket::{http::Status, State};
+use sea_orm::{prelude::*, QueryOrder, QuerySelect};
+use serde::Serialize;
+use serde_json::json;
+use typeshare::typeshare;
+
+use crate::routes::RouteList;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+struct JobsIndexCount {
+    kind: String,
+    status: String,
+    count: u32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+struct JobsIndexDeadItem {
+    id: i32,
+    file_id: i32,
+    kind: String,
+    attempts: i32,
+    last_error: Option<String>,
+    #[typeshare(serialized_as = "String")]
+    created_at: DateTime<Utc>,
+}
+
+impl From<jobs::Model> for JobsIndexDeadItem {
+    fn from(x: jobs::Model) -> Self {
+        Self {
+            id: x.id,
+            file_id: x.file_id,
+            kind: x.kind,
+            attempts: x.attempts,
+            last_error: x.last_error,
+            created_at: x.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+struct JobsIndex {
+    counts: Vec<JobsIndexCount>,
+    dead: Vec<JobsIndexDeadItem>,
+}
+
+#[get("/")]
+pub async fn index(db: &State<Arc<DatabaseConnection>>) -> Result<serde_json::Value, Status> {
+    let counts = jobs::Entity::find()
+        .select_only()
+        .column(jobs::Column::Kind)
+        .column(jobs::Column::Status)
+        .column_as(jobs::Column::Id.count(), "count")
+        .group_by(jobs::Column::Kind)
+        .group_by(jobs::Column::Status)
+        .into_tuple::<(String, String, i64)>()
+        .all(db.as_ref())
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to count jobs");
+            Status::InternalServerError
+        })?
+        .into_iter()
+        .map(|(kind, status, count)| JobsIndexCount {
+            kind,
+            status,
+            count: u32::try_from(count).unwrap_or(u32::MAX),
+        })
+        .collect();
+
+    let dead = jobs::Entity::find()
+        .filter(jobs::Column::Status.eq(JobStatus::Dead.as_str()))
+        .order_by_desc(jobs::Column::Id)
+        .all(db.as_ref())
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to get dead jobs");
+            Status::InternalServerError
+        })?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+
+    Ok(json!(JobsIndex { counts, dead }))
+}
+
+#[post("/retry-dead")]
+pub async fn retry_dead(fw: &State<Arc<FileWatcher>>) -> Result<serde_json::Value, Status> {
+    let retried = fw.retry_dead_jobs().await.map_err(|e| {
+        logger::error!(err = ?e, "failed to retry dead jobs");
+        Status::InternalServerError
+    })?;
+
+    Ok(json!({ "retried": retried }))
+}
+
+#[post("/rerun/<extractor>")]
+pub async fn rerun_extractor(
+    fw: &State<Arc<FileWatcher>>,
+    extractor: &str,
+) -> Result<serde_json::Value, Status> {
+    if fw.extractor(extractor).is_none() {
+        return Err(Status::NotFound);
+    }
+
+    let queued = fw.rerun_extractor(extractor).await.map_err(|e| {
+        logger::error!(err = ?e, "failed to rerun extractor");
+        Status::InternalServerError
+    })?;
+
+    Ok(json!({ "queued": queued }))
+}
+
+pub fn get() -> RouteList {
+    vec![("/".into(), routes![index, retry_dead, rerun_extractor])]
+}
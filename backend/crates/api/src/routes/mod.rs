@@ -1,14 +1,15 @@
-use std::{collections::HashMap, sync::Arc};
-
-use entity::{file_data, files};
-use rocket::{http::Status, Route, State};
-use sea_orm::{prelude::*, DatabaseConnection};
-use serde_json::json;
+use rocket::{Route, State};
 
 use crate::AppRoutes;
 
+mod admin;
+mod duplicates;
+mod export;
 mod file;
+mod jobs;
 mod page_data;
+mod search;
+mod tags;
 
 type RouteBase = String;
 type RouteList = Vec<(RouteBase, Vec<Route>)>;
@@ -27,52 +28,18 @@ pub fn routes(routes: &State<AppRoutes>) -> serde_json::Value {
         .collect::<Vec<_>>())
 }
 
-#[get("/indexed")]
-pub async fn indexed(db: &State<Arc<DatabaseConnection>>) -> Result<serde_json::Value, Status> {
-    let indexed = files::Entity::find().all(db.as_ref()).await.map_err(|e| {
-        logger::error!("failed to get indexed files: {}", e);
-        Status::InternalServerError
-    })?;
-
-    let file_datas = file_data::Entity::find()
-        .all(db.as_ref())
-        .await
-        .map_err(|e| {
-            logger::error!("failed to get indexed files: {}", e);
-            Status::InternalServerError
-        })?
-        .into_iter()
-        .fold(HashMap::new(), |mut acc: HashMap<_, Vec<_>>, x| {
-            let value = json!({
-                "id": x.id,
-                "key": x.key,
-                "value": x.value,
-                "meta": serde_json::from_str::<serde_json::Value>(&x.meta).ok(),
-                "createdAt": x.created_at,
-            });
-            acc.entry(x.file_id).or_default().push(value);
-            acc
-        });
-
-    Ok(serde_json::json!(indexed
-        .into_iter()
-        .map(|file| {
-            let data = file_datas.get(&file.id).cloned().unwrap_or_default();
-
-            json!({
-                "file": file,
-                "data": data,
-            })
-        })
-        .collect::<Vec<_>>()))
-}
-
 pub(super) fn get() -> RouteList {
     let mut joined = vec![];
 
-    joined.push(("/".into(), routes![index, indexed, routes]));
+    joined.push(("/".into(), routes![index, routes]));
     joined.append(&mut resolve_get("/page-data", page_data::get()));
     joined.append(&mut resolve_get("/file", file::get()));
+    joined.append(&mut resolve_get("/duplicates", duplicates::get()));
+    joined.append(&mut resolve_get("/jobs", jobs::get()));
+    joined.append(&mut resolve_get("/tags", tags::get()));
+    joined.append(&mut resolve_get("/search", search::get()));
+    joined.append(&mut resolve_get("/export", export::get()));
+    joined.append(&mut resolve_get("/admin", admin::get()));
 
     joined
 }
@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use entity::files;
+use file_watcher::data::{FileData, ThumbData};
+use rocket::{http::Status, State};
+use sea_orm::{prelude::*, Condition};
+use serde::Serialize;
+use serde_json::json;
+use typeshare::typeshare;
+
+use super::index::{load_items, PageDataIndexItem, PageDataIndexOrderBy};
+use crate::{
+    helpers::{filter::Filter, order, pagination::Cursor},
+    routes::RouteList,
+};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+struct PageDataFileThumbnail {
+    /// The size to request from `/file/serve/<ulid>/<size>`
+    size: String,
+    width: u32,
+    height: u32,
+}
+
+impl From<&ThumbData> for PageDataFileThumbnail {
+    fn from(x: &ThumbData) -> Self {
+        Self {
+            size: x.size.trim_start_matches("thumbnail-").to_string(),
+            width: x.width,
+            height: x.height,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+struct PageDataFile {
+    file: PageDataIndexItem,
+    /// Path relative to the watched directory
+    path: String,
+    hash: String,
+    thumbnails: Vec<PageDataFileThumbnail>,
+    /// Id of the previous file in the index with the same order and filter
+    prev: Option<String>,
+    /// Id of the next file in the index with the same order and filter
+    next: Option<String>,
+}
+
+#[get("/<ulid>?<order>&<filter>")]
+pub async fn file(
+    db: &State<Arc<DatabaseConnection>>,
+    ulid: &str,
+    order: Option<order::Order<PageDataIndexOrderBy>>,
+    filter: Filter,
+) -> Result<serde_json::Value, Status> {
+    let order = order.unwrap_or_default();
+
+    let condition = filter.condition(db.get_database_backend()).map_err(|e| {
+        logger::debug!(err = ?e, "invalid filter");
+        Status::BadRequest
+    })?;
+
+    let db_file = files::Entity::find()
+        .filter(files::Column::Ulid.eq(ulid.to_uppercase()))
+        .one(db.as_ref())
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to get file");
+            Status::InternalServerError
+        })?
+        .ok_or(Status::NotFound)?;
+
+    let file = load_items(db, vec![db_file.clone()])
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to get file tags and data");
+            Status::InternalServerError
+        })?
+        .pop()
+        .ok_or(Status::InternalServerError)?;
+
+    let prev = neighbour(db, &order, condition.clone(), &db_file, file.data(), true).await?;
+    let next = neighbour(db, &order, condition, &db_file, file.data(), false).await?;
+
+    // Every size once, whichever formats it has
+    let mut thumbnails: Vec<PageDataFileThumbnail> = vec![];
+    for data in file.data() {
+        if let FileData::Thumb(x) = data {
+            let thumb = PageDataFileThumbnail::from(x);
+            if !thumbnails.iter().any(|t| t.size == thumb.size) {
+                thumbnails.push(thumb);
+            }
+        }
+    }
+
+    Ok(json!(PageDataFile {
+        file,
+        path: db_file.path,
+        hash: db_file.hash,
+        thumbnails,
+        prev,
+        next,
+    }))
+}
+
+/// Id of the file right before or after the given one in the order
+async fn neighbour(
+    db: &DatabaseConnection,
+    order: &order::Order<PageDataIndexOrderBy>,
+    condition: Condition,
+    db_file: &files::Model,
+    data: &[FileData],
+    backwards: bool,
+) -> Result<Option<String>, Status> {
+    let by = order.by();
+
+    let cursor = Cursor {
+        key: by.key(db_file, data),
+        id: db_file.id,
+        backwards,
+    };
+
+    let neighbour = by
+        .select(db.get_database_backend(), order.direction(), Some(&cursor))
+        .ok_or(Status::InternalServerError)?
+        .filter(condition)
+        .one(db)
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to get neighbouring file");
+            Status::InternalServerError
+        })?;
+
+    Ok(neighbour.map(|x| x.ulid.to_lowercase()))
+}
+
+pub fn get() -> RouteList {
+    vec![("/".into(), routes![file])]
+}
@@ -1,32 +1,40 @@
 use std::{collections::HashMap, path::Path};
 
-use entity::{file_data, files, files_tags};
+use chrono::{DateTime, NaiveDate, NaiveTime, SecondsFormat, Utc};
+use entity::{file_data, files, files_tags, tags};
+use file_watcher::data::FileData;
 use rocket::{http::Status, State};
-use sea_orm::{prelude::*, IntoSimpleExpr, QueryOrder, QuerySelect};
+use sea_orm::{
+    prelude::*,
+    sea_query::{Func, Query, SimpleExpr},
+    Condition, DbBackend, IntoSimpleExpr, Order as SeaOrmOrder, QueryOrder, QuerySelect, Select,
+};
 use serde::Serialize;
 use serde_json::json;
 use typeshare::typeshare;
 
 use crate::{
-    helpers::{order, pagination::Pagination},
+    helpers::{
+        filter::{meta_field, Filter, MetaType},
+        order::{self, Direction},
+        pagination::{Cursor, CursorKey, Pagination},
+    },
     routes::RouteList,
 };
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 #[typeshare]
-struct PageDataIndexItemDataItem {
-    key: String,
-    value: String,
-    meta: serde_json::Value,
+struct PageDataIndexItemTag {
+    id: i32,
+    name: String,
 }
 
-impl From<&file_data::Model> for PageDataIndexItemDataItem {
-    fn from(x: &file_data::Model) -> Self {
+impl From<tags::Model> for PageDataIndexItemTag {
+    fn from(x: tags::Model) -> Self {
         Self {
-            key: x.key.clone(),
-            value: x.value.clone(),
-            meta: x.meta.parse().unwrap_or_default(),
+            id: x.id,
+            name: x.name,
         }
     }
 }
@@ -34,15 +42,20 @@ impl From<&file_data::Model> for PageDataIndexItemDataItem {
 #[derive(Debug, Default, Serialize)]
 #[serde(rename_all = "camelCase")]
 #[typeshare]
-struct PageDataIndexItem {
+pub(crate) struct PageDataIndexItem {
     id: String,
     name: String,
     file_size: Option<String>,
     file_type: Option<String>,
-    created: Option<String>,
-    modified: Option<String>,
-    tags: Vec<i32>,
-    data: Vec<PageDataIndexItemDataItem>,
+    #[typeshare(serialized_as = "Option<String>")]
+    created: Option<DateTime<Utc>>,
+    #[typeshare(serialized_as = "Option<String>")]
+    modified: Option<DateTime<Utc>>,
+    tags: Vec<PageDataIndexItemTag>,
+    data: Vec<FileData>,
+    /// Matching part of the file with the matches wrapped in `<mark>`. Only set for search results.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snippet: Option<String>,
 }
 
 impl From<files::Model> for PageDataIndexItem {
@@ -64,15 +77,24 @@ impl From<files::Model> for PageDataIndexItem {
 }
 
 impl PageDataIndexItem {
-    fn with_tags(mut self, tags: Vec<i32>) -> Self {
+    fn with_tags(mut self, tags: Vec<PageDataIndexItemTag>) -> Self {
         self.tags = tags;
         self
     }
 
-    fn with_data(mut self, data: Vec<PageDataIndexItemDataItem>) -> Self {
+    pub(super) fn data(&self) -> &[FileData] {
+        &self.data
+    }
+
+    fn with_data(mut self, data: Vec<FileData>) -> Self {
         self.data = data;
         self
     }
+
+    pub(crate) fn with_snippet(mut self, snippet: String) -> Self {
+        self.snippet = Some(snippet);
+        self
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -91,93 +113,495 @@ pub enum PageDataIndexOrderBy {
     Created,
     Size,
     Id,
+    Duration,
+    Container,
+    VideoCodec,
+    AudioCodec,
+    FrameRate,
+    BitRate,
+    Rotation,
+    PixelFormat,
+    HasAudio,
 }
 
 impl PageDataIndexOrderBy {
-    fn into_simple_expr(self) -> impl IntoSimpleExpr {
+    /// The sort key. Missing values sort as the lowest, like `NULL`s.
+    fn key_expr(&self, backend: DbBackend) -> SimpleExpr {
+        let media_info =
+            |field, kind, default: Value| media_info_expr(backend, field, kind, default);
+
+        let (column, default) = match self {
+            PageDataIndexOrderBy::Modified => (files::Column::FileMtime, Value::from(no_date())),
+            PageDataIndexOrderBy::Created => (files::Column::FileCtime, Value::from(no_date())),
+            PageDataIndexOrderBy::Size => (files::Column::FileSize, Value::from(0i64)),
+            PageDataIndexOrderBy::Id => return files::Column::Id.into_simple_expr(),
+            PageDataIndexOrderBy::Duration => {
+                return media_info("duration", MetaType::Number, 0.0.into())
+            }
+            PageDataIndexOrderBy::Container => {
+                return media_info("container", MetaType::Text, "".into())
+            }
+            PageDataIndexOrderBy::VideoCodec => {
+                return media_info("videoCodec", MetaType::Text, "".into())
+            }
+            PageDataIndexOrderBy::AudioCodec => {
+                return media_info("audioCodec", MetaType::Text, "".into())
+            }
+            PageDataIndexOrderBy::FrameRate => {
+                return media_info("frameRate", MetaType::Number, 0.0.into())
+            }
+            PageDataIndexOrderBy::BitRate => {
+                return media_info("bitRate", MetaType::Number, 0i64.into())
+            }
+            PageDataIndexOrderBy::Rotation => {
+                return media_info("rotation", MetaType::Number, 0i64.into())
+            }
+            PageDataIndexOrderBy::PixelFormat => {
+                return media_info("pixelFormat", MetaType::Text, "".into())
+            }
+            PageDataIndexOrderBy::HasAudio => {
+                return media_info("hasAudio", MetaType::Bool, 0i64.into())
+            }
+        };
+
+        Func::coalesce([column.into_simple_expr(), Expr::val(default).into()]).into()
+    }
+
+    /// The sort key of the file with the data, matching [`Self::key_expr`]
+    pub(super) fn key(&self, file: &files::Model, data: &[FileData]) -> CursorKey {
+        let info = data
+            .iter()
+            .find_map(|x| match x {
+                FileData::MediaInfo(x) => Some(x.clone()),
+                _ => None,
+            })
+            .unwrap_or_default();
+
+        let text = |x: Option<String>| CursorKey::Text(x.unwrap_or_default());
+
         match self {
-            PageDataIndexOrderBy::Modified => files::Column::FileMtime.into_simple_expr(),
-            PageDataIndexOrderBy::Created => files::Column::FileCtime.into_simple_expr(),
-            PageDataIndexOrderBy::Size => files::Column::FileSize.into_simple_expr(),
-            PageDataIndexOrderBy::Id => files::Column::Id.into_simple_expr(),
+            PageDataIndexOrderBy::Modified => date_key(file.file_mtime),
+            PageDataIndexOrderBy::Created => date_key(file.file_ctime),
+            PageDataIndexOrderBy::Size => CursorKey::Number(file.file_size.unwrap_or_default()),
+            PageDataIndexOrderBy::Id => CursorKey::Number(file.id.into()),
+            PageDataIndexOrderBy::Duration => CursorKey::Float(info.duration.unwrap_or_default()),
+            PageDataIndexOrderBy::Container => text(info.container),
+            PageDataIndexOrderBy::VideoCodec => text(info.video_codec),
+            PageDataIndexOrderBy::AudioCodec => text(info.audio_codec),
+            PageDataIndexOrderBy::FrameRate => {
+                CursorKey::Float(info.frame_rate.unwrap_or_default())
+            }
+            PageDataIndexOrderBy::BitRate => CursorKey::Number(
+                info.bit_rate
+                    .map_or(0, |x| i64::try_from(x).unwrap_or(i64::MAX)),
+            ),
+            PageDataIndexOrderBy::Rotation => CursorKey::Number(info.rotation.into()),
+            PageDataIndexOrderBy::PixelFormat => text(info.pixel_format),
+            PageDataIndexOrderBy::HasAudio => CursorKey::Number(info.has_audio.into()),
+        }
+    }
+
+    /// Files after the cursor when going in the given direction
+    fn after(
+        &self,
+        backend: DbBackend,
+        cursor: &Cursor,
+        direction: &SeaOrmOrder,
+    ) -> Option<Condition> {
+        let value = match (self, &cursor.key) {
+            (
+                PageDataIndexOrderBy::Modified | PageDataIndexOrderBy::Created,
+                CursorKey::Text(x),
+            ) => Value::from(DateTime::parse_from_rfc3339(x).ok()?.with_timezone(&Utc)),
+            (
+                PageDataIndexOrderBy::Size
+                | PageDataIndexOrderBy::Id
+                | PageDataIndexOrderBy::BitRate
+                | PageDataIndexOrderBy::Rotation
+                | PageDataIndexOrderBy::HasAudio,
+                CursorKey::Number(x),
+            ) => Value::from(*x),
+            (
+                PageDataIndexOrderBy::Duration | PageDataIndexOrderBy::FrameRate,
+                CursorKey::Float(x),
+            ) => Value::from(*x),
+            (
+                PageDataIndexOrderBy::Container
+                | PageDataIndexOrderBy::VideoCodec
+                | PageDataIndexOrderBy::AudioCodec
+                | PageDataIndexOrderBy::PixelFormat,
+                CursorKey::Text(x),
+            ) => Value::from(x.clone()),
+            _ => return None,
+        };
+
+        let key = Expr::expr(self.key_expr(backend));
+        let id = files::Column::Id;
+
+        let condition = match direction {
+            SeaOrmOrder::Asc => Condition::any()
+                .add(key.clone().gt(value.clone()))
+                .add(Condition::all().add(key.eq(value)).add(id.gt(cursor.id))),
+            _ => Condition::any()
+                .add(key.clone().lt(value.clone()))
+                .add(Condition::all().add(key.eq(value)).add(id.lt(cursor.id))),
+        };
+
+        Some(condition)
+    }
+
+    /// Files in this order, starting after the cursor if there is one.
+    ///
+    /// Going backwards from the cursor the files come in reverse order. `None` if the
+    /// cursor was made for a different order.
+    pub(super) fn select(
+        &self,
+        backend: DbBackend,
+        direction: Direction,
+        cursor: Option<&Cursor>,
+    ) -> Option<Select<files::Entity>> {
+        let direction = match (
+            SeaOrmOrder::from(direction),
+            cursor.is_some_and(|x| x.backwards),
+        ) {
+            (SeaOrmOrder::Asc, false) | (SeaOrmOrder::Desc, true) => SeaOrmOrder::Asc,
+            _ => SeaOrmOrder::Desc,
+        };
+
+        let query = files::Entity::find()
+            .order_by(self.key_expr(backend), direction.clone())
+            .order_by(files::Column::Id, direction.clone());
+
+        match cursor {
+            Some(cursor) => Some(query.filter(self.after(backend, cursor, &direction)?)),
+            None => Some(query),
         }
     }
 }
 
-#[get("/?<pagination>&<order>")]
+/// A field of the media info of the file, see [`file_watcher::data::MediaInfo`]
+fn media_info_expr(backend: DbBackend, field: &str, kind: MetaType, default: Value) -> SimpleExpr {
+    let value = Query::select()
+        .expr(Expr::cust(meta_field(backend, field, kind)))
+        .from(file_data::Entity)
+        .and_where(
+            Expr::col((file_data::Entity, file_data::Column::FileId))
+                .equals((files::Entity, files::Column::Id)),
+        )
+        .and_where(file_data::Column::Key.eq(FileData::MEDIA_INFO))
+        .limit(1)
+        .to_owned();
+
+    Func::coalesce([
+        SimpleExpr::SubQuery(None, Box::new(value.into_sub_query_statement())),
+        Expr::val(default).into(),
+    ])
+    .into()
+}
+
+/// Stands in for missing dates when ordering. The earliest date all databases can store.
+fn no_date() -> DateTime<Utc> {
+    NaiveDate::from_ymd_opt(1000, 1, 1)
+        .unwrap_or_default()
+        .and_time(NaiveTime::MIN)
+        .and_utc()
+}
+
+fn date_key(date: Option<DateTime<Utc>>) -> CursorKey {
+    CursorKey::Text(
+        date.unwrap_or_else(no_date)
+            .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+    )
+}
+
+#[get("/?<pagination>&<order>&<filter>")]
 pub async fn index(
     db: &State<std::sync::Arc<DatabaseConnection>>,
     pagination: Option<Pagination>,
     order: Option<order::Order<PageDataIndexOrderBy>>,
+    filter: Filter,
 ) -> Result<serde_json::Value, Status> {
     let mut pagination = pagination.unwrap_or_default().with_defaults();
     let order = order.unwrap_or_default();
+    let by = order.by();
     let per_page = pagination.per_page();
 
-    let items = files::Entity::find()
-        .order_by(order.by().into_simple_expr(), order.direction().into())
-        .limit(per_page)
-        .offset(pagination.offset())
-        .all(db.as_ref())
-        .await
-        .map_err(|e| {
-            logger::error!(err = ?e, "failed to get files");
-            Status::InternalServerError
-        })?;
+    let condition = filter.condition(db.get_database_backend()).map_err(|e| {
+        logger::debug!(err = ?e, "invalid filter");
+        Status::BadRequest
+    })?;
+
+    let cursor = pagination.cursor().map_err(|e| {
+        logger::debug!(err = ?e, "invalid cursor");
+        Status::BadRequest
+    })?;
+
+    let backwards = cursor.as_ref().is_some_and(|x| x.backwards);
 
-    let total_items = files::Entity::find()
-        .count(db.as_ref())
-        .await
-        .map_err(|_| Status::InternalServerError)?;
+    let mut query = by
+        .select(
+            db.get_database_backend(),
+            order.direction(),
+            cursor.as_ref(),
+        )
+        .ok_or(Status::BadRequest)?
+        .filter(condition.clone())
+        // One more to know whether there is a next page
+        .limit(per_page + 1);
+
+    if cursor.is_none() {
+        query = query.offset(pagination.offset());
+    }
+
+    let mut items = query.all(db.as_ref()).await.map_err(|e| {
+        logger::error!(err = ?e, "failed to get files");
+        Status::InternalServerError
+    })?;
+
+    let page_len = usize::try_from(per_page).unwrap_or(usize::MAX);
+    let has_more = items.len() > page_len;
+    items.truncate(page_len);
+
+    // Going backwards the files are fetched in reverse
+    if backwards {
+        items.reverse();
+    }
 
-    pagination.set_total_pages(total_items);
+    // Sort keys can depend on the file data, so it's loaded before the cursors are made
+    let loaded = load_items(db, items.clone()).await.map_err(|e| {
+        logger::error!(err = ?e, "failed to get file tags and data");
+        Status::InternalServerError
+    })?;
 
-    let file_ids = items.iter().map(|x| x.id).collect::<Vec<_>>();
+    let cursor_at = |index: Option<usize>, backwards| {
+        let index = index?;
+        let (file, item) = (items.get(index)?, loaded.get(index)?);
 
-    let files_tags = files_tags::Entity::find()
+        Some(Cursor {
+            key: by.key(file, item.data()),
+            id: file.id,
+            backwards,
+        })
+    };
+
+    let (has_next, has_prev) = match &cursor {
+        None => (has_more, pagination.page() > 1),
+        Some(_) if backwards => (true, has_more),
+        Some(_) => (has_more, true),
+    };
+
+    pagination.set_cursors(
+        cursor_at(items.len().checked_sub(1).filter(|_| has_next), false).as_ref(),
+        cursor_at(Some(0).filter(|_| has_prev), true).as_ref(),
+    );
+
+    // Counting gets slow on large libraries, so it's only done for page numbers
+    if cursor.is_none() {
+        let total_items = files::Entity::find()
+            .filter(condition)
+            .count(db.as_ref())
+            .await
+            .map_err(|_| Status::InternalServerError)?;
+
+        pagination.set_total_pages(total_items);
+    }
+
+    let items = loaded;
+
+    Ok(json!(PageDataIndex { items, pagination }))
+}
+
+/// Build index items for the files, including their tags and data
+pub(crate) async fn load_items(
+    db: &DatabaseConnection,
+    files: Vec<files::Model>,
+) -> Result<Vec<PageDataIndexItem>, DbErr> {
+    let file_ids = files.iter().map(|x| x.id).collect::<Vec<_>>();
+
+    let mut files_tags = files_tags::Entity::find()
         .filter(files_tags::Column::FileId.is_in(file_ids.clone()))
-        .all(db.as_ref())
-        .await
-        .map_err(|_| Status::InternalServerError)?
+        .find_also_related(tags::Entity)
+        .order_by_asc(files_tags::Column::Id)
+        .all(db)
+        .await?
         .into_iter()
-        .fold(HashMap::new(), |mut acc: HashMap<i32, Vec<_>>, x| {
-            acc.entry(x.file_id).or_default().push(x.tag_id);
+        .fold(HashMap::new(), |mut acc: HashMap<i32, Vec<_>>, (x, tag)| {
+            if let Some(tag) = tag {
+                acc.entry(x.file_id).or_default().push(tag);
+            }
             acc
         });
 
-    let file_data = file_data::Entity::find()
+    let mut file_data = file_data::Entity::find()
         .filter(file_data::Column::FileId.is_in(file_ids))
-        .all(db.as_ref())
-        .await
-        .map_err(|_| Status::InternalServerError)?
+        .all(db)
+        .await?
         .into_iter()
         .fold(HashMap::new(), |mut acc: HashMap<i32, Vec<_>>, x| {
-            acc.entry(x.file_id).or_default().push(x);
+            let file_id = x.file_id;
+
+            match FileData::try_from(x) {
+                Ok(data) => acc.entry(file_id).or_default().push(data),
+                Err(e) => logger::warn!(err = ?e, "Skipping file data"),
+            }
+
             acc
         });
 
-    let items = items
+    let items = files
         .into_iter()
         .map(|x| {
             let id = x.id;
 
             let mut item = PageDataIndexItem::from(x);
 
-            if let Some(tags) = files_tags.get(&id) {
-                item = item.with_tags(tags.clone());
+            if let Some(tags) = files_tags.remove(&id) {
+                item = item.with_tags(tags.into_iter().map(Into::into).collect());
             }
 
-            if let Some(data) = file_data.get(&id) {
-                item = item.with_data(data.iter().map(Into::into).collect());
+            if let Some(data) = file_data.remove(&id) {
+                item = item.with_data(data);
             }
 
             item
         })
         .collect::<Vec<_>>();
 
-    Ok(json!(PageDataIndex { items, pagination }))
+    Ok(items)
 }
 
 pub fn get() -> RouteList {
     vec![("/index".into(), routes![index])]
 }
+
+#[cfg(test)]
+mod tests {
+    use migration::MigratorTrait;
+    use sea_orm::{ActiveValue::Set, Database};
+
+    use super::*;
+
+    async fn setup(sizes: &[i64]) -> DatabaseConnection {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        migration::Migrator::up(&db, None).await.unwrap();
+
+        for (i, size) in sizes.iter().enumerate() {
+            files::ActiveModel {
+                ulid: Set(format!("ulid-{i}")),
+                path: Set(format!("/files/{i}")),
+                hash: Set(format!("hash-{i}")),
+                created_at: Set(Utc::now()),
+                file_size: Set(Some(*size)),
+                ..Default::default()
+            }
+            .insert(&db)
+            .await
+            .unwrap();
+        }
+
+        db
+    }
+
+    async fn fetch(
+        db: &DatabaseConnection,
+        by: &PageDataIndexOrderBy,
+        direction: Direction,
+        cursor: Option<&Cursor>,
+        limit: u64,
+    ) -> Vec<files::Model> {
+        by.select(db.get_database_backend(), direction, cursor)
+            .unwrap()
+            .limit(limit)
+            .all(db)
+            .await
+            .unwrap()
+    }
+
+    /// Walk the whole list a few files at a time, following `next` cursors
+    async fn walk(
+        db: &DatabaseConnection,
+        by: &PageDataIndexOrderBy,
+        direction: Direction,
+    ) -> Vec<i32> {
+        let mut ids = vec![];
+        let mut cursor = None;
+
+        loop {
+            let files = fetch(db, by, direction.clone(), cursor.as_ref(), 2).await;
+            let Some(last) = files.last() else {
+                return ids;
+            };
+
+            cursor = Some(Cursor {
+                key: by.key(last, &[]),
+                id: last.id,
+                backwards: false,
+            });
+            ids.extend(files.iter().map(|x| x.id));
+        }
+    }
+
+    #[tokio::test]
+    async fn cursors_break_ties_by_id() {
+        let db = setup(&[10, 20, 10, 10, 20, 10, 30]).await;
+        let by = PageDataIndexOrderBy::Size;
+
+        assert_eq!(walk(&db, &by, Direction::Asc).await, [1, 3, 4, 6, 2, 5, 7]);
+        assert_eq!(walk(&db, &by, Direction::Desc).await, [7, 5, 2, 6, 4, 3, 1]);
+    }
+
+    #[tokio::test]
+    async fn backwards_cursors_return_the_previous_files() {
+        let db = setup(&[10, 10, 10, 10, 10]).await;
+        let by = PageDataIndexOrderBy::Size;
+
+        let file = files::Entity::find_by_id(4)
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        let cursor = Cursor {
+            key: by.key(&file, &[]),
+            id: file.id,
+            backwards: true,
+        };
+
+        // The previous files come closest first, and are reversed by the route
+        let ids = |files: Vec<files::Model>| files.iter().map(|x| x.id).collect::<Vec<_>>();
+
+        assert_eq!(
+            ids(fetch(&db, &by, Direction::Asc, Some(&cursor), 2).await),
+            [3, 2]
+        );
+        assert_eq!(
+            ids(fetch(&db, &by, Direction::Desc, Some(&cursor), 2).await),
+            [5]
+        );
+    }
+
+    #[test]
+    fn rejects_cursors_for_another_order() {
+        let cursor = Cursor {
+            key: CursorKey::Text("2023-01-01T00:00:00Z".into()),
+            id: 1,
+            backwards: false,
+        };
+        let backend = DbBackend::Sqlite;
+
+        assert!(PageDataIndexOrderBy::Modified
+            .select(backend, Direction::Desc, Some(&cursor))
+            .is_some());
+        assert!(PageDataIndexOrderBy::Size
+            .select(backend, Direction::Desc, Some(&cursor))
+            .is_none());
+
+        let tampered = Cursor {
+            key: CursorKey::Text("yesterday".into()),
+            ..cursor
+        };
+
+        assert!(PageDataIndexOrderBy::Modified
+            .select(backend, Direction::Desc, Some(&tampered))
+            .is_none());
+    }
+}
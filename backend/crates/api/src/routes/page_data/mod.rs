@@ -1,11 +1,13 @@
 use super::{resolve_get, RouteList};
 
-mod index;
+mod file;
+pub(super) mod index;
 
 pub(super) fn get() -> RouteList {
     let mut joined = vec![];
 
     joined.append(&mut resolve_get("/", index::get()));
+    joined.append(&mut resolve_get("/file", file::get()));
 
     joined
 }
@@ -0,0 +1,227 @@
+use std::{collections::HashMap, sync::Arc};
+
+use entity::files;
+use rocket::{http::Status, response::status::Custom, State};
+use sea_orm::{
+    prelude::*,
+    sea_query::{Alias, Query, SelectStatement},
+    Condition, DbBackend, FromQueryResult, Order, QueryOrder, QuerySelect,
+};
+use serde::Serialize;
+use serde_json::json;
+use typeshare::typeshare;
+
+use crate::{
+    helpers::{filter::Filter, fts, pagination::Pagination},
+    query,
+    routes::{
+        page_data::index::{load_items, PageDataIndexItem},
+        RouteList,
+    },
+};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+struct SearchIndex {
+    items: Vec<PageDataIndexItem>,
+    pagination: Pagination,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct SearchResult {
+    id: i32,
+    snippet: String,
+}
+
+/// Files matching the query, best matches first.
+///
+/// See [`crate::query`] for the syntax. Queries without any words or phrases are ordered by
+/// modification time instead.
+#[get("/?<q>&<pagination>&<filter>")]
+pub async fn index(
+    db: &State<Arc<DatabaseConnection>>,
+    q: &str,
+    pagination: Option<Pagination>,
+    filter: Filter,
+) -> Result<serde_json::Value, Custom<serde_json::Value>> {
+    let mut pagination = pagination.unwrap_or_default().with_defaults();
+
+    let query = query::parse(q).map_err(|e| Custom(Status::BadRequest, json!({ "error": e })))?;
+    let backend = db.get_database_backend();
+
+    let condition = query
+        .condition(backend)
+        .and_then(|x| Ok(Condition::all().add(x).add(filter.condition(backend)?)))
+        .map_err(|e| {
+            logger::debug!(err = ?e, "invalid query");
+            error(Status::BadRequest)
+        })?;
+
+    let items = match query.match_query(backend) {
+        Some(match_query) => {
+            search_files(db.as_ref(), &match_query, condition, &mut pagination).await?
+        }
+        None => list_files(db.as_ref(), condition, &mut pagination).await?,
+    };
+
+    Ok(json!(SearchIndex { items, pagination }))
+}
+
+/// Files matching the condition, most recently modified first
+async fn list_files(
+    db: &DatabaseConnection,
+    condition: Condition,
+    pagination: &mut Pagination,
+) -> Result<Vec<PageDataIndexItem>, Custom<serde_json::Value>> {
+    let files = files::Entity::find()
+        .filter(condition.clone())
+        .order_by_desc(files::Column::FileMtime)
+        .order_by_desc(files::Column::Id)
+        .limit(pagination.per_page())
+        .offset(pagination.offset())
+        .all(db)
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to get files");
+            error(Status::InternalServerError)
+        })?;
+
+    let total_items = files::Entity::find()
+        .filter(condition)
+        .count(db)
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to count files");
+            error(Status::InternalServerError)
+        })?;
+
+    pagination.set_total_pages(total_items);
+
+    let items = load_items(db, files).await.map_err(|e| {
+        logger::error!(err = ?e, "failed to get file tags and data");
+        error(Status::InternalServerError)
+    })?;
+
+    Ok(items)
+}
+
+/// Files matching the condition and the FTS query, best matches first
+async fn search_files(
+    db: &DatabaseConnection,
+    match_query: &str,
+    condition: Condition,
+    pagination: &mut Pagination,
+) -> Result<Vec<PageDataIndexItem>, Custom<serde_json::Value>> {
+    let backend = db.get_database_backend();
+    let (rank, rank_order) = fts::rank(backend, match_query);
+
+    let filtered_files = Query::select()
+        .column(files::Column::Id)
+        .from(files::Entity)
+        .cond_where(condition)
+        .to_owned();
+
+    let stmt = search_query(backend, match_query, filtered_files.clone())
+        .expr_as(Expr::col(Alias::new("rowid")), Alias::new("id"))
+        .expr_as(fts::snippet(backend, match_query), Alias::new("snippet"))
+        .order_by_expr(rank, rank_order)
+        .order_by(Alias::new("rowid"), Order::Desc)
+        .limit(pagination.per_page())
+        .offset(pagination.offset())
+        .to_owned();
+
+    let results = SearchResult::find_by_statement(backend.build(&stmt))
+        .all(db)
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to search files");
+            error(Status::InternalServerError)
+        })?;
+
+    let stmt = search_query(backend, match_query, filtered_files)
+        .expr_as(Expr::cust("COUNT(*)"), Alias::new("count"))
+        .to_owned();
+
+    let total_items = db
+        .query_one(backend.build(&stmt))
+        .await
+        .and_then(|x| x.map(|x| x.try_get::<i64>("", "count")).transpose())
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to count search results");
+            error(Status::InternalServerError)
+        })?
+        .unwrap_or_default();
+
+    pagination.set_total_pages(total_items.unsigned_abs());
+
+    let mut files = files::Entity::find()
+        .filter(files::Column::Id.is_in(results.iter().map(|x| x.id)))
+        .all(db)
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to get files");
+            error(Status::InternalServerError)
+        })?
+        .into_iter()
+        .map(|x| (x.id, x))
+        .collect::<HashMap<_, _>>();
+
+    // Keep the files in the order of their rank
+    let mut snippets = HashMap::new();
+    let ranked_files = results
+        .into_iter()
+        .filter_map(|x| {
+            snippets.insert(x.id, fts::snippet_to_html(&x.snippet));
+            files.remove(&x.id)
+        })
+        .collect::<Vec<_>>();
+    let ids = ranked_files.iter().map(|x| x.id).collect::<Vec<_>>();
+
+    let items = load_items(db, ranked_files)
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to get file tags and data");
+            error(Status::InternalServerError)
+        })?
+        .into_iter()
+        .zip(ids)
+        .map(|(item, id)| match snippets.remove(&id) {
+            Some(snippet) => item.with_snippet(snippet),
+            None => item,
+        })
+        .collect();
+
+    Ok(items)
+}
+
+/// Parse the query without running it, eg. to save it or to show errors while typing
+#[get("/parse?<q>")]
+pub fn parse(q: &str) -> Result<serde_json::Value, Custom<serde_json::Value>> {
+    let query = query::parse(q).map_err(|e| Custom(Status::BadRequest, json!({ "error": e })))?;
+
+    Ok(json!({
+        "normalized": query.to_string(),
+        "query": query,
+    }))
+}
+
+fn error(status: Status) -> Custom<serde_json::Value> {
+    Custom(status, json!({ "error": { "message": status.reason() } }))
+}
+
+fn search_query(
+    backend: DbBackend,
+    match_query: &str,
+    filtered_files: SelectStatement,
+) -> SelectStatement {
+    Query::select()
+        .from(Alias::new("files_fts"))
+        .and_where(fts::matches(backend, match_query))
+        .and_where(Expr::col(Alias::new("rowid")).in_subquery(filtered_files))
+        .to_owned()
+}
+
+pub fn get() -> RouteList {
+    vec![("/".into(), routes![index, parse])]
+}
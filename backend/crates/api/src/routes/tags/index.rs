@@ -0,0 +1,294 @@
+use std::{collections::HashSet, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use entity::tags;
+use file_watcher::{
+    tags::{normalize_tag_name, TagWithCount},
+    FileWatcher,
+};
+use rocket::{http::Status, serde::json::Json, State};
+use sea_orm::{prelude::*, DatabaseConnection};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use typeshare::typeshare;
+
+use crate::routes::RouteList;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+struct TagsIndexItem {
+    id: i32,
+    name: String,
+    /// Number of files with the tag
+    count: u32,
+    #[typeshare(serialized_as = "String")]
+    created_at: DateTime<Utc>,
+}
+
+impl From<TagWithCount> for TagsIndexItem {
+    fn from(x: TagWithCount) -> Self {
+        Self {
+            id: x.id,
+            name: x.name,
+            count: u32::try_from(x.count).unwrap_or(u32::MAX),
+            created_at: x.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+struct TagsIndex {
+    items: Vec<TagsIndexItem>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct TagRequest {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct TagsBulkRequest {
+    /// ULIDs of the files to change
+    files: Vec<String>,
+    /// IDs of the tags to add to every file
+    #[serde(default)]
+    add: Vec<i32>,
+    /// IDs of the tags to remove from every file
+    #[serde(default)]
+    remove: Vec<i32>,
+}
+
+#[get("/")]
+pub async fn index(fw: &State<Arc<FileWatcher>>) -> Result<serde_json::Value, Status> {
+    let tags = fw.get_tags().await.map_err(|e| {
+        logger::error!(err = ?e, "failed to get tags");
+        Status::InternalServerError
+    })?;
+
+    Ok(json!(TagsIndex {
+        items: tags.into_iter().map(Into::into).collect(),
+    }))
+}
+
+#[get("/<id>")]
+pub async fn show(fw: &State<Arc<FileWatcher>>, id: i32) -> Result<serde_json::Value, Status> {
+    let tag = get_tag(fw, id).await?;
+
+    Ok(json!(tag))
+}
+
+#[post("/", data = "<request>")]
+pub async fn create(
+    fw: &State<Arc<FileWatcher>>,
+    request: Json<TagRequest>,
+) -> Result<serde_json::Value, Status> {
+    let name = normalize_tag_name(&request.name).ok_or(Status::BadRequest)?;
+
+    ensure_name_available(fw, &name, None).await?;
+
+    let tag = fw.create_tag(&name).await.map_err(|e| {
+        logger::error!(err = ?e, "failed to create tag");
+        Status::InternalServerError
+    })?;
+
+    let tag = get_tag(fw, tag.id).await?;
+
+    Ok(json!(tag))
+}
+
+#[patch("/<id>", data = "<request>")]
+pub async fn rename(
+    fw: &State<Arc<FileWatcher>>,
+    id: i32,
+    request: Json<TagRequest>,
+) -> Result<serde_json::Value, Status> {
+    let name = normalize_tag_name(&request.name).ok_or(Status::BadRequest)?;
+
+    ensure_name_available(fw, &name, Some(id)).await?;
+
+    fw.rename_tag(id, &name)
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to rename tag");
+            Status::InternalServerError
+        })?
+        .ok_or(Status::NotFound)?;
+
+    let tag = get_tag(fw, id).await?;
+
+    Ok(json!(tag))
+}
+
+#[delete("/<id>")]
+pub async fn delete(fw: &State<Arc<FileWatcher>>, id: i32) -> Result<serde_json::Value, Status> {
+    let deleted = fw.delete_tag(id).await.map_err(|e| {
+        logger::error!(err = ?e, "failed to delete tag");
+        Status::InternalServerError
+    })?;
+
+    if !deleted {
+        return Err(Status::NotFound);
+    }
+
+    Ok(json!({ "deleted": id }))
+}
+
+#[post("/<id>/files/<ulid>")]
+pub async fn add_to_file(
+    fw: &State<Arc<FileWatcher>>,
+    db: &State<Arc<DatabaseConnection>>,
+    id: i32,
+    ulid: &str,
+) -> Result<serde_json::Value, Status> {
+    ensure_tags_exist(db, &[id]).await?;
+    let file_ids = get_file_ids(fw, &[ulid.to_string()]).await?;
+
+    let added = fw.add_tags(&file_ids, &[id]).await.map_err(|e| {
+        logger::error!(err = ?e, "failed to add tag to file");
+        Status::InternalServerError
+    })?;
+
+    Ok(json!({ "added": added }))
+}
+
+#[delete("/<id>/files/<ulid>")]
+pub async fn remove_from_file(
+    fw: &State<Arc<FileWatcher>>,
+    db: &State<Arc<DatabaseConnection>>,
+    id: i32,
+    ulid: &str,
+) -> Result<serde_json::Value, Status> {
+    ensure_tags_exist(db, &[id]).await?;
+    let file_ids = get_file_ids(fw, &[ulid.to_string()]).await?;
+
+    let removed = fw.remove_tags(&file_ids, &[id]).await.map_err(|e| {
+        logger::error!(err = ?e, "failed to remove tag from file");
+        Status::InternalServerError
+    })?;
+
+    Ok(json!({ "removed": removed }))
+}
+
+#[post("/bulk", data = "<request>")]
+pub async fn bulk(
+    fw: &State<Arc<FileWatcher>>,
+    db: &State<Arc<DatabaseConnection>>,
+    request: Json<TagsBulkRequest>,
+) -> Result<serde_json::Value, Status> {
+    ensure_tags_exist(db, &request.add).await?;
+    ensure_tags_exist(db, &request.remove).await?;
+    let file_ids = get_file_ids(fw, &request.files).await?;
+
+    let removed = fw
+        .remove_tags(&file_ids, &request.remove)
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to remove tags from files");
+            Status::InternalServerError
+        })?;
+
+    let added = fw.add_tags(&file_ids, &request.add).await.map_err(|e| {
+        logger::error!(err = ?e, "failed to add tags to files");
+        Status::InternalServerError
+    })?;
+
+    Ok(json!({
+        "added": added,
+        "removed": removed,
+    }))
+}
+
+async fn get_tag(fw: &FileWatcher, id: i32) -> Result<TagsIndexItem, Status> {
+    fw.get_tag(id)
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to get tag");
+            Status::InternalServerError
+        })?
+        .map(Into::into)
+        .ok_or(Status::NotFound)
+}
+
+/// Tag names are unique regardless of case
+async fn ensure_name_available(
+    fw: &FileWatcher,
+    name: &str,
+    except_id: Option<i32>,
+) -> Result<(), Status> {
+    let existing = fw.find_tag_by_name(name).await.map_err(|e| {
+        logger::error!(err = ?e, "failed to find tag");
+        Status::InternalServerError
+    })?;
+
+    match existing {
+        Some(x) if Some(x.id) != except_id => Err(Status::Conflict),
+        _ => Ok(()),
+    }
+}
+
+async fn ensure_tags_exist(db: &DatabaseConnection, ids: &[i32]) -> Result<(), Status> {
+    let ids = ids.iter().copied().collect::<HashSet<_>>();
+
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let count = tags::Entity::find()
+        .filter(tags::Column::Id.is_in(ids.iter().copied()))
+        .count(db)
+        .await
+        .map_err(|e| {
+            logger::error!(err = ?e, "failed to get tags");
+            Status::InternalServerError
+        })?;
+
+    if count != ids.len() as u64 {
+        return Err(Status::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Resolve the ULIDs to file ids. Fails if any of the files doesn't exist.
+async fn get_file_ids(fw: &FileWatcher, ulids: &[String]) -> Result<Vec<i32>, Status> {
+    let ulids = ulids
+        .iter()
+        .map(|x| x.to_uppercase())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    let files = fw.get_files_by_ulids(&ulids).await.map_err(|e| {
+        logger::error!(err = ?e, "failed to get files");
+        Status::InternalServerError
+    })?;
+
+    if files.len() != ulids.len() {
+        return Err(Status::NotFound);
+    }
+
+    Ok(files.into_iter().map(|x| x.id).collect())
+}
+
+pub fn get() -> RouteList {
+    vec![(
+        "/".into(),
+        routes![
+            index,
+            show,
+            create,
+            rename,
+            delete,
+            add_to_file,
+            remove_from_file,
+            bulk
+        ],
+    )]
+}
@@ -14,7 +14,9 @@ pub struct Model {
     pub key: String,
     pub value: String,
     pub meta: String,
-    pub created_at: String,
+    #[serde(deserialize_with = "crate::timestamp::deserialize")]
+    #[typeshare(serialized_as = "String")]
+    pub created_at: DateTimeUtc,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
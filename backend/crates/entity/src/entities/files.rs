@@ -13,11 +13,17 @@ pub struct Model {
     pub ulid: String,
     pub path: String,
     pub hash: String,
-    pub created_at: String,
+    #[serde(deserialize_with = "crate::timestamp::deserialize")]
+    #[typeshare(serialized_as = "String")]
+    pub created_at: DateTimeUtc,
     pub file_type: Option<String>,
     pub file_size: Option<i64>,
-    pub file_ctime: Option<String>,
-    pub file_mtime: Option<String>,
+    #[serde(default, deserialize_with = "crate::timestamp::deserialize_option")]
+    #[typeshare(serialized_as = "Option<String>")]
+    pub file_ctime: Option<DateTimeUtc>,
+    #[serde(default, deserialize_with = "crate::timestamp::deserialize_option")]
+    #[typeshare(serialized_as = "Option<String>")]
+    pub file_mtime: Option<DateTimeUtc>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -26,6 +32,8 @@ pub enum Relation {
     FileData,
     #[sea_orm(has_many = "super::files_tags::Entity")]
     FilesTags,
+    #[sea_orm(has_many = "super::jobs::Entity")]
+    Jobs,
 }
 
 impl Related<super::file_data::Entity> for Entity {
@@ -40,4 +48,10 @@ impl Related<super::files_tags::Entity> for Entity {
     }
 }
 
+impl Related<super::jobs::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Jobs.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
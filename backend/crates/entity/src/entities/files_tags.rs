@@ -12,7 +12,9 @@ pub struct Model {
     pub id: i32,
     pub file_id: i32,
     pub tag_id: i32,
-    pub created_at: String,
+    #[serde(deserialize_with = "crate::timestamp::deserialize")]
+    #[typeshare(serialized_as = "String")]
+    pub created_at: DateTimeUtc,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
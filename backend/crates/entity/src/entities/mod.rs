@@ -5,4 +5,5 @@ pub mod prelude;
 pub mod file_data;
 pub mod files;
 pub mod files_tags;
+pub mod jobs;
 pub mod tags;
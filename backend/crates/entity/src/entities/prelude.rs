@@ -2,5 +2,5 @@
 
 pub use super::{
     file_data::Entity as FileData, files::Entity as Files, files_tags::Entity as FilesTags,
-    tags::Entity as Tags,
+    jobs::Entity as Jobs, tags::Entity as Tags,
 };
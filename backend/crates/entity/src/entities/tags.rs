@@ -11,7 +11,9 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     pub name: String,
-    pub created_at: String,
+    #[serde(deserialize_with = "crate::timestamp::deserialize")]
+    #[typeshare(serialized_as = "String")]
+    pub created_at: DateTimeUtc,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
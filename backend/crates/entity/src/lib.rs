@@ -1,3 +1,5 @@
 mod entities;
+pub mod search;
+pub mod timestamp;
 
 pub use entities::*;
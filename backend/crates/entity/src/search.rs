@@ -0,0 +1,14 @@
+//! What goes into the full-text search index, besides the path of the file.
+
+/// Text recognized in the image
+pub const OCR: &str = "ocr";
+/// A description of the image, eg. written by a captioning model
+pub const CAPTION: &str = "caption";
+/// Titles, descriptions and comments embedded in the file, eg. in its EXIF or container tags
+pub const DESCRIPTION: &str = "description";
+
+/// `file_data` keys whose values are searchable text.
+///
+/// The search index is kept up to date by triggers created with a copy of this list in the
+/// migrations, so changing it needs a migration that creates them again.
+pub const TEXT_KEYS: &[&str] = &[OCR, CAPTION, DESCRIPTION];
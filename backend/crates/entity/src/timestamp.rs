@@ -0,0 +1,29 @@
+//! Reading timestamps that were stored or exported before they had a proper type.
+//!
+//! Those are either RFC 3339 or the `2023-12-06 12:00:00` format of SQLite's
+//! `CURRENT_TIMESTAMP`, always in UTC.
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Deserializer};
+
+/// Parse a timestamp in any of the formats the database has used
+pub fn parse(value: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    value.parse::<DateTime<Utc>>().or_else(|_| {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f").map(|x| x.and_utc())
+    })
+}
+
+/// For `#[serde(deserialize_with)]`, accepting the same formats as [`parse`]
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse(&value).map_err(serde::de::Error::custom)
+}
+
+/// [`deserialize`] for optional timestamps
+pub fn deserialize_option<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DateTime<Utc>>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|x| parse(&x).map_err(serde::de::Error::custom))
+        .transpose()
+}
@@ -167,7 +167,6 @@ impl error::Error for FfProbeError {}
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(feature = "__internal_deny_unknown_fields", serde(deny_unknown_fields))]
-#[serde(rename_all = "camelCase")]
 pub struct FfProbeResult {
     pub streams: Option<Vec<Stream>>,
     pub format: Option<Format>,
@@ -175,7 +174,6 @@ pub struct FfProbeResult {
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(feature = "__internal_deny_unknown_fields", serde(deny_unknown_fields))]
-#[serde(rename_all = "camelCase")]
 pub struct Stream {
     pub index: i64,
     pub codec_name: Option<String>,
@@ -229,20 +227,85 @@ pub struct Stream {
     #[serde(default)]
     pub side_data_list: Vec<SideData>,
 }
+impl FfProbeResult {
+    /// The main video stream. Cover art of audio files shows up as a video stream too, it's
+    /// skipped.
+    #[must_use]
+    pub fn video_stream(&self) -> Option<&Stream> {
+        self.streams.as_deref()?.iter().find(|x| {
+            x.codec_type.as_deref() == Some("video")
+                && x.disposition
+                    .as_ref()
+                    .and_then(|x| x.attached_pic)
+                    .unwrap_or_default()
+                    == 0
+        })
+    }
+}
+
+impl Stream {
+    /// Degrees the stream has to be turned clockwise to display upright, one of 0, 90, 180 and
+    /// 270.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn rotation(&self) -> i32 {
+        // The display matrix rotates counterclockwise
+        let degrees = self
+            .side_data_list
+            .iter()
+            .find_map(|x| x.rotation)
+            .map(|x| -x)
+            .or_else(|| self.tags.as_ref()?.rotate.as_deref()?.parse().ok())
+            .unwrap_or_default();
+
+        ((degrees / 90.0).round() as i32).rem_euclid(4) * 90
+    }
+
+    /// Width of a pixel relative to its height. [`None`] if unknown, which means square.
+    #[must_use]
+    pub fn sample_aspect_ratio(&self) -> Option<f64> {
+        let (numer, denom) = self.sample_aspect_ratio.as_deref()?.split_once(':')?;
+        let (numer, denom) = (numer.parse::<f64>().ok()?, denom.parse::<f64>().ok()?);
+
+        (numer > 0.0 && denom > 0.0).then(|| numer / denom)
+    }
+
+    /// The size the stream is displayed at, with non-square pixels stretched to square ones
+    /// and the rotation applied.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn display_dimensions(&self) -> Option<(i64, i64)> {
+        let width = self.width.filter(|x| *x > 0)?;
+        let height = self.height.filter(|x| *x > 0)?;
+
+        let width = match self.sample_aspect_ratio() {
+            Some(sar) => ((width as f64 * sar).round() as i64).max(1),
+            None => width,
+        };
+
+        if self.rotation() % 180 == 90 {
+            Some((height, width))
+        } else {
+            Some((width, height))
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(feature = "__internal_deny_unknown_fields", serde(deny_unknown_fields))]
 // Allowed to prevent having to break compatibility of float fields are added.
 #[allow(clippy::derive_partial_eq_without_eq)]
-#[serde(rename_all = "camelCase")]
 pub struct SideData {
     pub side_data_type: String,
+    /// Degrees the video is rotated by counterclockwise, set on the display matrix.
+    pub rotation: Option<f64>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(feature = "__internal_deny_unknown_fields", serde(deny_unknown_fields))]
 // Allowed to prevent having to break compatibility of float fields are added.
 #[allow(clippy::derive_partial_eq_without_eq)]
-#[serde(rename_all = "camelCase")]
 pub struct Disposition {
     pub default: i64,
     pub dub: i64,
@@ -262,7 +325,6 @@ pub struct Disposition {
 #[cfg_attr(feature = "__internal_deny_unknown_fields", serde(deny_unknown_fields))]
 // Allowed to prevent having to break compatibility of float fields are added.
 #[allow(clippy::derive_partial_eq_without_eq)]
-#[serde(rename_all = "camelCase")]
 pub struct StreamTags {
     pub language: Option<String>,
     pub creation_time: Option<String>,
@@ -270,11 +332,13 @@ pub struct StreamTags {
     pub encoder: Option<String>,
     pub timecode: Option<String>,
     pub reel_name: Option<String>,
+    /// Degrees the video is rotated by clockwise. Only set by older versions of ffprobe,
+    /// newer ones put it on the display matrix side data.
+    pub rotate: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(feature = "__internal_deny_unknown_fields", serde(deny_unknown_fields))]
-#[serde(rename_all = "camelCase")]
 pub struct Format {
     pub filename: Option<String>,
     pub nb_streams: Option<i64>,
@@ -314,7 +378,6 @@ impl Format {
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(feature = "__internal_deny_unknown_fields", serde(deny_unknown_fields))]
 #[allow(clippy::derive_partial_eq_without_eq)]
-#[serde(rename_all = "camelCase")]
 pub struct FormatTags {
     #[serde(rename = "WMFSDKNeeded")]
     pub wmf_sdk_needed: Option<String>,
@@ -1 +1,2 @@
 pub mod ffprobe;
+pub mod sprite;
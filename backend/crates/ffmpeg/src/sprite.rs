@@ -0,0 +1,299 @@
+use std::{
+    error,
+    fmt::{self, Debug, Write},
+    io,
+    path::Path,
+    time::Duration,
+};
+
+use config::CONFIG;
+use tokio::process;
+
+use crate::ffprobe::{self, FfProbeError};
+
+/// Make a sprite sheet of evenly spaced frames of the video at `input`, saved to `output`.
+///
+/// The frames are laid out in rows, left to right, with the first frame taken at the start of
+/// the video. Only keyframes are decoded, so a frame can be up to a keyframe interval off.
+#[tracing::instrument]
+pub async fn sprite(
+    input: impl AsRef<Path> + Debug,
+    output: impl AsRef<Path> + Debug,
+    config: SpriteConfig,
+) -> Result<Sprite, SpriteError> {
+    let (input, output) = (input.as_ref(), output.as_ref());
+
+    let ffmpeg_path = CONFIG
+        .dependencies
+        .ffmpeg_path
+        .as_ref()
+        .ok_or_else(|| SpriteError::MissingBinary("ffmpeg".to_string()))?;
+
+    let probe = ffprobe::ffprobe(input).await.map_err(SpriteError::Probe)?;
+
+    let duration = probe
+        .format
+        .as_ref()
+        .and_then(ffprobe::Format::get_duration)
+        .filter(|x| !x.is_zero())
+        .ok_or(SpriteError::NoDuration)?;
+
+    // Frames come out rotated and with square pixels, so the tiles get the displayed size
+    let (width, height) = probe
+        .video_stream()
+        .and_then(ffprobe::Stream::display_dimensions)
+        .ok_or(SpriteError::NoVideo)?;
+
+    let frames = config.frames.max(1);
+    let columns = config.columns.clamp(1, frames);
+    let rows = frames.div_ceil(columns);
+    let tile_width = config.tile_width;
+    let tile_height = tile_height(tile_width, width, height);
+
+    let mut cmd = process::Command::new(ffmpeg_path);
+    {
+        cmd.args(["-hide_banner", "-loglevel", "error", "-y"])
+            .args(["-skip_frame", "nokey"])
+            .arg("-i")
+            .arg(input)
+            .args(["-an", "-sn"])
+            .args([
+                "-vf",
+                &format!(
+                    "fps={fps},scale={tile_width}:{tile_height},tile={columns}x{rows}",
+                    fps = f64::from(frames) / duration.as_secs_f64(),
+                ),
+            ])
+            .args(["-frames:v", "1"])
+            .args(["-q:v", &config.quality.to_string()])
+            .arg(output);
+    }
+
+    logger::debug!(?cmd, "Running ffmpeg");
+
+    let out = cmd.output().await.map_err(SpriteError::Io)?;
+
+    logger::trace!(?out, "ffmpeg output");
+
+    if !out.status.success() {
+        return Err(SpriteError::Status(out));
+    }
+
+    Ok(Sprite {
+        frames,
+        columns,
+        rows,
+        tile_width,
+        tile_height,
+        duration,
+    })
+}
+
+/// Height of a tile `tile_width` wide with the aspect ratio of the video, rounded to even for
+/// the encoders that need it
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+#[allow(clippy::cast_precision_loss)]
+fn tile_height(tile_width: u32, width: i64, height: i64) -> u32 {
+    let height = f64::from(tile_width) * height as f64 / width as f64;
+
+    ((height / 2.0).round() as u32).max(1) * 2
+}
+
+/// Sprite sheet configuration.
+///
+/// Use [`SpriteConfig::builder`] for constructing a new config.
+#[derive(Clone, Copy, Debug)]
+pub struct SpriteConfig {
+    frames: u32,
+    columns: u32,
+    tile_width: u32,
+    quality: u8,
+}
+
+impl SpriteConfig {
+    /// Construct a new `SpriteConfigBuilder`.
+    #[must_use]
+    pub fn builder() -> SpriteConfigBuilder {
+        SpriteConfigBuilder::new()
+    }
+}
+
+impl Default for SpriteConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// Build the sprite sheet configuration.
+pub struct SpriteConfigBuilder {
+    config: SpriteConfig,
+}
+
+impl SpriteConfigBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            config: SpriteConfig {
+                frames: 100,
+                columns: 10,
+                tile_width: 160,
+                quality: 5,
+            },
+        }
+    }
+
+    /// How many frames the sprite sheet has.
+    #[must_use]
+    pub fn frames(mut self, frames: u32) -> Self {
+        self.config.frames = frames;
+        self
+    }
+
+    /// How many frames are in a row.
+    #[must_use]
+    pub fn columns(mut self, columns: u32) -> Self {
+        self.config.columns = columns;
+        self
+    }
+
+    /// Width of a frame, the height follows from the aspect ratio of the video.
+    #[must_use]
+    pub fn tile_width(mut self, tile_width: u32) -> Self {
+        self.config.tile_width = tile_width;
+        self
+    }
+
+    /// The -`q:v` setting of the output, 2 (best) to 31 (worst) for JPEG.
+    #[must_use]
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.config.quality = quality;
+        self
+    }
+
+    /// Finalize the builder into a [`SpriteConfig`].
+    #[must_use]
+    pub fn build(self) -> SpriteConfig {
+        self.config
+    }
+
+    /// Make a sprite sheet with the config produced by this builder.
+    pub async fn run(
+        self,
+        input: impl AsRef<Path> + Debug,
+        output: impl AsRef<Path> + Debug,
+    ) -> Result<Sprite, SpriteError> {
+        sprite(input, output, self.config).await
+    }
+}
+
+impl Default for SpriteConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Layout of a sprite sheet made by [`sprite`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprite {
+    pub frames: u32,
+    pub columns: u32,
+    pub rows: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// Length of the video
+    pub duration: Duration,
+}
+
+impl Sprite {
+    /// Width of the whole sheet
+    #[must_use]
+    pub fn width(&self) -> u32 {
+        self.columns * self.tile_width
+    }
+
+    /// Height of the whole sheet
+    #[must_use]
+    pub fn height(&self) -> u32 {
+        self.rows * self.tile_height
+    }
+
+    /// Time between two frames
+    #[must_use]
+    pub fn interval(&self) -> Duration {
+        self.duration / self.frames
+    }
+
+    /// A WebVTT thumbnails track mapping the time each frame covers to its region of the sheet,
+    /// which is referred to by `url`.
+    #[must_use]
+    pub fn webvtt(&self, url: &str) -> String {
+        let mut vtt = String::from("WEBVTT\n");
+
+        for i in 0..self.frames {
+            let start = self.interval() * i;
+            let end = (self.interval() * (i + 1)).min(self.duration);
+
+            let x = i % self.columns * self.tile_width;
+            let y = i / self.columns * self.tile_height;
+
+            // Writing to a String can't fail
+            let _ = write!(
+                vtt,
+                "\n{} --> {}\n{url}#xywh={x},{y},{w},{h}\n",
+                vtt_timestamp(start),
+                vtt_timestamp(end),
+                w = self.tile_width,
+                h = self.tile_height,
+            );
+        }
+
+        vtt
+    }
+}
+
+/// `hh:mm:ss.ttt`
+fn vtt_timestamp(time: Duration) -> String {
+    let millis = time.as_millis();
+
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SpriteError {
+    Io(io::Error),
+    Status(std::process::Output),
+    Probe(FfProbeError),
+    MissingBinary(String),
+    NoDuration,
+    NoVideo,
+}
+
+impl fmt::Display for SpriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpriteError::Io(e) => write!(f, "I/O error: {e}"),
+            SpriteError::Status(o) => {
+                write!(
+                    f,
+                    "ffmpeg exited with status code {}: {}",
+                    o.status,
+                    String::from_utf8_lossy(&o.stderr)
+                )
+            }
+            SpriteError::Probe(e) => write!(f, "ffprobe error: {e}"),
+            SpriteError::MissingBinary(e) => write!(f, "Missing binary: {e}"),
+            SpriteError::NoDuration => write!(f, "Video has no duration"),
+            SpriteError::NoVideo => write!(f, "File has no video stream"),
+        }
+    }
+}
+
+impl error::Error for SpriteError {}
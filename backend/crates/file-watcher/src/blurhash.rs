@@ -3,16 +3,20 @@ use std::path::PathBuf;
 use anyhow::{anyhow, Result};
 use blurhash::encode as blurhash_encode;
 use config::CONFIG;
-use entity::{file_data, files};
+use entity::files;
 use image::{EncodableLayout, GenericImageView};
-use sea_orm::{prelude::*, Set};
+use sea_orm::prelude::*;
 use tokio::task;
 use tracing::instrument;
 
-use crate::FileWatcher;
+use crate::{
+    data::{BlurhashData, FileData},
+    helpers::image::open_image,
+    thumb::{ThumbFormat, ThumbSize},
+    FileWatcher,
+};
 
 pub const BLURHASH_COMPONENTS: (u32, u32) = (3, 3);
-pub const FILE_DATA_BLURHASH_KEY: &str = "blurhash";
 
 impl FileWatcher {
     #[instrument(skip(self))]
@@ -23,16 +27,8 @@ impl FileWatcher {
             .await?
             .ok_or_else(|| anyhow!("File not found: {:?}", ulid))?;
 
-        let file_data_blurhash = file_data::Entity::find()
-            .filter(file_data::Column::FileId.eq(db_file.id))
-            .filter(file_data::Column::Key.eq(FILE_DATA_BLURHASH_KEY))
-            .one(self.db())
-            .await?;
-
-        if let Some(file_data_blurhash) = file_data_blurhash {
-            logger::trace!(data = ?file_data_blurhash, "File has blurhash data");
-
-            return Ok(file_data_blurhash.value);
+        if let Some(hash) = self.get_blurhash(db_file.id).await? {
+            return Ok(hash);
         }
 
         let file_path = CONFIG.app.directory_absolute(&db_file.path);
@@ -42,6 +38,48 @@ impl FileWatcher {
         Ok(hash)
     }
 
+    /// Get or generate the blurhash for the file, falling back to
+    /// generating it from the poster thumb if the raw file can't be decoded.
+    #[instrument(skip(self))]
+    pub(crate) async fn get_or_generate_file_blurhash(
+        &self,
+        db_file: &files::Model,
+    ) -> Result<String> {
+        if let Some(hash) = self.get_blurhash(db_file.id).await? {
+            return Ok(hash);
+        }
+
+        let file_path = CONFIG.app.directory_absolute(&db_file.path);
+
+        if let Ok(hash) = self.generate_blurhash(file_path, db_file.id).await {
+            return Ok(hash);
+        }
+
+        logger::debug!("Failed to generate blurhash from raw file. Generating from thumb");
+
+        let thumb = self
+            .get_or_generate_thumb(&db_file.ulid, ThumbSize::Poster, ThumbFormat::Jpeg)
+            .await
+            .map_err(|e| anyhow!("Failed to generate thumb: {}", e))?;
+
+        let thumb_path = CONFIG
+            .app
+            .metadata_directory_absolute(&thumb.path.to_string_lossy());
+
+        self.generate_blurhash(thumb_path, db_file.id).await
+    }
+
+    pub(crate) async fn get_blurhash(&self, file_id: i32) -> Result<Option<String>> {
+        match self.get_file_data(file_id, FileData::BLURHASH).await? {
+            Some(FileData::Blurhash(x)) => {
+                logger::trace!(data = ?x, "File has blurhash data");
+
+                Ok(Some(x.hash))
+            }
+            _ => Ok(None),
+        }
+    }
+
     #[instrument(skip(self))]
     pub(crate) async fn generate_blurhash(
         &self,
@@ -51,7 +89,7 @@ impl FileWatcher {
         logger::trace!(path = ?image_path, "Generating blurhash");
 
         let hash = task::spawn_blocking(move || {
-            let img = image::open(&image_path)
+            let img = open_image(&image_path)
                 .map_err(|e| anyhow!("Failed to open image {:?}: {}", &image_path, e))?;
             let (width, height) = img.dimensions();
 
@@ -68,17 +106,12 @@ impl FileWatcher {
 
         logger::trace!(hash = ?&hash, "Generated blurhash");
 
-        let file_data_model = file_data::ActiveModel {
-            file_id: Set(file_id),
-            key: Set(FILE_DATA_BLURHASH_KEY.to_string()),
-            value: Set(hash.clone()),
-            ..Default::default()
-        };
-
-        file_data_model
-            .save(self.db())
-            .await
-            .map_err(|e| anyhow!("Failed to save blurhash to db: {}", e.to_string()))?;
+        self.add_file_data(
+            file_id,
+            FileData::Blurhash(BlurhashData { hash: hash.clone() }),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to save blurhash to db: {}", e.to_string()))?;
 
         logger::trace!("Saved blurhash to db");
 
@@ -0,0 +1,452 @@
+//! Typed access to the data derived from files, stored in the `file_data` table.
+//!
+//! A row holds one [`FileData`]: its key, the payload as JSON in `meta` with the payload
+//! version under `"v"`, and the main value of the payload (eg. the thumb path) in `value`,
+//! which is what the search index and exports use.
+
+use std::borrow::Cow;
+
+use anyhow::{anyhow, bail, Result};
+use entity::{file_data, search};
+use sea_orm::{prelude::*, Condition, Set, TransactionTrait};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use typeshare::typeshare;
+
+use crate::{
+    thumb::{is_thumb_key, thumb_key, thumb_key_condition, ThumbFormat},
+    FileWatcher,
+};
+
+/// Version of the payloads written to `meta`.
+///
+/// Rows written before payloads were versioned count as version 0. Bump this when a payload
+/// changes and teach [`upgrade`] how to read the older versions.
+pub const FILE_DATA_VERSION: u64 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "kind", content = "data")]
+#[typeshare]
+pub enum FileData {
+    Thumb(ThumbData),
+    Blurhash(BlurhashData),
+    MediaDimensions(MediaDimensions),
+    MediaInfo(MediaInfo),
+    Phash(PhashData),
+    PosterFrame(PosterFrameData),
+    Sprite(SpriteData),
+    SpriteTrack(SpriteTrackData),
+    Description(DescriptionData),
+    /// Data of extractors this crate doesn't know, eg. text for the search index
+    Other(OtherData),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct ThumbData {
+    /// The `ThumbSize` it was made for, eg. `poster`
+    pub size: String,
+    /// Thumbs from before other formats were supported are all JPEG
+    #[serde(default)]
+    pub format: ThumbFormat,
+    /// Relative to the metadata directory
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub hash: String,
+    /// Where in the video the frame was taken from, in seconds
+    #[serde(default)]
+    pub timestamp: Option<f64>,
+}
+
+impl ThumbData {
+    /// The `file_data` key of the thumb, see [`crate::thumb::ThumbSize::key`]
+    #[must_use]
+    pub fn key(&self) -> String {
+        thumb_key(&self.size, self.format)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct BlurhashData {
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct MediaDimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl From<(u32, u32)> for MediaDimensions {
+    fn from((width, height): (u32, u32)) -> Self {
+        Self { width, height }
+    }
+}
+
+impl From<(i64, i64)> for MediaDimensions {
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    fn from((width, height): (i64, i64)) -> Self {
+        Self {
+            width: width as u32,
+            height: height as u32,
+        }
+    }
+}
+
+/// What ffprobe knows about a video or audio file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct MediaInfo {
+    /// In seconds
+    pub duration: Option<f64>,
+    /// Names of the container format, comma separated, eg. `mov,mp4,m4a,3gp,3g2,mj2`
+    pub container: Option<String>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    /// Average frames per second
+    pub frame_rate: Option<f64>,
+    /// Bits per second of the whole file
+    #[typeshare(serialized_as = "Option<number>")]
+    pub bit_rate: Option<u64>,
+    /// Degrees the video has to be turned clockwise to display upright, one of 0, 90, 180
+    /// and 270
+    pub rotation: i32,
+    pub pixel_format: Option<String>,
+    pub has_audio: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct PhashData {
+    /// The 64 bit hash as 16 hex digits
+    pub hash: String,
+}
+
+impl PhashData {
+    #[must_use]
+    pub fn new(hash: u64) -> Self {
+        Self {
+            hash: format!("{hash:016x}"),
+        }
+    }
+
+    pub fn value(&self) -> Result<u64> {
+        u64::from_str_radix(&self.hash, 16)
+            .map_err(|e| anyhow!("Invalid phash {:?}: {}", self.hash, e))
+    }
+}
+
+/// The frame of a video its thumbs are made from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct PosterFrameData {
+    /// In seconds from the start
+    pub timestamp: f64,
+    /// Whether it was picked by a user instead of selected automatically
+    pub manual: bool,
+}
+
+/// A sheet of evenly spaced frames of a video, for previews while scrubbing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct SpriteData {
+    /// Relative to the metadata directory
+    pub path: String,
+    pub hash: String,
+    pub frames: u32,
+    pub columns: u32,
+    pub rows: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// Seconds between two frames
+    pub interval: f64,
+}
+
+/// The WebVTT thumbnails track mapping the time ranges of a video to regions of its sprite
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct SpriteTrackData {
+    /// Relative to the metadata directory
+    pub path: String,
+    pub hash: String,
+}
+
+/// Titles, descriptions and comments embedded in the file, one per line. Searchable.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct DescriptionData {
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct OtherData {
+    pub key: String,
+    pub value: String,
+    pub meta: Value,
+}
+
+impl FileData {
+    pub const BLURHASH: &'static str = "blurhash";
+    pub const DESCRIPTION: &'static str = search::DESCRIPTION;
+    pub const MEDIA_DIMENSIONS: &'static str = "media-dimensions";
+    pub const MEDIA_INFO: &'static str = "media-info";
+    pub const PHASH: &'static str = "phash";
+    pub const POSTER_FRAME: &'static str = "poster-frame";
+    pub const SPRITE: &'static str = "sprite";
+    pub const SPRITE_TRACK: &'static str = "sprite-track";
+
+    /// Whether the `value` of rows with the key is the path of a file in the metadata directory
+    #[must_use]
+    pub fn is_file_key(key: &str) -> bool {
+        is_thumb_key(key) || matches!(key, Self::SPRITE | Self::SPRITE_TRACK)
+    }
+
+    /// Condition over `file_data` that includes every row of a [`Self::is_file_key`] key.
+    /// Also matches some other keys, so filter the rows with it too.
+    #[must_use]
+    pub fn file_key_condition() -> Condition {
+        thumb_key_condition().add(file_data::Column::Key.is_in([Self::SPRITE, Self::SPRITE_TRACK]))
+    }
+
+    /// The `file_data` key of the data
+    #[must_use]
+    pub fn key(&self) -> Cow<'_, str> {
+        match self {
+            Self::Thumb(x) => x.key().into(),
+            Self::Blurhash(_) => Self::BLURHASH.into(),
+            Self::MediaDimensions(_) => Self::MEDIA_DIMENSIONS.into(),
+            Self::MediaInfo(_) => Self::MEDIA_INFO.into(),
+            Self::Phash(_) => Self::PHASH.into(),
+            Self::PosterFrame(_) => Self::POSTER_FRAME.into(),
+            Self::Sprite(_) => Self::SPRITE.into(),
+            Self::SpriteTrack(_) => Self::SPRITE_TRACK.into(),
+            Self::Description(_) => Self::DESCRIPTION.into(),
+            Self::Other(x) => x.key.as_str().into(),
+        }
+    }
+
+    /// What goes into the `value` column
+    fn value(&self) -> String {
+        match self {
+            Self::Thumb(x) => x.path.clone(),
+            Self::Blurhash(x) => x.hash.clone(),
+            Self::MediaDimensions(_) | Self::MediaInfo(_) => String::new(),
+            Self::Phash(x) => x.hash.clone(),
+            Self::PosterFrame(x) => x.timestamp.to_string(),
+            Self::Sprite(x) => x.path.clone(),
+            Self::SpriteTrack(x) => x.path.clone(),
+            Self::Description(x) => x.text.clone(),
+            Self::Other(x) => x.value.clone(),
+        }
+    }
+
+    /// What goes into the `meta` column
+    fn meta(&self) -> Result<String> {
+        let mut meta = match self {
+            Self::Thumb(x) => serde_json::to_value(x)?,
+            Self::Blurhash(x) => serde_json::to_value(x)?,
+            Self::MediaDimensions(x) => serde_json::to_value(x)?,
+            Self::MediaInfo(x) => serde_json::to_value(x)?,
+            Self::Phash(x) => serde_json::to_value(x)?,
+            Self::PosterFrame(x) => serde_json::to_value(x)?,
+            Self::Sprite(x) => serde_json::to_value(x)?,
+            Self::SpriteTrack(x) => serde_json::to_value(x)?,
+            Self::Description(x) => serde_json::to_value(x)?,
+            // Not ours to version
+            Self::Other(x) => return Ok(x.meta.to_string()),
+        };
+
+        meta["v"] = json!(FILE_DATA_VERSION);
+
+        Ok(meta.to_string())
+    }
+
+    /// A new row for the data of the file
+    pub fn to_active_model(&self, file_id: i32) -> Result<file_data::ActiveModel> {
+        Ok(file_data::ActiveModel {
+            file_id: Set(file_id),
+            key: Set(self.key().to_string()),
+            value: Set(self.value()),
+            meta: Set(self.meta()?),
+            ..Default::default()
+        })
+    }
+
+    /// Store the data of the file in place of its data with the same key. A file has one row
+    /// per key, so run it in a transaction for the row to never be missing.
+    pub async fn replace<C>(&self, db: &C, file_id: i32) -> Result<file_data::Model>
+    where
+        C: ConnectionTrait,
+    {
+        file_data::Entity::delete_many()
+            .filter(file_data::Column::FileId.eq(file_id))
+            .filter(file_data::Column::Key.eq(self.key().as_ref()))
+            .exec(db)
+            .await?;
+
+        Ok(self.to_active_model(file_id)?.insert(db).await?)
+    }
+}
+
+impl TryFrom<file_data::Model> for FileData {
+    type Error = anyhow::Error;
+
+    fn try_from(data: file_data::Model) -> Result<Self, Self::Error> {
+        let meta: Value = match data.meta.as_str() {
+            "" => json!({}),
+            x => serde_json::from_str(x)?,
+        };
+
+        let parsed = match data.key.as_str() {
+            key if is_thumb_key(key) => payload(&data, meta).map(Self::Thumb),
+            Self::BLURHASH => payload(&data, meta).map(Self::Blurhash),
+            Self::MEDIA_DIMENSIONS => payload(&data, meta).map(Self::MediaDimensions),
+            Self::MEDIA_INFO => payload(&data, meta).map(Self::MediaInfo),
+            Self::PHASH => payload(&data, meta).map(Self::Phash),
+            Self::POSTER_FRAME => payload(&data, meta).map(Self::PosterFrame),
+            Self::SPRITE => payload(&data, meta).map(Self::Sprite),
+            Self::SPRITE_TRACK => payload(&data, meta).map(Self::SpriteTrack),
+            Self::DESCRIPTION => payload(&data, meta).map(Self::Description),
+            _ => {
+                return Ok(Self::Other(OtherData {
+                    key: data.key,
+                    value: data.value,
+                    meta,
+                }))
+            }
+        };
+
+        parsed.map_err(|e| anyhow!("Invalid {:?} file data {}: {}", data.key, data.id, e))
+    }
+}
+
+/// Read the payload of a row, upgrading older versions
+fn payload<T: DeserializeOwned>(data: &file_data::Model, meta: Value) -> Result<T> {
+    if !meta.is_object() {
+        bail!("Payload is not an object: {}", meta);
+    }
+
+    let version = meta.get("v").and_then(Value::as_u64).unwrap_or(0);
+
+    let meta = match version {
+        FILE_DATA_VERSION => meta,
+        x if x < FILE_DATA_VERSION => upgrade(data, x, meta),
+        x => bail!("Payload version {} is newer than this build", x),
+    };
+
+    Ok(serde_json::from_value(meta)?)
+}
+
+/// Bring the payload of an older version up to date
+fn upgrade(data: &file_data::Model, version: u64, mut meta: Value) -> Value {
+    if version == 0 {
+        // The main value of the payload was only stored in `value`
+        match data.key.as_str() {
+            key if is_thumb_key(key) => {
+                meta["size"] = json!(data.key);
+                meta["path"] = json!(data.value);
+            }
+            FileData::BLURHASH | FileData::PHASH => meta["hash"] = json!(data.value),
+            _ => {}
+        }
+    }
+
+    meta
+}
+
+impl FileWatcher {
+    /// All the data of the file. Rows that can't be read are skipped.
+    pub async fn get_all_file_data(&self, file_id: i32) -> Result<Vec<FileData>> {
+        let data = file_data::Entity::find()
+            .filter(file_data::Column::FileId.eq(file_id))
+            .all(self.db())
+            .await?
+            .into_iter()
+            .filter_map(|x| {
+                FileData::try_from(x)
+                    .map_err(|e| logger::warn!(err = ?e, "Skipping file data"))
+                    .ok()
+            })
+            .collect();
+
+        Ok(data)
+    }
+
+    /// The data of the file with the key, eg. [`FileData::BLURHASH`]
+    pub async fn get_file_data(&self, file_id: i32, key: &str) -> Result<Option<FileData>> {
+        file_data::Entity::find()
+            .filter(file_data::Column::FileId.eq(file_id))
+            .filter(file_data::Column::Key.eq(key))
+            .one(self.db())
+            .await?
+            .map(FileData::try_from)
+            .transpose()
+    }
+
+    /// Store data for the file, replacing its data with the same key
+    pub async fn add_file_data(&self, file_id: i32, data: FileData) -> Result<file_data::Model> {
+        let txn = self.db().begin().await?;
+        let model = data.replace(&txn, file_id).await?;
+        txn.commit().await?;
+
+        logger::trace!(data = ?model, "Saved file data");
+
+        Ok(model)
+    }
+
+    /// Delete the data of the file with the key
+    pub async fn remove_file_data(&self, file_id: i32, key: &str) -> Result<()> {
+        file_data::Entity::delete_many()
+            .filter(file_data::Column::FileId.eq(file_id))
+            .filter(file_data::Column::Key.eq(key))
+            .exec(self.db())
+            .await?;
+
+        if key == FileData::PHASH {
+            self.forget_phashes([file_id]);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test::{file_watcher, insert_file};
+
+    #[tokio::test]
+    async fn replaces_data_with_the_same_key() {
+        let fw = file_watcher().await;
+        let file = insert_file(&fw, "a.mp4", "a").await;
+
+        let blurhash = |hash: &str| {
+            FileData::Blurhash(BlurhashData {
+                hash: hash.to_string(),
+            })
+        };
+
+        fw.add_file_data(file.id, blurhash("old")).await.unwrap();
+        fw.add_file_data(file.id, blurhash("new")).await.unwrap();
+
+        assert_eq!(
+            fw.get_all_file_data(file.id).await.unwrap(),
+            [blurhash("new")]
+        );
+    }
+}
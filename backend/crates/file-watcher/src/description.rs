@@ -0,0 +1,81 @@
+//! Text embedded in files by the tools that made them, indexed for search.
+
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use ffmpeg::ffprobe::FfProbeResult;
+use tokio::task;
+use tracing::instrument;
+
+use crate::{
+    data::{DescriptionData, FileData},
+    helpers::image::exif_text,
+    FileWatcher,
+};
+
+/// Container tags of videos and audio files that hold text worth searching
+const TEXT_TAGS: &[&str] = &["title", "description", "synopsis", "comment", "keywords"];
+
+impl FileWatcher {
+    /// Read the titles, descriptions and comments from the EXIF of images or the container
+    /// tags of videos and audio files, and save them
+    #[instrument(skip(self))]
+    pub(crate) async fn generate_description(
+        &self,
+        file_id: i32,
+        file_type: &str,
+        file_path: &Path,
+    ) -> Result<DescriptionData> {
+        let lines = if file_type.starts_with("image/") {
+            let file_path = file_path.to_path_buf();
+
+            task::spawn_blocking(move || exif_text(&file_path)).await?
+        } else {
+            let probe = ffmpeg::ffprobe::ffprobe(file_path)
+                .await
+                .map_err(|e| anyhow!("Failed to run ffprobe to get tags: {}", e.to_string()))?;
+
+            tag_text(&probe)
+        };
+
+        let mut text = Vec::<String>::new();
+        for line in lines {
+            if !text.contains(&line) {
+                text.push(line);
+            }
+        }
+
+        let description = DescriptionData {
+            text: text.join("\n"),
+        };
+
+        logger::trace!(?description, "Got description");
+
+        // Files without any are saved too, so they aren't read again
+        self.remove_file_data(file_id, FileData::DESCRIPTION)
+            .await?;
+        self.add_file_data(file_id, FileData::Description(description.clone()))
+            .await?;
+
+        Ok(description)
+    }
+}
+
+fn tag_text(probe: &FfProbeResult) -> Vec<String> {
+    let Some(tags) = probe.format.as_ref().and_then(|x| x.tags.as_ref()) else {
+        return vec![];
+    };
+
+    // Tags are named differently by every container, eg. `TITLE` in Matroska
+    TEXT_TAGS
+        .iter()
+        .flat_map(|name| {
+            tags.extra
+                .iter()
+                .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+                .filter_map(|(_, value)| value.as_str())
+        })
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
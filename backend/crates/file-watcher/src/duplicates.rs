@@ -0,0 +1,399 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use anyhow::{anyhow, bail, Result};
+use config::CONFIG;
+use entity::files;
+use sea_orm::{prelude::*, sea_query::Expr, QueryOrder, QuerySelect};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::instrument;
+use typeshare::typeshare;
+
+use crate::{helpers::file::file_hash, FileWatcher};
+
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub hash: String,
+    pub files: Vec<DuplicateFile>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DuplicateFile {
+    pub file: files::Model,
+    /// Whether the file is a hard link to another file in the group,
+    /// ie. it doesn't take up any extra space.
+    pub linked: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub enum DuplicateResolution {
+    /// Delete the other files from disk
+    Delete,
+    /// Replace the other files with hard links to the kept file
+    Hardlink,
+}
+
+/// What resolving a duplicate group did
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedDuplicates {
+    /// Files that were replaced or removed
+    pub resolved: Vec<files::Model>,
+    /// Files left alone, as their contents changed since they were indexed
+    pub skipped: Vec<files::Model>,
+    /// Why resolving stopped before the rest of the files
+    pub error: Option<String>,
+}
+
+impl FileWatcher {
+    /// Number of hashes shared by more than one file
+    pub async fn count_duplicate_groups(&self) -> Result<u64> {
+        let count = files::Entity::find()
+            .select_only()
+            .column(files::Column::Hash)
+            .group_by(files::Column::Hash)
+            .having(Expr::expr(files::Column::Id.count()).gt(1))
+            .count(self.db())
+            .await?;
+
+        Ok(count)
+    }
+
+    /// Groups of files with the same hash, largest groups first
+    pub async fn get_duplicate_groups(
+        &self,
+        limit: u64,
+        offset: u64,
+    ) -> Result<Vec<DuplicateGroup>> {
+        let hashes = files::Entity::find()
+            .select_only()
+            .column(files::Column::Hash)
+            .group_by(files::Column::Hash)
+            .having(Expr::expr(files::Column::Id.count()).gt(1))
+            .order_by_desc(files::Column::Id.count())
+            .order_by_asc(files::Column::Hash)
+            .limit(limit)
+            .offset(offset)
+            .into_tuple::<String>()
+            .all(self.db())
+            .await?;
+
+        let mut files_by_hash = files::Entity::find()
+            .filter(files::Column::Hash.is_in(hashes.clone()))
+            .order_by_asc(files::Column::Id)
+            .all(self.db())
+            .await?
+            .into_iter()
+            .fold(
+                HashMap::new(),
+                |mut acc: HashMap<String, Vec<files::Model>>, x| {
+                    acc.entry(x.hash.to_lowercase()).or_default().push(x);
+                    acc
+                },
+            );
+
+        let mut groups = Vec::with_capacity(hashes.len());
+        for hash in hashes {
+            let files = files_by_hash
+                .remove(&hash.to_lowercase())
+                .unwrap_or_default();
+
+            let mut seen = HashSet::new();
+            let mut group_files = Vec::with_capacity(files.len());
+            for file in files {
+                let identity = file_identity(&CONFIG.app.directory_absolute(&file.path)).await;
+                let linked = match identity {
+                    Some(x) => !seen.insert(x),
+                    None => false,
+                };
+
+                group_files.push(DuplicateFile { file, linked });
+            }
+
+            groups.push(DuplicateGroup {
+                hash,
+                files: group_files,
+            });
+        }
+
+        Ok(groups)
+    }
+
+    /// Keep the file with the given ULID and get rid of the other copies with the same hash.
+    ///
+    /// The hashes are only as current as the last scan, so the files are hashed again first and
+    /// copies which changed since are left alone. Fails without touching anything if the file
+    /// to keep changed, or hard links can't be made.
+    #[instrument(skip(self))]
+    pub async fn resolve_duplicates(
+        &self,
+        hash: &str,
+        keep_ulid: &str,
+        resolution: DuplicateResolution,
+    ) -> Result<ResolvedDuplicates> {
+        self.resolve_duplicates_in(&CONFIG.app.directory, hash, keep_ulid, resolution)
+            .await
+    }
+
+    async fn resolve_duplicates_in(
+        &self,
+        directory: &Path,
+        hash: &str,
+        keep_ulid: &str,
+        resolution: DuplicateResolution,
+    ) -> Result<ResolvedDuplicates> {
+        let mut group = files::Entity::find()
+            .filter(files::Column::Hash.eq(hash))
+            .all(self.db())
+            .await?;
+
+        let keep = group
+            .iter()
+            .position(|x| x.ulid.eq_ignore_ascii_case(keep_ulid))
+            .map(|i| group.swap_remove(i))
+            .ok_or_else(|| anyhow!("File {} is not part of the duplicate group", keep_ulid))?;
+        let keep_path = directory.join(&keep.path);
+
+        if !fs::try_exists(&keep_path).await? {
+            bail!("File to keep does not exist: {:?}", keep_path);
+        }
+
+        if !file_hash(&keep_path).await?.eq_ignore_ascii_case(hash) {
+            bail!("File to keep changed since it was indexed: {:?}", keep_path);
+        }
+
+        let mut result = ResolvedDuplicates::default();
+        let mut others = vec![];
+
+        for other in group {
+            let other_path = directory.join(&other.path);
+
+            // Copies that are already gone have nothing left to lose
+            let unchanged = !fs::try_exists(&other_path).await?
+                || file_hash(&other_path)
+                    .await
+                    .is_ok_and(|x| x.eq_ignore_ascii_case(hash));
+
+            if unchanged {
+                others.push((other, other_path));
+            } else {
+                logger::warn!(path = ?other_path, "Duplicate changed since it was indexed, skipping");
+                result.skipped.push(other);
+            }
+        }
+
+        // Links can't cross file systems, find out before replacing anything
+        if resolution == DuplicateResolution::Hardlink {
+            let device = file_identity(&keep_path).await.map(|(device, _)| device);
+
+            for (_, other_path) in &others {
+                let other_device = match other_path.parent() {
+                    Some(x) => file_identity(x).await.map(|(device, _)| device),
+                    None => None,
+                };
+
+                if device != other_device {
+                    bail!(
+                        "Can't link {:?} to {:?} on another file system",
+                        other_path,
+                        keep_path
+                    );
+                }
+            }
+        }
+
+        for (other, other_path) in others {
+            if let Err(e) = resolve_duplicate(&keep_path, &other_path, resolution).await {
+                logger::warn!(err = ?e, path = ?other_path, "Failed to resolve duplicate");
+
+                result.error = Some(e.to_string());
+                break;
+            }
+
+            logger::debug!(path = ?other_path, ?resolution, "Resolved duplicate");
+
+            result.resolved.push(other);
+        }
+
+        if resolution == DuplicateResolution::Delete {
+            self.prune_files(&result.resolved).await?;
+        }
+
+        Ok(result)
+    }
+}
+
+/// Delete the copy, or replace it with a hard link to the file to keep
+async fn resolve_duplicate(
+    keep_path: &Path,
+    other_path: &Path,
+    resolution: DuplicateResolution,
+) -> Result<()> {
+    match resolution {
+        DuplicateResolution::Delete => {
+            if let Err(e) = fs::remove_file(other_path).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    bail!("Failed to delete {:?}: {}", other_path, e);
+                }
+            }
+        }
+        DuplicateResolution::Hardlink => {
+            // Link next to the target first so the target is replaced atomically
+            let mut tmp_path = other_path.to_path_buf().into_os_string();
+            tmp_path.push(".mw-link");
+
+            fs::hard_link(keep_path, &tmp_path)
+                .await
+                .map_err(|e| anyhow!("Failed to link {:?}: {}", other_path, e))?;
+            fs::rename(&tmp_path, other_path)
+                .await
+                .map_err(|e| anyhow!("Failed to replace {:?}: {}", other_path, e))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Something that uniquely identifies the file on disk, so hard links can be detected
+#[cfg(unix)]
+async fn file_identity(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    fs::metadata(path).await.ok().map(|x| (x.dev(), x.ino()))
+}
+
+#[cfg(not(unix))]
+async fn file_identity(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::helpers::test::{file_watcher, insert_file};
+
+    /// A directory with the files and the hash they all had when they were indexed
+    async fn setup(fw: &FileWatcher, names: &[&str]) -> (TempDir, String, Vec<files::Model>) {
+        let dir = TempDir::new().unwrap();
+        let mut files = vec![];
+
+        for name in names {
+            std::fs::write(dir.path().join(name), "same").unwrap();
+        }
+
+        let hash = file_hash(&dir.path().join(names[0])).await.unwrap();
+
+        for name in names {
+            files.push(insert_file(fw, name, &hash).await);
+        }
+
+        (dir, hash, files)
+    }
+
+    fn ids(files: &[files::Model]) -> Vec<i32> {
+        files.iter().map(|x| x.id).collect()
+    }
+
+    #[tokio::test]
+    async fn deletes_copies() {
+        let fw = file_watcher().await;
+        let (dir, hash, files) = setup(&fw, &["a", "b", "c"]).await;
+
+        let result = fw
+            .resolve_duplicates_in(
+                dir.path(),
+                &hash,
+                &files[1].ulid,
+                DuplicateResolution::Delete,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(ids(&result.resolved), [files[0].id, files[2].id]);
+        assert!(result.skipped.is_empty() && result.error.is_none());
+
+        assert!(dir.path().join("b").exists());
+        assert!(!dir.path().join("a").exists());
+        assert!(!dir.path().join("c").exists());
+
+        let left = files::Entity::find().all(fw.db()).await.unwrap();
+        assert_eq!(ids(&left), [files[1].id]);
+    }
+
+    #[tokio::test]
+    async fn skips_copies_changed_since_indexing() {
+        let fw = file_watcher().await;
+        let (dir, hash, files) = setup(&fw, &["a", "b", "c"]).await;
+
+        std::fs::write(dir.path().join("c"), "edited").unwrap();
+
+        let result = fw
+            .resolve_duplicates_in(
+                dir.path(),
+                &hash,
+                &files[0].ulid,
+                DuplicateResolution::Delete,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(ids(&result.resolved), [files[1].id]);
+        assert_eq!(ids(&result.skipped), [files[2].id]);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("c")).unwrap(),
+            "edited"
+        );
+        assert_eq!(files::Entity::find().count(fw.db()).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn refuses_when_the_kept_file_changed() {
+        let fw = file_watcher().await;
+        let (dir, hash, files) = setup(&fw, &["a", "b"]).await;
+
+        std::fs::write(dir.path().join("a"), "edited").unwrap();
+
+        let result = fw
+            .resolve_duplicates_in(
+                dir.path(),
+                &hash,
+                &files[0].ulid,
+                DuplicateResolution::Delete,
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert!(dir.path().join("b").exists());
+        assert_eq!(files::Entity::find().count(fw.db()).await.unwrap(), 2);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn links_copies() {
+        let fw = file_watcher().await;
+        let (dir, hash, files) = setup(&fw, &["a", "b"]).await;
+
+        let result = fw
+            .resolve_duplicates_in(
+                dir.path(),
+                &hash,
+                &files[0].ulid,
+                DuplicateResolution::Hardlink,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(ids(&result.resolved), [files[1].id]);
+        assert_eq!(
+            file_identity(&dir.path().join("a")).await,
+            file_identity(&dir.path().join("b")).await
+        );
+        assert_eq!(files::Entity::find().count(fw.db()).await.unwrap(), 2);
+    }
+}
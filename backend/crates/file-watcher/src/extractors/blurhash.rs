@@ -0,0 +1,30 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use entity::files;
+
+use super::Extractor;
+use crate::{data::FileData, FileWatcher};
+
+pub struct BlurhashExtractor;
+
+#[async_trait]
+impl Extractor for BlurhashExtractor {
+    fn key(&self) -> &'static str {
+        FileData::BLURHASH
+    }
+
+    fn supported_types(&self) -> &'static [&'static str] {
+        &["image/*", "video/*"]
+    }
+
+    // The poster thumb is used when the raw file can't be decoded
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["poster"]
+    }
+
+    async fn extract(&self, fw: &FileWatcher, file: &files::Model) -> Result<()> {
+        fw.get_or_generate_file_blurhash(file).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use config::CONFIG;
+use entity::files;
+
+use super::Extractor;
+use crate::{data::FileData, FileWatcher};
+
+pub struct DescriptionExtractor;
+
+#[async_trait]
+impl Extractor for DescriptionExtractor {
+    fn key(&self) -> &'static str {
+        FileData::DESCRIPTION
+    }
+
+    fn supported_types(&self) -> &'static [&'static str] {
+        &["image/*", "video/*", "audio/*"]
+    }
+
+    async fn extract(&self, fw: &FileWatcher, file: &files::Model) -> Result<()> {
+        let file_type = file.file_type.as_deref().unwrap_or_default();
+        let file_path = CONFIG.app.directory_absolute(&file.path);
+
+        fw.generate_description(file.id, file_type, &file_path)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use config::CONFIG;
+use entity::files;
+
+use super::Extractor;
+use crate::{data::FileData, FileWatcher};
+
+pub struct MediaDimensionsExtractor;
+
+#[async_trait]
+impl Extractor for MediaDimensionsExtractor {
+    fn key(&self) -> &'static str {
+        FileData::MEDIA_DIMENSIONS
+    }
+
+    fn supported_types(&self) -> &'static [&'static str] {
+        &["image/*", "video/*"]
+    }
+
+    async fn extract(&self, fw: &FileWatcher, file: &files::Model) -> Result<()> {
+        let file_type = file.file_type.as_deref().unwrap_or_default();
+        let file_path = CONFIG.app.directory_absolute(&file.path);
+
+        fw.generate_media_dimensions(file.id, file_type, &file_path)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use config::CONFIG;
+use entity::files;
+
+use super::Extractor;
+use crate::{data::FileData, FileWatcher};
+
+pub struct MediaInfoExtractor;
+
+#[async_trait]
+impl Extractor for MediaInfoExtractor {
+    fn key(&self) -> &'static str {
+        FileData::MEDIA_INFO
+    }
+
+    fn supported_types(&self) -> &'static [&'static str] {
+        &["video/*", "audio/*"]
+    }
+
+    // The media dimensions of videos come from the same ffprobe run
+    fn dependencies(&self) -> &'static [&'static str] {
+        &[FileData::MEDIA_DIMENSIONS]
+    }
+
+    async fn extract(&self, fw: &FileWatcher, file: &files::Model) -> Result<()> {
+        let file_path = CONFIG.app.directory_absolute(&file.path);
+
+        fw.generate_media_info(file.id, &file_path).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,284 @@
+use std::{collections::HashSet, sync::Arc};
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use config::CONFIG;
+use entity::{file_data, files};
+use sea_orm::prelude::*;
+use tokio::fs;
+use tracing::instrument;
+
+use crate::{
+    data::FileData,
+    jobs::{Job, JOB_PRIORITY_BULK},
+    FileWatcher,
+};
+
+mod blurhash;
+mod description;
+mod media_dimensions;
+mod media_info;
+mod phash;
+mod sprite;
+mod thumb;
+
+pub use self::{
+    blurhash::BlurhashExtractor, description::DescriptionExtractor,
+    media_dimensions::MediaDimensionsExtractor, media_info::MediaInfoExtractor,
+    phash::PerceptualHashExtractor, sprite::SpriteExtractor, thumb::ThumbExtractor,
+};
+
+/// A step of the indexing pipeline that derives data from a file
+/// and stores it in `file_data`.
+#[async_trait]
+pub trait Extractor: Send + Sync {
+    /// Unique key of the extractor.
+    ///
+    /// Also used as the `file_data` key by [`Extractor::is_extracted`].
+    fn key(&self) -> &'static str;
+
+    /// MIME types the extractor can handle. Supports wildcards like `image/*`.
+    fn supported_types(&self) -> &'static [&'static str];
+
+    /// Keys of extractors that have to run before this one.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// The `file_data` keys the extractor stores its data under.
+    ///
+    /// Rerunning the extractor throws away the rows with these keys.
+    fn data_keys(&self) -> Vec<String> {
+        vec![self.key().to_string()]
+    }
+
+    /// Whether the data for the file was already extracted.
+    async fn is_extracted(&self, fw: &FileWatcher, file: &files::Model) -> Result<bool> {
+        let count = file_data::Entity::find()
+            .filter(file_data::Column::FileId.eq(file.id))
+            .filter(file_data::Column::Key.eq(self.key()))
+            .count(fw.db())
+            .await?;
+
+        Ok(count > 0)
+    }
+
+    /// Extract the data from the file and save it to the database.
+    async fn extract(&self, fw: &FileWatcher, file: &files::Model) -> Result<()>;
+
+    fn supports(&self, file_type: &str) -> bool {
+        self.supported_types()
+            .iter()
+            .any(|x| mime_matches(x, file_type))
+    }
+}
+
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(prefix) => mime
+            .split_once('/')
+            .is_some_and(|(x, _)| x.eq_ignore_ascii_case(prefix)),
+        None => pattern == "*" || pattern.eq_ignore_ascii_case(mime),
+    }
+}
+
+#[must_use]
+pub fn default_extractors() -> Vec<Arc<dyn Extractor>> {
+    vec![
+        Arc::new(MediaDimensionsExtractor),
+        Arc::new(MediaInfoExtractor),
+        Arc::new(ThumbExtractor::poster()),
+        Arc::new(ThumbExtractor::preview()),
+        Arc::new(BlurhashExtractor),
+        Arc::new(PerceptualHashExtractor),
+        Arc::new(SpriteExtractor),
+        Arc::new(DescriptionExtractor),
+    ]
+}
+
+impl FileWatcher {
+    #[must_use]
+    pub fn extractor(&self, key: &str) -> Option<Arc<dyn Extractor>> {
+        self.extractors.iter().find(|x| x.key() == key).cloned()
+    }
+
+    /// Extractors that can handle the given file type
+    #[must_use]
+    pub fn extractors_for(&self, file_type: &str) -> Vec<Arc<dyn Extractor>> {
+        self.extractors
+            .iter()
+            .filter(|x| x.supports(file_type))
+            .cloned()
+            .collect()
+    }
+
+    /// Queue all the extractors that can handle the file.
+    pub(crate) async fn enqueue_extractors(&self, file: &files::Model) -> Result<()> {
+        let file_type = file.file_type.as_deref().unwrap_or_default();
+
+        for extractor in self.extractors_for(file_type) {
+            self.enqueue_job(
+                file.id,
+                &Job::Extract(extractor.key().to_string()),
+                JOB_PRIORITY_BULK,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Run the extractor (and any missing dependencies) on the file.
+    #[instrument(skip(self, file), fields(file = file.path))]
+    pub async fn run_extractor(&self, key: &str, file: &files::Model) -> Result<()> {
+        let file_type = file.file_type.as_deref().unwrap_or_default();
+
+        for extractor in self.resolve_extractor(key)? {
+            if !extractor.supports(file_type) {
+                logger::trace!(
+                    extractor = extractor.key(),
+                    file_type,
+                    "Extractor doesn't support file type"
+                );
+                continue;
+            }
+
+            if extractor.is_extracted(self, file).await? {
+                continue;
+            }
+
+            logger::trace!(extractor = extractor.key(), "Running extractor");
+
+            extractor
+                .extract(self, file)
+                .await
+                .map_err(|e| anyhow!("Extractor {:?} failed: {}", extractor.key(), e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Throw away the data of an extractor and of the extractors depending on it, and queue them
+    /// up again for every file they support.
+    ///
+    /// Useful when the extractor changes. Returns the number of queued files.
+    pub async fn rerun_extractor(&self, key: &str) -> Result<u64> {
+        let extractors = self.dependent_extractors(key)?;
+
+        let keys = extractors
+            .iter()
+            .flat_map(|x| x.data_keys())
+            .collect::<Vec<_>>();
+
+        let stale_files = file_data::Entity::find()
+            .filter(file_data::Column::Key.is_in(&keys))
+            .all(self.db())
+            .await?
+            .into_iter()
+            .filter(|x| FileData::is_file_key(&x.key))
+            .map(|x| CONFIG.app.metadata_directory_absolute(&x.value))
+            .collect::<Vec<_>>();
+
+        file_data::Entity::delete_many()
+            .filter(file_data::Column::Key.is_in(&keys))
+            .exec(self.db())
+            .await?;
+
+        if keys.iter().any(|x| x == FileData::PHASH) {
+            self.reset_phash_index();
+        }
+
+        for path in &stale_files {
+            if let Err(e) = fs::remove_file(path).await {
+                logger::warn!(err = ?e, ?path, "Failed to remove stale file");
+            }
+        }
+
+        let mut count = 0;
+
+        for file in self.get_indexed().await? {
+            let file_type = file.file_type.as_deref().unwrap_or_default();
+            let mut queued = false;
+
+            for extractor in extractors.iter().filter(|x| x.supports(file_type)) {
+                self.enqueue_job(
+                    file.id,
+                    &Job::Extract(extractor.key().to_string()),
+                    JOB_PRIORITY_BULK,
+                )
+                .await?;
+
+                queued = true;
+            }
+
+            count += u64::from(queued);
+        }
+
+        logger::debug!(
+            extractor = key,
+            dependents = ?extractors.iter().skip(1).map(|x| x.key()).collect::<Vec<_>>(),
+            count,
+            "Queued extractor"
+        );
+
+        Ok(count)
+    }
+
+    /// The extractor followed by every extractor depending on it, directly or not.
+    fn dependent_extractors(&self, key: &str) -> Result<Vec<Arc<dyn Extractor>>> {
+        let mut dependents = vec![self
+            .extractor(key)
+            .ok_or_else(|| anyhow!("Unknown extractor: {}", key))?];
+
+        loop {
+            let next = self.extractors.iter().find(|x| {
+                !dependents.iter().any(|y| y.key() == x.key())
+                    && x.dependencies()
+                        .iter()
+                        .any(|dependency| dependents.iter().any(|y| y.key() == *dependency))
+            });
+
+            match next {
+                Some(x) => dependents.push(x.clone()),
+                None => break,
+            }
+        }
+
+        Ok(dependents)
+    }
+
+    /// The extractor with all of its dependencies in the order they should be run.
+    fn resolve_extractor(&self, key: &str) -> Result<Vec<Arc<dyn Extractor>>> {
+        fn visit(
+            fw: &FileWatcher,
+            key: &str,
+            visiting: &mut HashSet<String>,
+            resolved: &mut Vec<Arc<dyn Extractor>>,
+        ) -> Result<()> {
+            if resolved.iter().any(|x| x.key() == key) {
+                return Ok(());
+            }
+
+            if !visiting.insert(key.to_string()) {
+                bail!("Extractor dependency cycle detected at {:?}", key);
+            }
+
+            let extractor = fw
+                .extractor(key)
+                .ok_or_else(|| anyhow!("Unknown extractor: {}", key))?;
+
+            for dependency in extractor.dependencies() {
+                visit(fw, dependency, visiting, resolved)?;
+            }
+
+            resolved.push(extractor);
+
+            Ok(())
+        }
+
+        let mut resolved = Vec::new();
+        visit(self, key, &mut HashSet::new(), &mut resolved)?;
+
+        Ok(resolved)
+    }
+}
@@ -0,0 +1,30 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use entity::files;
+
+use super::Extractor;
+use crate::{data::FileData, FileWatcher};
+
+pub struct PerceptualHashExtractor;
+
+#[async_trait]
+impl Extractor for PerceptualHashExtractor {
+    fn key(&self) -> &'static str {
+        FileData::PHASH
+    }
+
+    fn supported_types(&self) -> &'static [&'static str] {
+        &["image/*", "video/*"]
+    }
+
+    // Videos are hashed from their poster frame
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["poster"]
+    }
+
+    async fn extract(&self, fw: &FileWatcher, file: &files::Model) -> Result<()> {
+        fw.get_or_generate_phash(file).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,31 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use entity::files;
+
+use super::Extractor;
+use crate::{data::FileData, FileWatcher};
+
+pub struct SpriteExtractor;
+
+#[async_trait]
+impl Extractor for SpriteExtractor {
+    fn key(&self) -> &'static str {
+        FileData::SPRITE
+    }
+
+    fn supported_types(&self) -> &'static [&'static str] {
+        &["video/*"]
+    }
+
+    fn data_keys(&self) -> Vec<String> {
+        vec![
+            FileData::SPRITE.to_string(),
+            FileData::SPRITE_TRACK.to_string(),
+        ]
+    }
+
+    // Short videos don't get a sprite, so they're checked again whenever they're indexed
+    async fn extract(&self, fw: &FileWatcher, file: &files::Model) -> Result<()> {
+        fw.generate_sprite(file).await
+    }
+}
@@ -0,0 +1,98 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use entity::{file_data, files};
+use sea_orm::prelude::*;
+
+use super::Extractor;
+use crate::{
+    thumb::{ThumbFormat, ThumbSize},
+    FileWatcher,
+};
+
+pub struct ThumbExtractor {
+    key: &'static str,
+    size: ThumbSize,
+    supported_types: &'static [&'static str],
+}
+
+impl ThumbExtractor {
+    #[must_use]
+    pub fn poster() -> Self {
+        Self {
+            key: "poster",
+            size: ThumbSize::Poster,
+            supported_types: &["image/*", "video/*"],
+        }
+    }
+
+    #[must_use]
+    pub fn preview() -> Self {
+        Self {
+            key: "preview",
+            size: ThumbSize::Preview,
+            supported_types: &["image/gif", "video/*"],
+        }
+    }
+}
+
+#[async_trait]
+impl Extractor for ThumbExtractor {
+    fn key(&self) -> &'static str {
+        self.key
+    }
+
+    fn supported_types(&self) -> &'static [&'static str] {
+        self.supported_types
+    }
+
+    /// The thumb in every format, including the ones made on request
+    fn data_keys(&self) -> Vec<String> {
+        ThumbFormat::ALL.iter().map(|x| self.size.key(*x)).collect()
+    }
+
+    /// Whether the thumb exists in its base format. The others are made on request.
+    async fn is_extracted(&self, fw: &FileWatcher, file: &files::Model) -> Result<bool> {
+        let count = file_data::Entity::find()
+            .filter(file_data::Column::FileId.eq(file.id))
+            .filter(file_data::Column::Key.eq(self.size.key(self.size.base_format())))
+            .count(fw.db())
+            .await?;
+
+        Ok(count > 0)
+    }
+
+    async fn extract(&self, fw: &FileWatcher, file: &files::Model) -> Result<()> {
+        fw.get_or_generate_thumb(&file.ulid, self.size.clone(), self.size.base_format())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thumb::parse_thumb_key;
+
+    #[test]
+    fn data_keys_cover_every_format() {
+        assert_eq!(
+            ThumbExtractor::poster().data_keys(),
+            ["poster.avif", "poster.webp", "poster"]
+        );
+
+        // Previews are only made as WebP, so their rows never use the plain key
+        let preview = ThumbExtractor::preview();
+        assert!(preview
+            .data_keys()
+            .contains(&ThumbSize::Preview.key(ThumbFormat::Webp)));
+        assert_ne!(ThumbSize::Preview.key(ThumbFormat::Webp), preview.key());
+
+        for key in preview.data_keys() {
+            assert!(
+                matches!(parse_thumb_key(&key), Some((ThumbSize::Preview, _))),
+                "{key}"
+            );
+        }
+    }
+}
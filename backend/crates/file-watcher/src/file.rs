@@ -1,21 +1,23 @@
 use std::{
+    collections::HashSet,
+    fs::Metadata,
     path::{Path, PathBuf},
     time::Instant,
 };
 
 use anyhow::{anyhow, bail, Result};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, SubsecRound, Utc};
 use config::CONFIG;
-use entity::files;
+use entity::{file_data, files};
 use file_format::FileFormat;
 use infer::get_from_path as infer_from_path;
-use sea_orm::{prelude::*, Condition, Set, TransactionTrait};
+use sea_orm::{prelude::*, Set, TransactionTrait};
 use tokio::fs;
 use tracing::instrument;
 use tree_magic_mini::from_filepath as magic_infer_from_filepath;
 use ulid::Ulid;
 
-use crate::{helpers::file::file_hash, FileWatcher};
+use crate::{data::FileData, helpers::file::file_hash, FileWatcher};
 
 impl FileWatcher {
     #[instrument(skip(self))]
@@ -30,6 +32,19 @@ impl FileWatcher {
 
         let file_path_rel = CONFIG.app.directory_relative(file_path)?;
 
+        let res = files::Entity::find()
+            .filter(files::Column::Path.eq(&file_path_rel))
+            .one(self.db())
+            .await?;
+
+        if let Some(db_file) = &res {
+            if !has_changed(db_file, &meta) {
+                return Ok(db_file.clone());
+            }
+
+            logger::debug!(file = ?db_file, "File size or mtime changed");
+        }
+
         let file_hash = {
             let now = Instant::now();
             let res = file_hash(file_path).await;
@@ -37,24 +52,42 @@ impl FileWatcher {
             res
         }?;
 
-        let txn = self.db().begin().await?;
+        let file_size: Option<i64> = meta.len().try_into().ok();
+        let file_ctime = meta.created().ok().map(DateTime::<Utc>::from);
+        let file_mtime = meta.modified().ok().map(DateTime::<Utc>::from);
 
-        let res = files::Entity::find()
-            .filter(
-                Condition::all()
-                    .add(files::Column::Path.eq(&file_path_rel))
-                    .add(files::Column::Hash.eq(&file_hash)),
-            )
-            .one(&txn)
-            .await?;
+        let txn = self.db().begin().await?;
+        let mut stale_files = None;
 
         let db_file = match res {
-            Some(db_file) => db_file,
-            None => {
-                let meta = fs::metadata(file_path)
-                    .await
-                    .map_err(|e| anyhow!("Failed to get file metadata: {}", e))?;
+            Some(db_file) if db_file.hash.eq_ignore_ascii_case(&file_hash) => {
+                logger::trace!(file = ?db_file, "File content unchanged");
+
+                let mut db_file: files::ActiveModel = db_file.into();
+                db_file.file_size = Set(file_size);
+                db_file.file_ctime = Set(file_ctime);
+                db_file.file_mtime = Set(file_mtime);
+                db_file.update(&txn).await?
+            }
+            Some(db_file) => {
+                logger::debug!(file = ?db_file, "File content changed");
+
+                let file_type = infer_file_type(file_path.to_path_buf()).await.ok();
 
+                stale_files = Some(
+                    self.invalidate_file_data(&txn, &CONFIG.app.metadata_directory, db_file.id)
+                        .await?,
+                );
+
+                let mut db_file: files::ActiveModel = db_file.into();
+                db_file.hash = Set(file_hash);
+                db_file.file_type = Set(file_type);
+                db_file.file_size = Set(file_size);
+                db_file.file_ctime = Set(file_ctime);
+                db_file.file_mtime = Set(file_mtime);
+                db_file.update(&txn).await?
+            }
+            None => {
                 let file_type = match infer_file_type(file_path.to_path_buf()).await {
                     Ok(x) => Some(x),
                     Err(e) => {
@@ -62,17 +95,6 @@ impl FileWatcher {
                         None
                     }
                 };
-                let file_size: Option<i64> = meta.len().try_into().ok();
-                let file_ctime = meta
-                    .created()
-                    .ok()
-                    .map(DateTime::<Utc>::from)
-                    .map(|x| x.to_rfc3339());
-                let file_mtime = meta
-                    .modified()
-                    .ok()
-                    .map(DateTime::<Utc>::from)
-                    .map(|x| x.to_rfc3339());
 
                 let db_file = files::ActiveModel {
                     path: Set(file_path_rel),
@@ -95,8 +117,98 @@ impl FileWatcher {
 
         txn.commit().await?;
 
+        // Only once the rows pointing to them are gone
+        if let Some(stale_files) = stale_files {
+            self.forget_phashes([db_file.id]);
+
+            for path in stale_files {
+                if let Err(e) = fs::remove_file(&path).await {
+                    logger::warn!(err = ?e, ?path, "Failed to remove stale file");
+                }
+            }
+        }
+
         Ok(db_file)
     }
+
+    pub async fn get_changed<T>(&self, files_in_directory: &T) -> Result<Vec<files::Model>>
+    where
+        T: IntoIterator<Item = PathBuf> + Clone,
+    {
+        let files = files_in_directory
+            .clone()
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        let mut changed_files = Vec::new();
+
+        for db_file in self.get_indexed().await? {
+            let file_path = CONFIG.app.directory_absolute(&db_file.path);
+
+            if !files.contains(&file_path) {
+                continue;
+            }
+
+            let meta = match fs::metadata(&file_path).await {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+
+            if has_changed(&db_file, &meta) {
+                changed_files.push(db_file);
+            }
+        }
+
+        logger::trace!(num_files = changed_files.len(), "found changed files");
+
+        Ok(changed_files)
+    }
+
+    /// Remove all data derived from the file contents (thumbnails, blurhash, dimensions, ...),
+    /// returns the thumbs and sprites to remove from disk once the changes are committed
+    async fn invalidate_file_data<C>(
+        &self,
+        db: &C,
+        metadata_directory: &Path,
+        file_id: i32,
+    ) -> Result<Vec<PathBuf>>
+    where
+        C: ConnectionTrait,
+    {
+        let stale_files = file_data::Entity::find()
+            .filter(file_data::Column::FileId.eq(file_id))
+            .all(db)
+            .await?
+            .into_iter()
+            .filter(|x| FileData::is_file_key(&x.key))
+            .map(|x| metadata_directory.join(&x.value))
+            .collect();
+
+        let res = file_data::Entity::delete_many()
+            .filter(file_data::Column::FileId.eq(file_id))
+            .exec(db)
+            .await?;
+
+        logger::trace!(file_id, count = res.rows_affected, "Invalidated file data");
+
+        Ok(stale_files)
+    }
+}
+
+fn has_changed(db_file: &files::Model, meta: &Metadata) -> bool {
+    let file_size: Option<i64> = meta.len().try_into().ok();
+    if db_file.file_size != file_size {
+        return true;
+    }
+
+    // Postgres and MySQL only keep microseconds
+    let file_mtime = meta
+        .modified()
+        .ok()
+        .map(|x| DateTime::<Utc>::from(x).trunc_subsecs(6));
+    let db_file_mtime = db_file.file_mtime.map(|x| x.trunc_subsecs(6));
+
+    db_file_mtime != file_mtime
 }
 
 async fn infer_file_type(file: PathBuf) -> Result<String> {
@@ -113,3 +225,100 @@ async fn infer_file_type(file: PathBuf) -> Result<String> {
     })
     .await?
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::helpers::test::{file_watcher, insert_file};
+
+    #[test]
+    fn notices_changed_size_and_mtime() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("a");
+        std::fs::write(&path, "hello").unwrap();
+        let meta = std::fs::metadata(&path).unwrap();
+
+        let db_file = files::Model {
+            id: 1,
+            ulid: Ulid::new().to_string(),
+            path: "a".to_string(),
+            hash: String::new(),
+            created_at: Utc::now(),
+            file_type: None,
+            file_size: Some(5),
+            file_ctime: None,
+            file_mtime: meta.modified().ok().map(DateTime::<Utc>::from),
+        };
+        assert!(!has_changed(&db_file, &meta));
+
+        // As read back from a database keeping only microseconds
+        let stored = files::Model {
+            file_mtime: db_file.file_mtime.map(|x| x.trunc_subsecs(6)),
+            ..db_file.clone()
+        };
+        assert!(!has_changed(&stored, &meta));
+
+        let resized = files::Model {
+            file_size: Some(6),
+            ..db_file.clone()
+        };
+        assert!(has_changed(&resized, &meta));
+
+        let touched = files::Model {
+            file_mtime: db_file.file_mtime.map(|x| x - Duration::seconds(1)),
+            ..db_file.clone()
+        };
+        assert!(has_changed(&touched, &meta));
+
+        let unknown = files::Model {
+            file_size: None,
+            ..db_file
+        };
+        assert!(has_changed(&unknown, &meta));
+    }
+
+    #[tokio::test]
+    async fn invalidates_all_data_and_returns_its_files() {
+        let fw = file_watcher().await;
+        let file = insert_file(&fw, "a.mp4", "a").await;
+        let other = insert_file(&fw, "b.mp4", "b").await;
+
+        for (file_id, key, value) in [
+            (file.id, FileData::MEDIA_DIMENSIONS, ""),
+            (file.id, "poster", "thumbs/a.poster.jpeg"),
+            (file.id, FileData::SPRITE, "thumbs/a.sprite.jpg"),
+            (other.id, "poster", "thumbs/b.poster.jpeg"),
+        ] {
+            file_data::ActiveModel {
+                file_id: Set(file_id),
+                key: Set(key.to_string()),
+                value: Set(value.to_string()),
+                ..Default::default()
+            }
+            .insert(fw.db())
+            .await
+            .unwrap();
+        }
+
+        let mut stale = fw
+            .invalidate_file_data(fw.db(), Path::new("/meta"), file.id)
+            .await
+            .unwrap();
+        stale.sort();
+
+        assert_eq!(
+            stale,
+            [
+                PathBuf::from("/meta/thumbs/a.poster.jpeg"),
+                PathBuf::from("/meta/thumbs/a.sprite.jpg"),
+            ]
+        );
+
+        let left = file_data::Entity::find().all(fw.db()).await.unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].file_id, other.id);
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+/// A BK-tree over 64 bit hashes using the Hamming distance as the metric.
+///
+/// Allows finding all hashes within a distance of a query without
+/// comparing it to every stored hash.
+#[derive(Debug)]
+pub struct BkTree<T> {
+    root: Option<BkNode<T>>,
+    len: usize,
+}
+
+#[derive(Debug)]
+struct BkNode<T> {
+    hash: u64,
+    values: Vec<T>,
+    children: HashMap<u32, BkNode<T>>,
+}
+
+impl<T> BkNode<T> {
+    fn new(hash: u64, value: T) -> Self {
+        Self {
+            hash,
+            values: vec![value],
+            children: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Default for BkTree<T> {
+    fn default() -> Self {
+        Self { root: None, len: 0 }
+    }
+}
+
+impl<T> BkTree<T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn insert(&mut self, hash: u64, value: T) {
+        self.len += 1;
+
+        let mut node = match self.root.as_mut() {
+            Some(x) => x,
+            None => {
+                self.root = Some(BkNode::new(hash, value));
+                return;
+            }
+        };
+
+        loop {
+            let distance = hamming_distance(node.hash, hash);
+
+            if distance == 0 {
+                node.values.push(value);
+                return;
+            }
+
+            match node.children.entry(distance) {
+                Entry::Occupied(x) => node = x.into_mut(),
+                Entry::Vacant(x) => {
+                    x.insert(BkNode::new(hash, value));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Remove the value stored under the hash, returns whether it was there.
+    ///
+    /// The node of the hash stays in the tree, even without values, as the way to its children.
+    pub fn remove(&mut self, hash: u64, value: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        let mut node = self.root.as_mut();
+
+        while let Some(x) = node {
+            let distance = hamming_distance(x.hash, hash);
+
+            if distance == 0 {
+                let Some(i) = x.values.iter().position(|y| y == value) else {
+                    return false;
+                };
+
+                x.values.swap_remove(i);
+                self.len -= 1;
+
+                return true;
+            }
+
+            node = x.children.get_mut(&distance);
+        }
+
+        false
+    }
+
+    /// All values with a hash within `max_distance` of `hash`
+    pub fn find(&self, hash: u64, max_distance: u32) -> Vec<(u32, &T)> {
+        let mut found = Vec::new();
+        let mut stack = self.root.iter().collect::<Vec<_>>();
+
+        while let Some(node) = stack.pop() {
+            let distance = hamming_distance(node.hash, hash);
+
+            if distance <= max_distance {
+                found.extend(node.values.iter().map(|x| (distance, x)));
+            }
+
+            let min = distance.saturating_sub(max_distance);
+            let max = distance.saturating_add(max_distance);
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|(d, _)| (min..=max).contains(*d))
+                    .map(|(_, x)| x),
+            );
+        }
+
+        found
+    }
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted<'a>(found: Vec<(u32, &&'a str)>) -> Vec<(u32, &'a str)> {
+        let mut found = found.into_iter().map(|(d, x)| (d, *x)).collect::<Vec<_>>();
+        found.sort_unstable();
+        found
+    }
+
+    #[test]
+    fn finds_hashes_within_distance() {
+        let mut tree = BkTree::default();
+        tree.insert(0b0000, "a");
+        tree.insert(0b0001, "b");
+        tree.insert(0b0011, "c");
+        tree.insert(0b1111, "d");
+        tree.insert(u64::MAX, "e");
+
+        assert_eq!(sorted(tree.find(0b0000, 0)), [(0, "a")]);
+        assert_eq!(sorted(tree.find(0b0000, 2)), [(0, "a"), (1, "b"), (2, "c")]);
+        assert_eq!(sorted(tree.find(0b0111, 1)), [(1, "c"), (1, "d")]);
+        assert_eq!(sorted(tree.find(u64::MAX, 60)), [(0, "e"), (60, "d")]);
+        assert_eq!(tree.find(u64::MAX, 64).len(), 5);
+    }
+
+    #[test]
+    fn keeps_values_with_the_same_hash() {
+        let mut tree = BkTree::default();
+        tree.insert(0b1010, "a");
+        tree.insert(0b1010, "b");
+        tree.insert(0b1011, "c");
+
+        assert_eq!(tree.len(), 3);
+        assert_eq!(sorted(tree.find(0b1010, 0)), [(0, "a"), (0, "b")]);
+    }
+
+    #[test]
+    fn finds_children_of_removed_hashes() {
+        let mut tree = BkTree::default();
+        tree.insert(0b0000, "a");
+        tree.insert(0b0001, "b");
+        tree.insert(0b0011, "c");
+
+        assert!(tree.remove(0b0000, &"a"));
+        assert!(!tree.remove(0b0000, &"a"));
+        assert!(!tree.remove(0b0001, &"c"));
+
+        assert_eq!(tree.len(), 2);
+        assert_eq!(sorted(tree.find(0b0000, 2)), [(1, "b"), (2, "c")]);
+    }
+}
@@ -0,0 +1,114 @@
+use std::{fs, io::BufReader, path::Path};
+
+use anyhow::{anyhow, Result};
+use exif::{Context, Exif, In, Reader, Tag, Value};
+use image::{io::Reader as ImageReader, DynamicImage};
+
+/// Decode the image and turn it upright by its EXIF orientation
+pub fn open_image(path: &Path) -> Result<DynamicImage> {
+    let img = ImageReader::open(path)
+        .map_err(|e| anyhow!("Failed to open file: {}", e.to_string()))?
+        .with_guessed_format()
+        .map_err(|e| anyhow!("Failed to guess file format: {}", e.to_string()))?
+        .decode()
+        .map_err(|e| anyhow!("Failed to decode image: {}", e.to_string()))?;
+
+    Ok(apply_orientation(img, exif_orientation(path)))
+}
+
+/// Windows' `XPTitle`, `XPComment`, `XPKeywords` and `XPSubject` tags, which are UTF-16
+const XP_TAGS: [u16; 4] = [0x9c9b, 0x9c9c, 0x9c9e, 0x9c9f];
+
+fn read_exif(path: &Path) -> Option<Exif> {
+    let mut reader = BufReader::new(fs::File::open(path).ok()?);
+
+    Reader::new().read_from_container(&mut reader).ok()
+}
+
+/// The EXIF orientation of the image, from 1 (upright) to 8. Images without one are upright.
+pub fn exif_orientation(path: &Path) -> u32 {
+    read_exif(path)
+        .and_then(|x| {
+            x.get_field(Tag::Orientation, In::PRIMARY)?
+                .value
+                .get_uint(0)
+        })
+        .filter(|x| (1..=8).contains(x))
+        .unwrap_or(1)
+}
+
+/// The description, comments, title and keywords in the EXIF of the image
+pub fn exif_text(path: &Path) -> Vec<String> {
+    let Some(exif) = read_exif(path) else {
+        return vec![];
+    };
+
+    exif.fields()
+        .filter(|x| x.ifd_num == In::PRIMARY)
+        .filter_map(|field| match (field.tag, &field.value) {
+            (Tag::ImageDescription, Value::Ascii(x)) => Some(
+                x.iter()
+                    .map(|x| String::from_utf8_lossy(x))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            (Tag::UserComment, Value::Undefined(x, _)) => Some(user_comment(x, &exif)),
+            (Tag(Context::Tiff, tag), Value::Byte(x)) if XP_TAGS.contains(&tag) => {
+                Some(utf16(x, true))
+            }
+            _ => None,
+        })
+        .map(|x| {
+            x.trim_matches(|c: char| c == '\0' || c.is_whitespace())
+                .to_string()
+        })
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// `UserComment` starts with 8 bytes naming its character set
+fn user_comment(value: &[u8], exif: &Exif) -> String {
+    if value.len() < 8 {
+        return String::new();
+    }
+
+    let (charset, text) = value.split_at(8);
+
+    match charset {
+        b"UNICODE\0" => utf16(text, exif.little_endian()),
+        _ => String::from_utf8_lossy(text).into_owned(),
+    }
+}
+
+fn utf16(value: &[u8], little_endian: bool) -> String {
+    let units = value
+        .chunks_exact(2)
+        .map(|x| {
+            if little_endian {
+                u16::from_le_bytes([x[0], x[1]])
+            } else {
+                u16::from_be_bytes([x[0], x[1]])
+            }
+        })
+        .collect::<Vec<_>>();
+
+    String::from_utf16_lossy(&units)
+}
+
+/// Whether the image is turned sideways, so its width and height swap when it's turned upright
+pub fn swaps_dimensions(orientation: u32) -> bool {
+    (5..=8).contains(&orientation)
+}
+
+fn apply_orientation(img: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
@@ -1,2 +1,5 @@
-pub mod date;
+pub mod bk_tree;
 pub mod file;
+pub mod image;
+#[cfg(test)]
+pub mod test;
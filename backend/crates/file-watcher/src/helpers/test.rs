@@ -0,0 +1,30 @@
+//! Setup shared by the tests touching the database. `CONFIG` parses the command line, so the
+//! tests stay clear of it and pass directories explicitly.
+
+use chrono::Utc;
+use entity::files;
+use migration::MigratorTrait;
+use sea_orm::{prelude::*, Database, Set};
+use ulid::Ulid;
+
+use crate::FileWatcher;
+
+pub async fn file_watcher() -> FileWatcher {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    migration::Migrator::up(&db, None).await.unwrap();
+    FileWatcher::new(db)
+}
+
+pub async fn insert_file(fw: &FileWatcher, path: &str, hash: &str) -> files::Model {
+    files::ActiveModel {
+        ulid: Set(Ulid::new().to_string()),
+        path: Set(path.to_string()),
+        hash: Set(hash.to_string()),
+        created_at: Set(Utc::now()),
+        file_type: Set(Some("video/mp4".to_string())),
+        ..Default::default()
+    }
+    .insert(fw.db())
+    .await
+    .unwrap()
+}
@@ -1,7 +1,6 @@
 use std::{
     collections::HashSet,
     path::{Path, PathBuf},
-    sync::Arc,
 };
 
 use anyhow::Result;
@@ -9,11 +8,14 @@ use chrono::{DateTime, Utc};
 use config::CONFIG;
 use entity::files;
 use futures::StreamExt;
-use sea_orm::prelude::*;
+use sea_orm::{prelude::*, Condition};
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
-use crate::{thumb::ThumbSize, FileWatcher};
+use crate::{
+    jobs::{Job, JOB_PRIORITY_BULK},
+    FileWatcher,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileMetadata {
@@ -72,47 +74,75 @@ impl FileWatcher {
         Ok(unindexed_files)
     }
 
-    pub async fn prune_indexed<'a, T>(&self, files_in_directory: &T) -> Result<Arc<Vec<String>>>
+    pub async fn get_removed<T>(&self, files_in_directory: &T) -> Result<Vec<files::Model>>
     where
         T: IntoIterator<Item = PathBuf> + Clone,
     {
-        logger::trace!("pruning moved/deleted inspected files");
-        let db_files = self.get_indexed_paths().await?;
-        logger::trace!(
-            num_files = db_files.len(),
-            "found indexed files in database"
-        );
-        let db_files = db_files.into_iter().collect::<HashSet<_>>();
-        logger::trace!(num_files = db_files.len(), "of those are unique");
-
         let files = files_in_directory
             .clone()
             .into_iter()
             .collect::<HashSet<_>>();
 
-        let removed_files = db_files
-            .difference(&files)
+        let removed_files = self
+            .get_indexed()
+            .await?
+            .into_iter()
+            .filter(|x| !files.contains(&CONFIG.app.directory_absolute(&x.path)))
+            .collect::<Vec<_>>();
+        logger::trace!(num_files = removed_files.len(), "found removed files");
+
+        Ok(removed_files)
+    }
+
+    pub async fn get_indexed_under(&self, paths: &[PathBuf]) -> Result<Vec<files::Model>> {
+        let paths = paths
+            .iter()
             .map(|x| CONFIG.app.directory_relative(x))
             .collect::<Result<Vec<_>>>()?;
-        logger::trace!(num_files = removed_files.len(), "found files to remove");
-        let removed_files = Arc::new(removed_files);
 
-        if removed_files.is_empty() {
-            return Ok(removed_files);
+        if paths.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let condition = paths.iter().fold(Condition::any(), |acc, x| {
+            acc.add(files::Column::Path.eq(x))
+                .add(files::Column::Path.starts_with(format!("{x}/")))
+        });
+
+        // LIKE treats `%` and `_` in the paths as wildcards (and ignores case in SQLite), so the
+        // matches are narrowed down to the files actually under the paths
+        let files = files::Entity::find()
+            .filter(condition)
+            .all(self.db())
+            .await?
+            .into_iter()
+            .filter(|file| paths.iter().any(|x| Path::new(&file.path).starts_with(x)))
+            .collect();
+
+        Ok(files)
+    }
+
+    pub async fn prune_files(&self, files: &[files::Model]) -> Result<u64> {
+        logger::trace!("pruning moved/deleted inspected files");
+
+        if files.is_empty() {
+            return Ok(0);
         }
 
         let res = files::Entity::delete_many()
-            .filter(files::Column::Path.is_in(removed_files.iter()))
+            .filter(files::Column::Id.is_in(files.iter().map(|x| x.id)))
             .exec(self.db())
             .await?;
 
+        self.forget_phashes(files.iter().map(|x| x.id));
+
         logger::trace!(
             count = res.rows_affected,
-            files = ?removed_files,
+            files = ?files.iter().map(|x| &x.path).collect::<Vec<_>>(),
             "Removed files",
         );
 
-        Ok(removed_files)
+        Ok(res.rows_affected)
     }
 
     #[instrument(skip(self))]
@@ -121,33 +151,7 @@ impl FileWatcher {
 
         let file = self.get_or_create_file(file_path).await?;
 
-        self.get_or_generate_media_dimensions(&file.ulid).await?;
-
-        if self
-            .generate_blurhash(file_path.to_path_buf(), file.id)
-            .await
-            .is_err()
-        {
-            logger::debug!("Failed to generate blurhash from raw file. Generating from thumb");
-
-            let thumb = self
-                .get_or_generate_thumb(&file.ulid, ThumbSize::Poster)
-                .await;
-
-            match thumb {
-                Ok(thumb) => {
-                    let thumb_path = CONFIG
-                        .app
-                        .metadata_directory_absolute(&thumb.path.to_string_lossy());
-                    if let Err(e) = self.generate_blurhash(thumb_path, file.id).await {
-                        logger::warn!(err = ?e, ?thumb, "failed to generate blurhash");
-                    }
-                }
-                Err(e) => {
-                    logger::warn!(err = ?e, ?file, "failed to generate thumb");
-                }
-            }
-        };
+        self.enqueue_extractors(&file).await?;
 
         Ok(file_path.to_path_buf())
     }
@@ -156,14 +160,29 @@ impl FileWatcher {
         let files = self.scan_directory().await;
         logger::trace!(num_files = files.len(), "scanned directory");
 
-        let old_files = self.prune_indexed(&files).await?;
-        logger::trace!(num_old_files = old_files.len(), "pruned old files");
-
+        let removed_files = self.get_removed(&files).await?;
         let new_files = self.get_unindexed(&files).await?;
         logger::trace!(num_new_files = new_files.len(), "found new files");
 
-        logger::trace!("starting file inspection");
-        let res = new_files
+        let (old_files, new_files) = self.apply_moves(removed_files, new_files).await?;
+
+        let pruned = self.prune_files(&old_files).await?;
+        logger::trace!(num_old_files = pruned, "pruned old files");
+
+        let changed_files = self.get_changed(&files).await?;
+        for file in &changed_files {
+            self.enqueue_job(file.id, &Job::Hash, JOB_PRIORITY_BULK)
+                .await?;
+        }
+
+        let inspected = self.index_paths(&new_files).await;
+
+        Ok(inspected)
+    }
+
+    pub async fn index_paths(&self, paths: &[PathBuf]) -> Vec<PathBuf> {
+        logger::trace!(num_files = paths.len(), "starting file inspection");
+        let res = paths
             .iter()
             .map(|x| async move { (x.clone(), self.index_file(x).await) });
         let mut buff = tokio_stream::iter(res)
@@ -181,6 +200,6 @@ impl FileWatcher {
         }
         logger::trace!(num_inspected = inspected.len(), "finished inspecting files");
 
-        Ok(inspected)
+        inspected
     }
 }
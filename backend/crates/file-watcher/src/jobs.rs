@@ -0,0 +1,560 @@
+use std::{fmt::Display, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::Utc;
+use config::CONFIG;
+use entity::{files, jobs};
+use futures::future;
+use sea_orm::{
+    prelude::*,
+    sea_query::{Expr, Query},
+    QueryOrder, QuerySelect, Set,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::broadcast, time};
+use tracing::instrument;
+
+use crate::{
+    data::FileData,
+    thumb::{ThumbFormat, ThumbSize},
+    FileWatcher,
+};
+
+/// How many times a job is tried before it is moved to the dead-letter state.
+pub const JOB_MAX_ATTEMPTS: i32 = 5;
+/// Delay before the first retry. Doubles with every failed attempt.
+pub const JOB_BACKOFF_BASE: Duration = Duration::from_secs(5);
+/// How often idle workers check for jobs whose backoff has expired.
+pub const JOB_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+pub const JOB_PRIORITY_BULK: i32 = 0;
+pub const JOB_PRIORITY_ON_DEMAND: i32 = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Job {
+    Hash,
+    Extract(String),
+    Thumb(ThumbSize, ThumbFormat),
+}
+
+impl Job {
+    #[must_use]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Hash => "hash",
+            Self::Extract(_) => "extract",
+            Self::Thumb(..) => "thumb",
+        }
+    }
+
+    pub fn payload(&self) -> Result<String> {
+        match self {
+            Self::Extract(key) => serde_json::to_string(key).map_err(Into::into),
+            Self::Thumb(size, format) => serde_json::to_string(&(size, format)).map_err(Into::into),
+            Self::Hash => Ok("{}".to_string()),
+        }
+    }
+}
+
+impl TryFrom<&jobs::Model> for Job {
+    type Error = anyhow::Error;
+
+    fn try_from(job: &jobs::Model) -> Result<Self, Self::Error> {
+        let parsed = match job.kind.as_str() {
+            "hash" => Self::Hash,
+            "extract" => Self::Extract(serde_json::from_str(&job.payload)?),
+            // Kinds queued before extractors were introduced
+            "probe" => Self::Extract(FileData::MEDIA_DIMENSIONS.to_string()),
+            "blurhash" => Self::Extract(FileData::BLURHASH.to_string()),
+            "thumb" => {
+                // Thumbs queued before other formats were supported only have the size
+                let (size, format) = serde_json::from_str(&job.payload).or_else(|_| {
+                    serde_json::from_str(&job.payload).map(|x| (x, ThumbFormat::Jpeg))
+                })?;
+
+                Self::Thumb(size, format)
+            }
+            kind => bail!("Unknown job kind: {}", kind),
+        };
+
+        Ok(parsed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Dead,
+}
+
+impl JobStatus {
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Running => "running",
+            Self::Dead => "dead",
+        }
+    }
+}
+
+impl Display for JobStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JobEvent {
+    pub id: i32,
+    pub error: Option<String>,
+}
+
+impl FileWatcher {
+    /// Add a job to the queue.
+    ///
+    /// If the same job is already queued for the file, its priority is raised
+    /// instead of adding a duplicate. Returns the id of the queued job.
+    #[instrument(skip(self))]
+    pub async fn enqueue_job(&self, file_id: i32, job: &Job, priority: i32) -> Result<i32> {
+        let payload = job.payload()?;
+
+        let existing = jobs::Entity::find()
+            .filter(jobs::Column::FileId.eq(file_id))
+            .filter(jobs::Column::Kind.eq(job.kind()))
+            .filter(jobs::Column::Payload.eq(&payload))
+            .filter(
+                jobs::Column::Status
+                    .is_in([JobStatus::Pending.as_str(), JobStatus::Running.as_str()]),
+            )
+            .one(self.db())
+            .await?;
+
+        let id = match existing {
+            Some(existing) => {
+                if existing.status == JobStatus::Pending.as_str() && existing.priority < priority {
+                    let mut model: jobs::ActiveModel = existing.clone().into();
+                    model.priority = Set(priority);
+                    model.run_at = Set(Utc::now());
+                    model.update(self.db()).await?;
+
+                    logger::trace!(id = existing.id, priority, "Raised job priority");
+                }
+
+                existing.id
+            }
+            None => {
+                let model = jobs::ActiveModel {
+                    file_id: Set(file_id),
+                    kind: Set(job.kind().to_string()),
+                    payload: Set(payload),
+                    status: Set(JobStatus::Pending.to_string()),
+                    priority: Set(priority),
+                    run_at: Set(Utc::now()),
+                    ..Default::default()
+                }
+                .insert(self.db())
+                .await?;
+
+                logger::trace!(job = ?model, "Job enqueued");
+
+                model.id
+            }
+        };
+
+        self.job_notify.notify_one();
+
+        Ok(id)
+    }
+
+    /// Put jobs that were running when the app stopped back into the queue.
+    pub async fn resume_jobs(&self) -> Result<u64> {
+        let res = jobs::Entity::update_many()
+            .col_expr(
+                jobs::Column::Status,
+                Expr::value(JobStatus::Pending.as_str()),
+            )
+            .filter(jobs::Column::Status.eq(JobStatus::Running.as_str()))
+            .exec(self.db())
+            .await?;
+
+        logger::debug!(count = res.rows_affected, "Resumed interrupted jobs");
+
+        Ok(res.rows_affected)
+    }
+
+    /// Put dead jobs back into the queue with a fresh set of attempts.
+    pub async fn retry_dead_jobs(&self) -> Result<u64> {
+        let res = jobs::Entity::update_many()
+            .col_expr(
+                jobs::Column::Status,
+                Expr::value(JobStatus::Pending.as_str()),
+            )
+            .col_expr(jobs::Column::Attempts, Expr::value(0))
+            .col_expr(jobs::Column::RunAt, Expr::value(Utc::now()))
+            .filter(jobs::Column::Status.eq(JobStatus::Dead.as_str()))
+            .exec(self.db())
+            .await?;
+
+        logger::debug!(count = res.rows_affected, "Retrying dead jobs");
+
+        self.job_notify.notify_waiters();
+
+        Ok(res.rows_affected)
+    }
+
+    /// Subscribe to job completion events.
+    ///
+    /// Subscribe before enqueueing a job to not miss its event.
+    #[must_use]
+    pub fn subscribe_jobs(&self) -> broadcast::Receiver<JobEvent> {
+        self.job_events.subscribe()
+    }
+
+    /// Wait until the job with the given id either succeeds or fails.
+    pub async fn wait_for_job(
+        &self,
+        mut events: broadcast::Receiver<JobEvent>,
+        id: i32,
+    ) -> Result<()> {
+        loop {
+            let event = match events.recv().await {
+                Ok(x) => x,
+                // The event of the job may have been among the missed ones
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    match jobs::Entity::find_by_id(id).one(self.db()).await? {
+                        None => return Ok(()),
+                        Some(job) if job.status == JobStatus::Dead.as_str() => {
+                            bail!("Job failed: {}", job.last_error.unwrap_or_default())
+                        }
+                        Some(_) => continue,
+                    }
+                }
+                Err(e) => bail!("Job event channel closed: {}", e),
+            };
+
+            if event.id != id {
+                continue;
+            }
+
+            return match event.error {
+                Some(e) => Err(anyhow!("Job failed: {}", e)),
+                None => Ok(()),
+            };
+        }
+    }
+
+    /// Run the job workers. Never returns.
+    pub async fn run_job_workers(&self) {
+        let workers = CONFIG.app.job_workers.unwrap_or_else(num_cpus::get).max(1);
+
+        logger::debug!(workers, "Starting job workers");
+
+        future::join_all((0..workers).map(|worker| self.job_worker(worker))).await;
+    }
+
+    #[instrument(skip(self))]
+    async fn job_worker(&self, worker: usize) {
+        loop {
+            match self.claim_job().await {
+                Ok(Some(job)) => {
+                    let res = self.run_job(&job).await;
+
+                    if let Err(e) = self.finish_job(job, res).await {
+                        logger::error!(err = ?e, "Failed to finish job");
+                    }
+                }
+                Ok(None) => {
+                    let _ = time::timeout(JOB_POLL_INTERVAL, self.job_notify.notified()).await;
+                }
+                Err(e) => {
+                    logger::error!(err = ?e, "Failed to claim job");
+                    time::sleep(JOB_POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    /// Claim the next job that is due. Jobs of a file run one at a time, as they share the data
+    /// they depend on (eg. the poster thumb of blurhash and phash) and would both make it.
+    async fn claim_job(&self) -> Result<Option<jobs::Model>> {
+        // Claims are made one at a time, so two jobs of a file can't both see it as idle
+        let _claiming = self.job_claim.lock().await;
+
+        let running_files = Query::select()
+            .column(jobs::Column::FileId)
+            .from(jobs::Entity)
+            .and_where(jobs::Column::Status.eq(JobStatus::Running.as_str()))
+            .to_owned();
+
+        loop {
+            let job = jobs::Entity::find()
+                .filter(jobs::Column::Status.eq(JobStatus::Pending.as_str()))
+                .filter(jobs::Column::RunAt.lte(Utc::now()))
+                .filter(jobs::Column::FileId.not_in_subquery(running_files.clone()))
+                .order_by_desc(jobs::Column::Priority)
+                .order_by_asc(jobs::Column::Id)
+                .limit(1)
+                .one(self.db())
+                .await?;
+
+            let job = match job {
+                Some(x) => x,
+                None => return Ok(None),
+            };
+
+            // Another worker might have claimed the job in the meantime
+            let res = jobs::Entity::update_many()
+                .col_expr(
+                    jobs::Column::Status,
+                    Expr::value(JobStatus::Running.as_str()),
+                )
+                .col_expr(
+                    jobs::Column::Attempts,
+                    Expr::col(jobs::Column::Attempts).add(1),
+                )
+                .filter(jobs::Column::Id.eq(job.id))
+                .filter(jobs::Column::Status.eq(JobStatus::Pending.as_str()))
+                .exec(self.db())
+                .await?;
+
+            if res.rows_affected == 1 {
+                return Ok(Some(jobs::Model {
+                    status: JobStatus::Running.to_string(),
+                    attempts: job.attempts + 1,
+                    ..job
+                }));
+            }
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn run_job(&self, job: &jobs::Model) -> Result<()> {
+        let kind = Job::try_from(job)?;
+
+        let db_file = files::Entity::find_by_id(job.file_id)
+            .one(self.db())
+            .await?
+            .ok_or_else(|| anyhow!("File not found: {}", job.file_id))?;
+
+        logger::trace!(?kind, file = ?db_file, "Running job");
+
+        match kind {
+            Job::Hash => {
+                self.index_file(&CONFIG.app.directory_absolute(&db_file.path))
+                    .await?;
+            }
+            Job::Extract(key) => {
+                self.run_extractor(&key, &db_file).await?;
+            }
+            Job::Thumb(size, format) => {
+                self.get_or_generate_thumb(&db_file.ulid, size, format)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn finish_job(&self, job: jobs::Model, res: Result<()>) -> Result<()> {
+        // Jobs of the file can run again, see `claim_job`
+        self.job_notify.notify_one();
+
+        let error = match res {
+            Ok(()) => {
+                jobs::Entity::delete_by_id(job.id).exec(self.db()).await?;
+
+                logger::trace!(id = job.id, kind = job.kind, "Job finished");
+
+                let _ = self.job_events.send(JobEvent {
+                    id: job.id,
+                    error: None,
+                });
+
+                return Ok(());
+            }
+            Err(e) => e.to_string(),
+        };
+
+        let mut model: jobs::ActiveModel = job.clone().into();
+        model.last_error = Set(Some(error.clone()));
+
+        if job.attempts >= JOB_MAX_ATTEMPTS {
+            logger::warn!(?job, err = error, "Job failed too many times, giving up");
+
+            model.status = Set(JobStatus::Dead.to_string());
+        } else {
+            let backoff =
+                JOB_BACKOFF_BASE * 2_u32.pow(job.attempts.saturating_sub(1).unsigned_abs());
+            let run_at = Utc::now() + chrono::Duration::from_std(backoff)?;
+
+            logger::debug!(?job, err = error, ?backoff, "Job failed, retrying later");
+
+            model.status = Set(JobStatus::Pending.to_string());
+            model.run_at = Set(run_at);
+        }
+
+        model.update(self.db()).await?;
+
+        let _ = self.job_events.send(JobEvent {
+            id: job.id,
+            error: Some(error),
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use migration::{Migrator, MigratorTrait};
+
+    use super::*;
+    use crate::helpers::test::{file_watcher, insert_file};
+
+    #[tokio::test]
+    async fn runs_the_jobs_of_a_file_one_at_a_time() {
+        let fw = file_watcher().await;
+        let a = insert_file(&fw, "a.mp4", "a").await;
+        let b = insert_file(&fw, "b.mp4", "b").await;
+
+        let poster = Job::Thumb(ThumbSize::Poster, ThumbFormat::Jpeg);
+        let blurhash = Job::Extract(FileData::BLURHASH.to_string());
+
+        let first = fw
+            .enqueue_job(a.id, &poster, JOB_PRIORITY_BULK)
+            .await
+            .unwrap();
+        let second = fw
+            .enqueue_job(a.id, &blurhash, JOB_PRIORITY_BULK)
+            .await
+            .unwrap();
+        let other = fw
+            .enqueue_job(b.id, &blurhash, JOB_PRIORITY_BULK)
+            .await
+            .unwrap();
+
+        let claimed = fw.claim_job().await.unwrap().unwrap();
+        assert_eq!(claimed.id, first);
+
+        // The next job of the file waits for the running one, those of other files don't
+        assert_eq!(fw.claim_job().await.unwrap().map(|x| x.id), Some(other));
+        assert!(fw.claim_job().await.unwrap().is_none());
+
+        fw.finish_job(claimed, Ok(())).await.unwrap();
+        assert_eq!(fw.claim_job().await.unwrap().map(|x| x.id), Some(second));
+    }
+
+    #[tokio::test]
+    async fn retries_failed_jobs_until_they_are_dead() {
+        let fw = file_watcher().await;
+        let file = insert_file(&fw, "a.mp4", "a").await;
+        let id = fw
+            .enqueue_job(file.id, &Job::Hash, JOB_PRIORITY_BULK)
+            .await
+            .unwrap();
+
+        let job = fw.claim_job().await.unwrap().unwrap();
+        assert_eq!(job.attempts, 1);
+        fw.finish_job(job, Err(anyhow!("broken"))).await.unwrap();
+
+        let job = jobs::Entity::find_by_id(id)
+            .one(fw.db())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.status, JobStatus::Pending.as_str());
+        assert_eq!(job.last_error.as_deref(), Some("broken"));
+        assert!(job.run_at > Utc::now() + JOB_BACKOFF_BASE / 2);
+
+        // Backed off until then
+        assert!(fw.claim_job().await.unwrap().is_none());
+
+        let mut model: jobs::ActiveModel = job.into();
+        model.attempts = Set(JOB_MAX_ATTEMPTS);
+        let job = model.update(fw.db()).await.unwrap();
+        fw.finish_job(job, Err(anyhow!("still broken")))
+            .await
+            .unwrap();
+
+        let job = jobs::Entity::find_by_id(id)
+            .one(fw.db())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.status, JobStatus::Dead.as_str());
+        assert_eq!(job.last_error.as_deref(), Some("still broken"));
+    }
+
+    #[tokio::test]
+    async fn waits_for_jobs_whose_event_was_missed() {
+        let fw = file_watcher().await;
+        let file = insert_file(&fw, "a.mp4", "a").await;
+        let id = fw
+            .enqueue_job(file.id, &Job::Hash, JOB_PRIORITY_BULK)
+            .await
+            .unwrap();
+
+        let lag = |fw: &FileWatcher| {
+            // More than the channel holds
+            for _ in 0..=128 {
+                let _ = fw.job_events.send(JobEvent { id: 0, error: None });
+            }
+        };
+
+        // Dead
+        let events = fw.subscribe_jobs();
+        let job = fw.claim_job().await.unwrap().unwrap();
+        let mut model: jobs::ActiveModel = job.into();
+        model.attempts = Set(JOB_MAX_ATTEMPTS);
+        let job = model.update(fw.db()).await.unwrap();
+        fw.finish_job(job, Err(anyhow!("broken"))).await.unwrap();
+        lag(&fw);
+
+        let err = fw.wait_for_job(events, id).await.unwrap_err();
+        assert_eq!(err.to_string(), "Job failed: broken");
+
+        // Done
+        let events = fw.subscribe_jobs();
+        jobs::Entity::delete_by_id(id).exec(fw.db()).await.unwrap();
+        lag(&fw);
+
+        fw.wait_for_job(events, id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reads_the_jobs_queued_by_migrations() {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+
+        // Up to the one queueing the extractors of every image and video again
+        let reorient = Migrator::migrations()
+            .iter()
+            .position(|x| x.name() == "m20231209_090000_reorient_media")
+            .unwrap();
+        Migrator::up(&db, Some(u32::try_from(reorient).unwrap()))
+            .await
+            .unwrap();
+
+        let fw = FileWatcher::new(db);
+        let file = insert_file(&fw, "a.mp4", "a").await;
+        Migrator::up(fw.db(), None).await.unwrap();
+
+        let queued = jobs::Entity::find().all(fw.db()).await.unwrap();
+        assert!(!queued.is_empty());
+
+        for job in queued {
+            let parsed = Job::try_from(&job).unwrap();
+
+            assert!(
+                matches!(&parsed, Job::Extract(key) if fw.extractor(key).is_some()),
+                "{parsed:?}"
+            );
+            assert_eq!(job.priority, JOB_PRIORITY_BULK);
+
+            // The same as if it was queued now
+            let id = fw.enqueue_job(file.id, &parsed, JOB_PRIORITY_BULK).await;
+            assert_eq!(id.unwrap(), job.id);
+        }
+    }
+}
@@ -1,18 +1,47 @@
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
 use sea_orm::prelude::*;
+use tokio::sync::{broadcast, Mutex, Notify};
+
+use crate::{
+    extractors::{default_extractors, Extractor},
+    jobs::JobEvent,
+    phash::PhashIndex,
+};
 
 pub mod blurhash;
+pub mod data;
+pub mod description;
+pub mod duplicates;
+pub mod extractors;
 pub mod file;
 mod helpers;
 pub mod index;
+pub mod jobs;
 pub mod media_dimensions;
+pub mod media_info;
+pub mod moves;
+pub mod phash;
+pub mod poster;
+pub mod preview;
 pub mod scan;
+pub mod sprite;
+pub mod tags;
 pub mod thumb;
+pub mod thumb_maintenance;
+pub mod watch;
 
 pub struct FileWatcher {
     db: Arc<DatabaseConnection>,
     recursive: bool,
+    extractors: Vec<Arc<dyn Extractor>>,
+    job_notify: Notify,
+    /// Held while a worker claims a job
+    job_claim: Mutex<()>,
+    job_events: broadcast::Sender<JobEvent>,
+    phash_index: RwLock<Option<PhashIndex>>,
+    /// Held while the thumbs are maintained, so only one maintenance runs at a time
+    thumb_maintenance: Mutex<()>,
 }
 
 impl FileWatcher {
@@ -23,6 +52,12 @@ impl FileWatcher {
         Self {
             db: db.into(),
             recursive: true,
+            extractors: default_extractors(),
+            job_notify: Notify::new(),
+            job_claim: Mutex::new(()),
+            job_events: broadcast::channel(128).0,
+            phash_index: RwLock::new(None),
+            thumb_maintenance: Mutex::new(()),
         }
     }
 
@@ -37,7 +72,29 @@ impl FileWatcher {
         self
     }
 
-    fn db(&self) -> &DatabaseConnection {
+    /// Register an additional extractor to run when indexing files.
+    ///
+    /// Replaces any registered extractor with the same key.
+    pub fn register_extractor<T>(&mut self, extractor: T) -> &Self
+    where
+        T: Extractor + 'static,
+    {
+        self.extractors.retain(|x| x.key() != extractor.key());
+        self.extractors.push(Arc::new(extractor));
+        self
+    }
+
+    #[must_use]
+    pub fn with_extractor<T>(mut self, extractor: T) -> Self
+    where
+        T: Extractor + 'static,
+    {
+        self.register_extractor(extractor);
+        self
+    }
+
+    #[must_use]
+    pub fn db(&self) -> &DatabaseConnection {
         &self.db as &DatabaseConnection
     }
 }
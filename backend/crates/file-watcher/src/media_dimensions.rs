@@ -1,55 +1,17 @@
 use std::{convert::Into, path::Path};
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, Result};
 use config::CONFIG;
-use entity::{file_data, files};
-use sea_orm::{prelude::*, Set};
-use serde::{Deserialize, Serialize};
+use entity::files;
+use sea_orm::prelude::*;
+use tokio::task;
 use tracing::instrument;
 
-use crate::FileWatcher;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MediaDimensions {
-    pub width: u32,
-    pub height: u32,
-}
-
-impl From<(u32, u32)> for MediaDimensions {
-    fn from((width, height): (u32, u32)) -> Self {
-        Self { width, height }
-    }
-}
-
-impl From<(i64, i64)> for MediaDimensions {
-    #[allow(clippy::cast_possible_truncation)]
-    #[allow(clippy::cast_sign_loss)]
-    fn from((width, height): (i64, i64)) -> Self {
-        Self {
-            width: width as u32,
-            height: height as u32,
-        }
-    }
-}
-
-impl TryFrom<file_data::Model> for MediaDimensions {
-    type Error = anyhow::Error;
-
-    fn try_from(data: file_data::Model) -> Result<Self, Self::Error> {
-        if data.key != FILE_DATA_MEDIA_DIMENSIONS_KEY {
-            bail!("Invalid file data key: {}", data.key);
-        }
-
-        serde_json::from_str(&data.meta).map_err(|e| {
-            anyhow!(
-                "Failed to deserialize media dimensions from file data: {}",
-                e
-            )
-        })
-    }
-}
-
-pub const FILE_DATA_MEDIA_DIMENSIONS_KEY: &str = "media-dimensions";
+use crate::{
+    data::{FileData, MediaDimensions},
+    helpers::image::{exif_orientation, swaps_dimensions},
+    FileWatcher,
+};
 
 impl FileWatcher {
     #[instrument(skip(self))]
@@ -63,14 +25,10 @@ impl FileWatcher {
             .await?
             .ok_or_else(|| anyhow!("Could not find file with ulid: {}", ulid))?;
 
-        let db_file_data = file_data::Entity::find()
-            .filter(file_data::Column::Key.eq(FILE_DATA_MEDIA_DIMENSIONS_KEY))
-            .filter(file_data::Column::FileId.eq(db_file.id))
-            .one(self.db())
-            .await?;
-
-        if let Some(db_file_data) = db_file_data {
-            let dims = db_file_data.try_into()?;
+        if let Some(FileData::MediaDimensions(dims)) = self
+            .get_file_data(db_file.id, FileData::MEDIA_DIMENSIONS)
+            .await?
+        {
             return Ok(Some(dims));
         }
 
@@ -99,17 +57,30 @@ impl FileWatcher {
                 )
             })?;
 
-        let streams = match ffprobe_info.streams {
-            Some(x) => x,
-            None => {
-                return Ok(None);
-            }
+        // The same ffprobe run has everything else about videos
+        if file_type.starts_with("video/") {
+            self.save_media_info(file_id, &ffprobe_info).await?;
+        }
+
+        let Some(stream) = ffprobe_info.video_stream() else {
+            return Ok(None);
         };
 
-        let dims = streams.iter().find_map(|x| match (x.width, x.height) {
-            (Some(w), Some(h)) => Some((w, h)),
-            _ => None,
-        });
+        let dims = if file_type.starts_with("image/") {
+            // Images are turned by their EXIF orientation, which ffprobe doesn't apply
+            let orientation = {
+                let file_path = file_path.to_path_buf();
+                task::spawn_blocking(move || exif_orientation(&file_path)).await?
+            };
+
+            match (stream.width, stream.height) {
+                (Some(w), Some(h)) if swaps_dimensions(orientation) => Some((h, w)),
+                (Some(w), Some(h)) => Some((w, h)),
+                _ => None,
+            }
+        } else {
+            stream.display_dimensions()
+        };
 
         logger::trace!(?dims, "Got media dimensions from ffprobe");
 
@@ -120,17 +91,8 @@ impl FileWatcher {
             }
         };
 
-        let file_data_model = file_data::ActiveModel {
-            file_id: Set(file_id),
-            key: Set(FILE_DATA_MEDIA_DIMENSIONS_KEY.to_string()),
-            value: Set(String::new()),
-            meta: Set(serde_json::to_string(&dims)?),
-            ..Default::default()
-        };
-
-        let file_data_model = file_data_model.insert(self.db()).await?;
-
-        logger::trace!(data = ?file_data_model, "Inserted media dimensions into file data");
+        self.add_file_data(file_id, FileData::MediaDimensions(dims.clone()))
+            .await?;
 
         Ok(Some(dims))
     }
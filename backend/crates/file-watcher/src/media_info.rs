@@ -0,0 +1,89 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use ffmpeg::ffprobe::{FfProbeResult, Stream};
+use tracing::instrument;
+
+use crate::{
+    data::{FileData, MediaInfo},
+    FileWatcher,
+};
+
+impl FileWatcher {
+    /// Run ffprobe on the file and save what it found
+    #[instrument(skip(self))]
+    pub(crate) async fn generate_media_info(
+        &self,
+        file_id: i32,
+        file_path: &Path,
+    ) -> Result<MediaInfo> {
+        let ffprobe_info = ffmpeg::ffprobe::ConfigBuilder::new()
+            .with_streams(true)
+            .run(file_path)
+            .await
+            .map_err(|e| anyhow!("Failed to run ffprobe to get media info: {}", e.to_string()))?;
+
+        self.save_media_info(file_id, &ffprobe_info).await
+    }
+
+    /// Save the media info from the ffprobe output, replacing any there is
+    pub(crate) async fn save_media_info(
+        &self,
+        file_id: i32,
+        ffprobe_info: &FfProbeResult,
+    ) -> Result<MediaInfo> {
+        let info = MediaInfo::from(ffprobe_info);
+
+        logger::trace!(?info, "Got media info from ffprobe");
+
+        self.remove_file_data(file_id, FileData::MEDIA_INFO).await?;
+        self.add_file_data(file_id, FileData::MediaInfo(info.clone()))
+            .await?;
+
+        Ok(info)
+    }
+}
+
+impl From<&FfProbeResult> for MediaInfo {
+    fn from(probe: &FfProbeResult) -> Self {
+        let streams = probe.streams.as_deref().unwrap_or_default();
+
+        let video = probe.video_stream();
+        let audio = streams
+            .iter()
+            .find(|x| x.codec_type.as_deref() == Some("audio"));
+
+        let format = probe.format.as_ref();
+
+        Self {
+            duration: format
+                .and_then(ffmpeg::ffprobe::Format::get_duration)
+                .map(|x| x.as_secs_f64())
+                .or_else(|| video.and_then(|x| x.duration.as_deref()?.parse().ok())),
+            container: format.and_then(|x| x.format_name.clone()),
+            video_codec: video.and_then(|x| x.codec_name.clone()),
+            audio_codec: audio.and_then(|x| x.codec_name.clone()),
+            frame_rate: video.and_then(frame_rate),
+            bit_rate: format.and_then(|x| x.bit_rate.as_deref()?.parse().ok()),
+            rotation: video.map(Stream::rotation).unwrap_or_default(),
+            pixel_format: video.and_then(|x| x.pix_fmt.clone()),
+            has_audio: audio.is_some(),
+        }
+    }
+}
+
+/// Average frames per second of the stream
+fn frame_rate(stream: &Stream) -> Option<f64> {
+    let parse = |rate: &str| {
+        let (numer, denom) = rate.split_once('/')?;
+        let (numer, denom) = (numer.parse::<f64>().ok()?, denom.parse::<f64>().ok()?);
+
+        (numer > 0.0 && denom > 0.0).then(|| numer / denom)
+    };
+
+    stream
+        .avg_frame_rate
+        .as_deref()
+        .and_then(parse)
+        .or_else(|| stream.r_frame_rate.as_deref().and_then(parse))
+}
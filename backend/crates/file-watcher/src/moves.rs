@@ -0,0 +1,207 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use config::{AppConfig, CONFIG};
+use entity::files;
+use sea_orm::{prelude::*, Set};
+use tokio::fs;
+use tracing::instrument;
+
+use crate::{helpers::file::file_hash, FileWatcher};
+
+impl FileWatcher {
+    /// Match files that vanished from the index against new paths by their hash
+    /// and update the stored path in place, so the file keeps its ULID, tags and data.
+    ///
+    /// Returns the removed files and new paths that could not be matched.
+    #[instrument(skip_all)]
+    pub async fn apply_moves(
+        &self,
+        removed_files: Vec<files::Model>,
+        new_files: Vec<PathBuf>,
+    ) -> Result<(Vec<files::Model>, Vec<PathBuf>)> {
+        self.apply_moves_in(&CONFIG.app.directory, removed_files, new_files)
+            .await
+    }
+
+    async fn apply_moves_in(
+        &self,
+        directory: &Path,
+        removed_files: Vec<files::Model>,
+        new_files: Vec<PathBuf>,
+    ) -> Result<(Vec<files::Model>, Vec<PathBuf>)> {
+        let moved = self
+            .detect_moves(directory, &removed_files, &new_files)
+            .await?;
+
+        if moved.is_empty() {
+            return Ok((removed_files, new_files));
+        }
+
+        let moved_ids = moved.iter().map(|x| x.id).collect::<HashSet<_>>();
+        let moved_paths = moved
+            .iter()
+            .map(|x| directory.join(&x.path))
+            .collect::<HashSet<_>>();
+
+        let removed_files = removed_files
+            .into_iter()
+            .filter(|x| !moved_ids.contains(&x.id))
+            .collect();
+        let new_files = new_files
+            .into_iter()
+            .filter(|x| !moved_paths.contains(x))
+            .collect();
+
+        Ok((removed_files, new_files))
+    }
+
+    async fn detect_moves(
+        &self,
+        directory: &Path,
+        removed_files: &[files::Model],
+        new_files: &[PathBuf],
+    ) -> Result<Vec<files::Model>> {
+        if removed_files.is_empty() || new_files.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let new_files_rel = new_files
+            .iter()
+            .map(|x| AppConfig::relative_to(directory, x))
+            .collect::<Result<Vec<_>>>()?;
+
+        let already_indexed = files::Entity::find()
+            .filter(files::Column::Path.is_in(new_files_rel))
+            .all(self.db())
+            .await?
+            .into_iter()
+            .map(|x| x.path)
+            .collect::<HashSet<_>>();
+
+        let removed_sizes = removed_files
+            .iter()
+            .map(|x| x.file_size)
+            .collect::<HashSet<_>>();
+
+        let mut removed_by_hash = removed_files.iter().fold(
+            HashMap::new(),
+            |mut acc: HashMap<&str, Vec<&files::Model>>, x| {
+                acc.entry(x.hash.as_str()).or_default().push(x);
+                acc
+            },
+        );
+
+        let mut moved = Vec::new();
+
+        for new_file in new_files {
+            let rel_path = AppConfig::relative_to(directory, new_file)?;
+
+            if already_indexed.contains(&rel_path) {
+                continue;
+            }
+
+            let size = match fs::metadata(new_file).await {
+                Ok(meta) => meta.len().try_into().ok(),
+                Err(_) => continue,
+            };
+
+            // Hashing is expensive, so only hash files that could possibly match
+            if !removed_sizes.contains(&size) && !removed_sizes.contains(&None) {
+                continue;
+            }
+
+            let hash = match file_hash(new_file).await {
+                Ok(x) => x,
+                Err(e) => {
+                    logger::warn!(err = ?e, path = ?new_file, "Failed to hash file");
+                    continue;
+                }
+            };
+
+            let old_file = match removed_by_hash.get_mut(hash.as_str()).and_then(Vec::pop) {
+                Some(x) => x,
+                None => continue,
+            };
+
+            let mut model: files::ActiveModel = old_file.clone().into();
+            model.path = Set(rel_path);
+            let model = model.update(self.db()).await?;
+
+            logger::debug!(from = ?old_file.path, to = ?model.path, "Detected moved file");
+
+            moved.push(model);
+        }
+
+        Ok(moved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::helpers::test::{file_watcher, insert_file};
+
+    #[tokio::test]
+    async fn moves_files_with_the_same_hash() {
+        let fw = file_watcher().await;
+        let dir = TempDir::new().unwrap();
+
+        std::fs::write(dir.path().join("moved"), "moved").unwrap();
+        std::fs::write(dir.path().join("new"), "new").unwrap();
+        let hash = file_hash(&dir.path().join("moved")).await.unwrap();
+
+        let file = insert_file(&fw, "old", &hash).await;
+        let gone = insert_file(&fw, "gone", "other").await;
+
+        let (removed, new) = fw
+            .apply_moves_in(
+                dir.path(),
+                vec![file.clone(), gone.clone()],
+                vec![dir.path().join("moved"), dir.path().join("new")],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(removed, [gone]);
+        assert_eq!(new, [dir.path().join("new")]);
+
+        // The same file, under the new path
+        let moved = files::Entity::find_by_id(file.id)
+            .one(fw.db())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(moved.path, "moved");
+        assert_eq!(moved.ulid, file.ulid);
+    }
+
+    #[tokio::test]
+    async fn leaves_indexed_paths_alone() {
+        let fw = file_watcher().await;
+        let dir = TempDir::new().unwrap();
+
+        std::fs::write(dir.path().join("copy"), "same").unwrap();
+        let hash = file_hash(&dir.path().join("copy")).await.unwrap();
+
+        let file = insert_file(&fw, "old", &hash).await;
+        insert_file(&fw, "copy", &hash).await;
+
+        let (removed, new) = fw
+            .apply_moves_in(
+                dir.path(),
+                vec![file.clone()],
+                vec![dir.path().join("copy")],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(removed, [file]);
+        assert_eq!(new, [dir.path().join("copy")]);
+    }
+}
@@ -0,0 +1,282 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use config::CONFIG;
+use entity::{file_data, files};
+use image::imageops::FilterType;
+use sea_orm::prelude::*;
+use tokio::task;
+use tracing::instrument;
+
+use crate::{
+    data::{FileData, PhashData},
+    helpers::{
+        bk_tree::{hamming_distance, BkTree},
+        image::open_image,
+    },
+    thumb::{ThumbFormat, ThumbSize},
+    FileWatcher,
+};
+
+/// How many ids go into a single `IN (...)`, to stay under the bind parameter limits of the
+/// databases
+const QUERY_CHUNK_SIZE: usize = 500;
+
+#[derive(Debug, Clone)]
+pub struct SimilarFile {
+    pub file: files::Model,
+    pub distance: u32,
+}
+
+impl FileWatcher {
+    /// Get or generate the perceptual hash of the file.
+    ///
+    /// Videos (and images that can't be decoded) are hashed from their poster thumb.
+    #[instrument(skip(self))]
+    pub(crate) async fn get_or_generate_phash(&self, db_file: &files::Model) -> Result<u64> {
+        if let Some(hash) = self.get_phash(db_file.id).await? {
+            return Ok(hash);
+        }
+
+        let is_image = db_file
+            .file_type
+            .as_deref()
+            .is_some_and(|x| x.starts_with("image/"));
+
+        if is_image {
+            let file_path = CONFIG.app.directory_absolute(&db_file.path);
+
+            if let Ok(hash) = self.generate_phash(file_path, db_file.id).await {
+                return Ok(hash);
+            }
+
+            logger::debug!("Failed to generate phash from raw file. Generating from thumb");
+        }
+
+        let thumb = self
+            .get_or_generate_thumb(&db_file.ulid, ThumbSize::Poster, ThumbFormat::Jpeg)
+            .await
+            .map_err(|e| anyhow!("Failed to generate thumb: {}", e))?;
+
+        let thumb_path = CONFIG
+            .app
+            .metadata_directory_absolute(&thumb.path.to_string_lossy());
+
+        self.generate_phash(thumb_path, db_file.id).await
+    }
+
+    pub(crate) async fn get_phash(&self, file_id: i32) -> Result<Option<u64>> {
+        match self.get_file_data(file_id, FileData::PHASH).await? {
+            Some(FileData::Phash(x)) => Ok(Some(x.value()?)),
+            _ => Ok(None),
+        }
+    }
+
+    #[instrument(skip(self))]
+    pub(crate) async fn generate_phash(&self, image_path: PathBuf, file_id: i32) -> Result<u64> {
+        let hash = task::spawn_blocking(move || {
+            let img = open_image(&image_path)
+                .map_err(|e| anyhow!("Failed to open image {:?}: {}", &image_path, e))?;
+
+            Ok::<_, anyhow::Error>(dhash(&img))
+        })
+        .await??;
+
+        logger::trace!(hash = ?&hash, "Generated phash");
+
+        self.add_file_data(file_id, FileData::Phash(PhashData::new(hash)))
+            .await
+            .map_err(|e| anyhow!("Failed to save phash to db: {}", e.to_string()))?;
+
+        if let Some(index) = self.phash_index.write().unwrap().as_mut() {
+            index.insert(file_id, hash);
+        }
+
+        Ok(hash)
+    }
+
+    /// Files that look similar to the file with the given ULID, closest first
+    #[instrument(skip(self))]
+    pub async fn find_similar(&self, ulid: &str, max_distance: u32) -> Result<Vec<SimilarFile>> {
+        let db_file = files::Entity::find()
+            .filter(files::Column::Ulid.eq(ulid.to_uppercase()))
+            .one(self.db())
+            .await?
+            .ok_or_else(|| anyhow!("File not found: {:?}", ulid))?;
+
+        let hash = self
+            .get_phash(db_file.id)
+            .await?
+            .ok_or_else(|| anyhow!("File has no perceptual hash"))?;
+
+        if self.phash_index.read().unwrap().is_none() {
+            self.build_phash_index().await?;
+        }
+
+        let candidates = self
+            .phash_index
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|x| {
+                x.find(hash, max_distance)
+                    .into_iter()
+                    .filter(|id| *id != db_file.id)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let mut similar = Vec::new();
+
+        for candidates in candidates.chunks(QUERY_CHUNK_SIZE) {
+            let files = files::Entity::find()
+                .filter(files::Column::Id.is_in(candidates.iter().copied()))
+                .all(self.db())
+                .await?;
+
+            // Hashes that are in the index but not in the db anymore are left behind by
+            // changes outside of this process
+            let current_hashes = file_data::Entity::find()
+                .filter(file_data::Column::Key.eq(FileData::PHASH))
+                .filter(file_data::Column::FileId.is_in(candidates.iter().copied()))
+                .all(self.db())
+                .await?
+                .into_iter()
+                .filter_map(|x| Some((x.file_id, parse_phash(x).ok()?)))
+                .collect::<Vec<_>>();
+
+            similar.extend(files.into_iter().filter_map(|file| {
+                let distance = current_hashes
+                    .iter()
+                    .filter(|(id, _)| *id == file.id)
+                    .map(|(_, x)| hamming_distance(hash, *x))
+                    .min()?;
+
+                (distance <= max_distance).then_some(SimilarFile { file, distance })
+            }));
+        }
+
+        similar.sort_by_key(|x| (x.distance, x.file.id));
+
+        Ok(similar)
+    }
+
+    async fn build_phash_index(&self) -> Result<()> {
+        let hashes = file_data::Entity::find()
+            .filter(file_data::Column::Key.eq(FileData::PHASH))
+            .all(self.db())
+            .await?;
+
+        let mut index = PhashIndex::default();
+        for data in hashes {
+            let file_id = data.file_id;
+
+            match parse_phash(data) {
+                Ok(hash) => index.insert(file_id, hash),
+                Err(e) => logger::warn!(err = ?e, file_id, "Invalid phash in db"),
+            }
+        }
+
+        logger::debug!(size = index.len(), "Built phash index");
+
+        *self.phash_index.write().unwrap() = Some(index);
+
+        Ok(())
+    }
+
+    /// Take the files out of the phash index, for when their phash is removed
+    pub(crate) fn forget_phashes(&self, file_ids: impl IntoIterator<Item = i32>) {
+        if let Some(index) = self.phash_index.write().unwrap().as_mut() {
+            for file_id in file_ids {
+                index.remove(file_id);
+            }
+        }
+    }
+
+    /// Throw away the phash index, it's built again when it's needed next
+    pub(crate) fn reset_phash_index(&self) {
+        *self.phash_index.write().unwrap() = None;
+    }
+}
+
+/// The perceptual hashes of the files, searchable by distance
+#[derive(Debug, Default)]
+pub(crate) struct PhashIndex {
+    tree: BkTree<i32>,
+    /// The hash each file is stored under in the tree
+    hashes: HashMap<i32, u64>,
+}
+
+impl PhashIndex {
+    fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Add the hash of the file, replacing the one it had
+    fn insert(&mut self, file_id: i32, hash: u64) {
+        self.remove(file_id);
+
+        self.tree.insert(hash, file_id);
+        self.hashes.insert(file_id, hash);
+    }
+
+    fn remove(&mut self, file_id: i32) {
+        if let Some(hash) = self.hashes.remove(&file_id) {
+            self.tree.remove(hash, &file_id);
+        }
+    }
+
+    /// Ids of the files with a hash within `max_distance` of `hash`
+    fn find(&self, hash: u64, max_distance: u32) -> Vec<i32> {
+        self.tree
+            .find(hash, max_distance)
+            .into_iter()
+            .map(|(_, id)| *id)
+            .collect()
+    }
+}
+
+/// Difference hash: one bit per neighbouring pixel pair of a 9x8 grayscale version of the image
+fn dhash(img: &image::DynamicImage) -> u64 {
+    let small = img.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+
+    let mut hash = 0_u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+
+    hash
+}
+
+fn parse_phash(data: file_data::Model) -> Result<u64> {
+    match FileData::try_from(data)? {
+        FileData::Phash(x) => x.value(),
+        x => Err(anyhow!("Not a phash: {:?}", x)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_files_to_their_new_hash() {
+        let mut index = PhashIndex::default();
+        index.insert(1, 0b0000);
+        index.insert(2, 0b0001);
+        index.insert(1, 0b1111);
+
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.find(0b0000, 1), [2]);
+        assert_eq!(index.find(0b1111, 0), [1]);
+
+        index.remove(1);
+        assert_eq!(index.len(), 1);
+        assert!(index.find(0b1111, 0).is_empty());
+    }
+}
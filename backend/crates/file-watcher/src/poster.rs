@@ -0,0 +1,295 @@
+//! Picking the frame of a video its thumbs are made from.
+//!
+//! A few frames spread over the video are scored, so the thumbs don't show the black or faded
+//! in frame videos often start with. Users can pick the frame themselves instead, see
+//! [`FileWatcher::set_poster_timestamp`].
+
+use std::{
+    fmt::{self, Display},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use config::CONFIG;
+use entity::{file_data, files};
+use image::GrayImage;
+use sea_orm::{prelude::*, TransactionTrait};
+use tempfile::TempDir;
+use tokio::task;
+use tracing::instrument;
+
+use crate::{
+    data::{FileData, PosterFrameData},
+    jobs::{Job, JOB_PRIORITY_ON_DEMAND},
+    thumb::{parse_thumb_key, thumb_key_condition, ThumbSize},
+    FileWatcher,
+};
+
+/// How many frames of a video are scored
+pub const POSTER_SAMPLES: u32 = 8;
+/// Frames darker than this on average are taken as black
+pub const POSTER_MIN_BRIGHTNESS: f64 = 20.0;
+/// Frames brighter than this on average are taken as white
+pub const POSTER_MAX_BRIGHTNESS: f64 = 235.0;
+/// Frames with less contrast than this are taken as blank, eg. mid fade
+pub const POSTER_MIN_CONTRAST: f64 = 12.0;
+
+/// The poster frame can't be set as requested, eg. the file isn't a video
+#[derive(Debug)]
+pub struct InvalidPosterFrame(String);
+
+impl Display for InvalidPosterFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidPosterFrame {}
+
+#[derive(Debug, Clone, Copy)]
+struct FrameScore {
+    timestamp: f64,
+    /// Whether the frame isn't black, white or blank
+    usable: bool,
+    score: f64,
+}
+
+impl FileWatcher {
+    /// The timestamp (in seconds) to make the thumbs of the video from, selecting and storing
+    /// one if there is none yet
+    #[instrument(skip(self))]
+    pub(crate) async fn poster_timestamp(&self, file_id: i32, video_path: &Path) -> Result<f64> {
+        if let Some(FileData::PosterFrame(x)) =
+            self.get_file_data(file_id, FileData::POSTER_FRAME).await?
+        {
+            return Ok(x.timestamp);
+        }
+
+        let timestamp = self.select_poster_frame(video_path).await?;
+
+        logger::debug!(timestamp, "Selected poster frame");
+
+        self.store_poster_frame(
+            file_id,
+            PosterFrameData {
+                timestamp,
+                manual: false,
+            },
+        )
+        .await
+    }
+
+    /// Store the poster frame of the file in place of the current one. Several thumbs of a
+    /// video can be made at once, so a selected frame doesn't replace one stored meanwhile.
+    ///
+    /// Returns the timestamp of the stored frame.
+    async fn store_poster_frame(&self, file_id: i32, frame: PosterFrameData) -> Result<f64> {
+        let txn = self.db().begin().await?;
+
+        let current = file_data::Entity::find()
+            .filter(file_data::Column::FileId.eq(file_id))
+            .filter(file_data::Column::Key.eq(FileData::POSTER_FRAME))
+            .one(&txn)
+            .await?
+            .map(FileData::try_from)
+            .transpose()?;
+
+        if let (false, Some(FileData::PosterFrame(current))) = (frame.manual, current) {
+            txn.commit().await?;
+            return Ok(current.timestamp);
+        }
+
+        let timestamp = frame.timestamp;
+        FileData::PosterFrame(frame).replace(&txn, file_id).await?;
+
+        txn.commit().await?;
+
+        Ok(timestamp)
+    }
+
+    /// Make the thumbs of the video from the frame at the timestamp (in seconds), or go back to
+    /// the automatically selected frame if there is none.
+    ///
+    /// The thumbs made from the old frame are removed and queued for generation, returns how
+    /// many were. Fails with [`InvalidPosterFrame`] if the file or timestamp doesn't work.
+    #[instrument(skip(self, db_file), fields(file = db_file.path))]
+    pub async fn set_poster_timestamp(
+        &self,
+        db_file: &files::Model,
+        timestamp: Option<f64>,
+    ) -> Result<u64> {
+        let invalid = |message: String| Err(InvalidPosterFrame(message).into());
+
+        if !db_file
+            .file_type
+            .as_deref()
+            .is_some_and(|x| x.starts_with("video/"))
+        {
+            return invalid("Only videos have a poster frame".to_string());
+        }
+
+        match timestamp {
+            Some(timestamp) => {
+                if !timestamp.is_finite() || timestamp < 0.0 {
+                    return invalid(format!("Invalid poster timestamp: {timestamp}"));
+                }
+
+                let path = CONFIG.app.directory_absolute(&db_file.path);
+                if let Some(duration) = video_duration(&path).await? {
+                    if timestamp >= duration {
+                        return invalid(format!(
+                            "Poster timestamp {timestamp}s is past the end of the video"
+                        ));
+                    }
+                }
+
+                self.store_poster_frame(
+                    db_file.id,
+                    PosterFrameData {
+                        timestamp,
+                        manual: true,
+                    },
+                )
+                .await?;
+            }
+            None => {
+                self.remove_file_data(db_file.id, FileData::POSTER_FRAME)
+                    .await?;
+            }
+        }
+
+        // Previews aren't made from the poster frame, everything else derived from the thumbs is
+        let thumbs = file_data::Entity::find()
+            .filter(file_data::Column::FileId.eq(db_file.id))
+            .filter(thumb_key_condition())
+            .all(self.db())
+            .await?
+            .into_iter()
+            .filter_map(|x| parse_thumb_key(&x.key))
+            .filter(|(size, _)| !matches!(size, ThumbSize::Preview))
+            .collect::<Vec<_>>();
+
+        for (size, format) in &thumbs {
+            self.remove_file_data(db_file.id, &size.key(*format))
+                .await?;
+        }
+
+        for key in [FileData::BLURHASH, FileData::PHASH] {
+            self.remove_file_data(db_file.id, key).await?;
+        }
+
+        for (size, format) in thumbs.iter().cloned() {
+            self.enqueue_job(
+                db_file.id,
+                &Job::Thumb(size, format),
+                JOB_PRIORITY_ON_DEMAND,
+            )
+            .await?;
+        }
+
+        self.enqueue_extractors(db_file).await?;
+
+        logger::info!(
+            file = db_file.path,
+            timestamp,
+            thumbs = thumbs.len(),
+            "Changed poster frame"
+        );
+
+        Ok(thumbs.len() as u64)
+    }
+
+    /// The most representative of a few frames spread over the video, in seconds
+    #[instrument(skip(self))]
+    async fn select_poster_frame(&self, video_path: &Path) -> Result<f64> {
+        let Some(duration) = video_duration(video_path).await? else {
+            return Ok(0.0);
+        };
+
+        let dir = TempDir::new()
+            .map_err(|e| anyhow!("Failed to create temporary directory: {}", e.to_string()))?;
+
+        let mut best: Option<FrameScore> = None;
+
+        for i in 0..POSTER_SAMPLES {
+            let timestamp = duration * f64::from(i + 1) / f64::from(POSTER_SAMPLES + 1);
+            let frame_path = dir.path().join(format!("{i}.jpg"));
+
+            if let Err(e) = self
+                .extract_video_frame(video_path, &frame_path, timestamp)
+                .await
+            {
+                logger::debug!(err = ?e, timestamp, "Skipping poster frame");
+                continue;
+            }
+
+            let frame = task::spawn_blocking(move || -> Result<GrayImage> {
+                Ok(image::open(&frame_path)
+                    .map_err(|e| anyhow!("Failed to open frame: {}", e))?
+                    .thumbnail(64, 64)
+                    .to_luma8())
+            })
+            .await??;
+
+            let score = score_frame(&frame, timestamp);
+
+            logger::trace!(score = ?score, "Scored poster frame");
+
+            if best.is_none_or(|x| (score.usable, score.score) > (x.usable, x.score)) {
+                best = Some(score);
+            }
+        }
+
+        Ok(best.map_or(0.0, |x| x.timestamp))
+    }
+}
+
+/// Length of the video in seconds, if ffprobe knows it
+pub(crate) async fn video_duration(video_path: &Path) -> Result<Option<f64>> {
+    let probe = ffmpeg::ffprobe::ConfigBuilder::new()
+        .run(video_path)
+        .await
+        .map_err(|e| anyhow!("Failed to run ffprobe: {}", e.to_string()))?;
+
+    Ok(probe
+        .format
+        .and_then(|x| x.get_duration())
+        .map(|x| x.as_secs_f64()))
+}
+
+/// Score the frame by how much there is to see, ie. its contrast and the entropy of its
+/// histogram
+#[allow(clippy::cast_precision_loss)]
+fn score_frame(frame: &GrayImage, timestamp: f64) -> FrameScore {
+    let pixels = frame.as_raw();
+    let count = pixels.len().max(1) as f64;
+
+    let mut histogram = [0u32; 256];
+    for &x in pixels {
+        histogram[x as usize] += 1;
+    }
+
+    let mean = pixels.iter().map(|&x| f64::from(x)).sum::<f64>() / count;
+    let variance = pixels
+        .iter()
+        .map(|&x| (f64::from(x) - mean).powi(2))
+        .sum::<f64>()
+        / count;
+    let contrast = variance.sqrt();
+
+    let entropy = histogram
+        .iter()
+        .filter(|&&x| x > 0)
+        .map(|&x| {
+            let p = f64::from(x) / count;
+            -p * p.log2()
+        })
+        .sum::<f64>();
+
+    FrameScore {
+        timestamp,
+        usable: (POSTER_MIN_BRIGHTNESS..=POSTER_MAX_BRIGHTNESS).contains(&mean)
+            && contrast >= POSTER_MIN_CONTRAST,
+        score: entropy + contrast / 32.0,
+    }
+}
@@ -0,0 +1,263 @@
+//! Animated previews of videos and GIFs, see [`ThumbSize::Preview`].
+//!
+//! Both are made into a looping animated WebP, so they can be shown wherever the other thumbs
+//! are. Videos are cut down to a few short, muted segments spread over their length, GIFs are
+//! only scaled down.
+
+use std::{fs, io::BufReader, path::Path, process::Stdio, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use config::CONFIG;
+use image::{codecs::gif::GifDecoder, imageops::FilterType, AnimationDecoder, RgbaImage};
+use tokio::{process::Command, task};
+use tracing::instrument;
+use webp::{AnimEncoder, AnimFrame, WebPConfig};
+use which::which;
+
+use crate::{
+    helpers::file::file_hash,
+    thumb::{ThumbDimensions, ThumbGenerateResult, ThumbOptions},
+    FileWatcher,
+};
+
+/// How many segments of a video make up its preview
+pub const PREVIEW_SEGMENTS: u32 = 3;
+/// How long each segment of a video preview is
+pub const PREVIEW_SEGMENT_LENGTH: Duration = Duration::from_millis(1500);
+/// Frame rate of video previews
+pub const PREVIEW_FPS: u32 = 10;
+/// Longer GIFs are cut off after this many frames
+pub const PREVIEW_MAX_FRAMES: usize = 300;
+
+#[derive(Debug)]
+struct PreviewFrame {
+    image: RgbaImage,
+    /// When the frame is shown, in milliseconds from the start
+    timestamp: i32,
+}
+
+impl FileWatcher {
+    #[instrument(skip(self))]
+    pub(crate) async fn generate_preview(
+        &self,
+        file_path: &Path,
+        file_type: &str,
+        options: ThumbOptions,
+    ) -> Result<ThumbGenerateResult> {
+        let ThumbOptions {
+            path: thumb_path,
+            dimensions,
+            format,
+            quality,
+        } = options;
+
+        let frames = match file_type {
+            "image/gif" => gif_frames(file_path, dimensions.clone()).await?,
+            t if t.starts_with("video/") => video_frames(file_path, &dimensions).await?,
+            _ => bail!("Previews are only made of videos and GIFs"),
+        };
+
+        logger::trace!(frames = frames.len(), "Extracted preview frames");
+
+        let ((width, height), thumb_path) = task::spawn_blocking(move || -> Result<_> {
+            let size = frames
+                .first()
+                .map(|x| x.image.dimensions())
+                .ok_or_else(|| anyhow!("No frames to make a preview of"))?;
+
+            fs::write(&thumb_path, encode_animation(&frames, quality)?)?;
+
+            Ok((size, thumb_path))
+        })
+        .await?
+        .map_err(|e| anyhow!("Failed to save preview: {}", e.to_string()))?;
+
+        logger::trace!(path = ?thumb_path, "Saved preview");
+
+        Ok(ThumbGenerateResult {
+            width,
+            height,
+            hash: file_hash(&thumb_path).await?,
+            path: CONFIG.app.metadata_directory_relative(&thumb_path)?.into(),
+            format,
+            timestamp: None,
+        })
+    }
+}
+
+/// All frames of the GIF, scaled down
+async fn gif_frames(path: &Path, dimensions: ThumbDimensions) -> Result<Vec<PreviewFrame>> {
+    let path = path.to_path_buf();
+
+    task::spawn_blocking(move || -> Result<Vec<PreviewFrame>> {
+        let decoder = GifDecoder::new(BufReader::new(fs::File::open(path)?))
+            .map_err(|e| anyhow!("Failed to decode gif: {}", e))?;
+
+        let mut timestamp = 0;
+        let mut frames = vec![];
+
+        for frame in decoder.into_frames().take(PREVIEW_MAX_FRAMES) {
+            let frame = frame.map_err(|e| anyhow!("Failed to decode gif frame: {}", e))?;
+
+            let (width, height) = fit(frame.buffer().dimensions(), &dimensions);
+            let image =
+                image::imageops::resize(frame.buffer(), width, height, FilterType::Triangle);
+
+            frames.push(PreviewFrame { image, timestamp });
+
+            // Browsers play frames without a (sensible) delay at 10 fps as well
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            timestamp += match i32::try_from(numer / denom.max(1)).unwrap_or(i32::MAX) {
+                x if x <= 10 => 100,
+                x => x,
+            };
+        }
+
+        Ok(frames)
+    })
+    .await?
+}
+
+/// Frames of a few segments of the video, scaled down
+async fn video_frames(path: &Path, dimensions: &ThumbDimensions) -> Result<Vec<PreviewFrame>> {
+    let probe = ffmpeg::ffprobe::ConfigBuilder::new()
+        .with_streams(true)
+        .run(path)
+        .await
+        .map_err(|e| anyhow!("Failed to run ffprobe: {}", e.to_string()))?;
+
+    // ffmpeg rotates the frames, and they're scaled to square pixels below
+    let size = probe
+        .video_stream()
+        .and_then(ffmpeg::ffprobe::Stream::display_dimensions)
+        .and_then(|(w, h)| Some((u32::try_from(w).ok()?, u32::try_from(h).ok()?)))
+        .ok_or_else(|| anyhow!("Video has no dimensions"))?;
+
+    let (width, height) = fit(size, dimensions);
+    let segments = segments(probe.format.and_then(|x| x.get_duration()));
+
+    let mut cmd = Command::new(which("ffmpeg")?);
+    cmd.args(["-hide_banner", "-loglevel", "error"]);
+
+    for (start, length) in &segments {
+        cmd.args(["-ss", &format!("{:.3}", start.as_secs_f64())])
+            .args(["-t", &format!("{:.3}", length.as_secs_f64())])
+            .arg("-i")
+            .arg(path);
+    }
+
+    // Every segment is scaled the same way, then they're joined into one
+    let mut filters = vec![];
+    let mut inputs = vec![];
+    for i in 0..segments.len() {
+        filters.push(format!(
+            "[{i}:v:0]fps={PREVIEW_FPS},scale={width}:{height},setsar=1[v{i}]"
+        ));
+        inputs.push(format!("[v{i}]"));
+    }
+
+    cmd.args([
+        "-filter_complex",
+        &format!(
+            "{filters};{inputs}concat=n={n}:v=1:a=0[out]",
+            filters = filters.join(";"),
+            inputs = inputs.concat(),
+            n = segments.len()
+        ),
+    ])
+    .args(["-map", "[out]", "-an"])
+    .args(["-f", "rawvideo", "-pix_fmt", "rgba", "pipe:1"])
+    .stdin(Stdio::null());
+
+    logger::trace!(cmd = ?cmd, "Running ffmpeg command");
+
+    let output = cmd
+        .output()
+        .await
+        .map_err(|e| anyhow!("Failed to run ffmpeg command: {}", e.to_string()))?;
+
+    if !output.status.success() {
+        bail!(
+            "Failed to extract preview frames: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let frame_length = width as usize * height as usize * 4;
+
+    output
+        .stdout
+        .chunks_exact(frame_length)
+        .enumerate()
+        .map(|(i, x)| {
+            let image = RgbaImage::from_raw(width, height, x.to_vec())
+                .ok_or_else(|| anyhow!("Invalid frame from ffmpeg"))?;
+            let timestamp = i32::try_from(i)? * 1000 / i32::try_from(PREVIEW_FPS)?;
+
+            Ok(PreviewFrame { image, timestamp })
+        })
+        .collect()
+}
+
+/// Where the segments of a video preview start and how long they are.
+///
+/// Videos too short to skip through are shown from the start.
+fn segments(duration: Option<Duration>) -> Vec<(Duration, Duration)> {
+    let total = PREVIEW_SEGMENT_LENGTH * PREVIEW_SEGMENTS;
+
+    match duration {
+        Some(duration) if duration > total * 2 => (1..=PREVIEW_SEGMENTS)
+            .map(|i| {
+                let middle = duration * i / (PREVIEW_SEGMENTS + 1);
+                let start = middle.saturating_sub(PREVIEW_SEGMENT_LENGTH / 2);
+                (start, PREVIEW_SEGMENT_LENGTH)
+            })
+            .collect(),
+        Some(duration) => vec![(Duration::ZERO, duration.min(total))],
+        None => vec![(Duration::ZERO, total)],
+    }
+}
+
+/// The size to scale to so it fits the dimensions, keeping the aspect ratio. Never scales up.
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+fn fit((width, height): (u32, u32), dimensions: &ThumbDimensions) -> (u32, u32) {
+    let scale = f64::min(
+        f64::from(dimensions.width) / f64::from(width.max(1)),
+        f64::from(dimensions.height) / f64::from(height.max(1)),
+    )
+    .min(1.0);
+
+    let scaled = |x: u32| ((f64::from(x) * scale).round() as u32).max(1);
+
+    (scaled(width), scaled(height))
+}
+
+fn encode_animation(frames: &[PreviewFrame], quality: u8) -> Result<Vec<u8>> {
+    let (width, height) = frames
+        .first()
+        .map(|x| x.image.dimensions())
+        .ok_or_else(|| anyhow!("No frames to encode"))?;
+
+    let mut config =
+        WebPConfig::new().map_err(|()| anyhow!("Failed to configure the WebP encoder"))?;
+    config.quality = f32::from(quality);
+
+    let mut encoder = AnimEncoder::new(width, height, &config);
+    encoder.set_loop_count(0);
+
+    for frame in frames {
+        encoder.add_frame(AnimFrame::from_rgba(
+            frame.image.as_raw(),
+            width,
+            height,
+            frame.timestamp,
+        ));
+    }
+
+    let bytes = encoder
+        .try_encode()
+        .map_err(|e| anyhow!("Failed to encode animation: {:?}", e))?;
+
+    Ok(bytes.to_vec())
+}
@@ -1,4 +1,7 @@
-use std::{collections::HashSet, path::PathBuf};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
 
 use config::CONFIG;
 use tokio::task;
@@ -9,8 +12,14 @@ use crate::FileWatcher;
 impl FileWatcher {
     pub async fn scan_directory(&self) -> HashSet<PathBuf> {
         let dir = &CONFIG.app.directory;
-        let depth = if self.recursive { 10 } else { 1 };
-        logger::debug!(dir = ?&dir, depth, "Scanning directory");
+        logger::debug!(dir = ?&dir, depth = self.scan_depth(), "Scanning directory");
+
+        self.scan_path(dir, self.scan_depth()).await
+    }
+
+    /// Files in `dir` at most `depth` levels down
+    pub(crate) async fn scan_path(&self, dir: &Path, depth: usize) -> HashSet<PathBuf> {
+        let dir = dir.to_path_buf();
 
         task::spawn_blocking(move || {
             let mut files = HashSet::new();
@@ -37,4 +46,24 @@ impl FileWatcher {
         .await
         .unwrap_or_default()
     }
+
+    fn scan_depth(&self) -> usize {
+        if self.recursive {
+            10
+        } else {
+            1
+        }
+    }
+
+    /// How many levels below `path` a scan of the whole directory goes, `None` if `path` itself
+    /// is out of its reach.
+    ///
+    /// Paths deeper than that would be pruned again by the next full scan, so they are left out.
+    pub(crate) fn remaining_scan_depth(&self, path: &Path) -> Option<usize> {
+        let below_root = path
+            .strip_prefix(&CONFIG.app.directory)
+            .map_or(0, |x| x.components().count());
+
+        self.scan_depth().checked_sub(below_root)
+    }
 }
@@ -0,0 +1,115 @@
+//! Sprite sheets of videos for previews while scrubbing.
+//!
+//! A sheet holds evenly spaced frames of the video, and a WebVTT thumbnails track maps the time
+//! ranges of the video to the regions of the sheet. The track refers to the sheet by the
+//! relative URL `sprite.jpg`, so both have to be served next to each other.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use config::CONFIG;
+use entity::files;
+use sea_orm::TransactionTrait;
+use tokio::fs;
+use tracing::instrument;
+
+use crate::{
+    data::{FileData, SpriteData, SpriteTrackData},
+    helpers::file::file_hash,
+    poster::video_duration,
+    thumb::thumb_file_path,
+    FileWatcher,
+};
+
+/// Shorter videos are quick enough to scrub through without a sprite
+pub const SPRITE_MIN_DURATION: Duration = Duration::from_secs(30);
+/// The most frames a sprite has, longer videos get more time between them
+pub const SPRITE_MAX_FRAMES: u32 = 100;
+/// The least time between two frames of a sprite
+pub const SPRITE_MIN_INTERVAL: Duration = Duration::from_secs(2);
+/// How many frames are in a row of a sprite
+pub const SPRITE_COLUMNS: u32 = 10;
+/// Width of a frame of a sprite
+pub const SPRITE_TILE_WIDTH: u32 = 160;
+
+impl FileWatcher {
+    /// The sprite of the video and its thumbnails track, if it has them
+    pub async fn get_sprite(&self, ulid: &str) -> Result<Option<(SpriteData, SpriteTrackData)>> {
+        let db_file = self.get_file_by_ulid(ulid).await?;
+
+        let sprite = self.get_file_data(db_file.id, FileData::SPRITE).await?;
+        let track = self
+            .get_file_data(db_file.id, FileData::SPRITE_TRACK)
+            .await?;
+
+        match (sprite, track) {
+            (Some(FileData::Sprite(sprite)), Some(FileData::SpriteTrack(track))) => {
+                Ok(Some((sprite, track)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Make the sprite of the video and its thumbnails track. Videos shorter than
+    /// [`SPRITE_MIN_DURATION`] don't get one.
+    #[instrument(skip(self, db_file), fields(file = db_file.path))]
+    pub(crate) async fn generate_sprite(&self, db_file: &files::Model) -> Result<()> {
+        let file_path = CONFIG.app.directory_absolute(&db_file.path);
+
+        let Some(duration) = video_duration(&file_path).await? else {
+            logger::debug!("Video has no duration, not making a sprite");
+            return Ok(());
+        };
+
+        if duration < SPRITE_MIN_DURATION.as_secs_f64() {
+            logger::trace!(duration, "Video is too short for a sprite");
+            return Ok(());
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        let frames =
+            ((duration / SPRITE_MIN_INTERVAL.as_secs_f64()) as u32).clamp(1, SPRITE_MAX_FRAMES);
+
+        let sprite_path = thumb_file_path(db_file, "sprite.jpg");
+        let track_path = thumb_file_path(db_file, "sprite.vtt");
+
+        let sprite = ffmpeg::sprite::SpriteConfig::builder()
+            .frames(frames)
+            .columns(SPRITE_COLUMNS)
+            .tile_width(SPRITE_TILE_WIDTH)
+            .run(&file_path, &sprite_path)
+            .await
+            .map_err(|e| anyhow!("Failed to make sprite: {}", e.to_string()))?;
+
+        logger::trace!(sprite = ?sprite, "Made sprite");
+
+        fs::write(&track_path, sprite.webvtt("sprite.jpg")).await?;
+
+        let track = FileData::SpriteTrack(SpriteTrackData {
+            path: CONFIG.app.metadata_directory_relative(&track_path)?,
+            hash: file_hash(&track_path).await?,
+        });
+        let sprite = FileData::Sprite(SpriteData {
+            path: CONFIG.app.metadata_directory_relative(&sprite_path)?,
+            hash: file_hash(&sprite_path).await?,
+            frames: sprite.frames,
+            columns: sprite.columns,
+            rows: sprite.rows,
+            tile_width: sprite.tile_width,
+            tile_height: sprite.tile_height,
+            interval: sprite.interval().as_secs_f64(),
+        });
+
+        // Replace the rows of an earlier sprite, which had the same paths
+        let txn = self.db().begin().await?;
+
+        for data in [track, sprite] {
+            data.replace(&txn, db_file.id).await?;
+        }
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+}
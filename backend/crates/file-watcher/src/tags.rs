@@ -0,0 +1,221 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use entity::{files, files_tags, tags};
+use sea_orm::{
+    prelude::*,
+    sea_query::{Func, OnConflict, SimpleExpr},
+    DbBackend, FromQueryResult, JoinType, QueryOrder, QuerySelect, Select, Set,
+};
+use tracing::instrument;
+
+use crate::FileWatcher;
+
+#[derive(Debug, Clone, FromQueryResult)]
+pub struct TagWithCount {
+    pub id: i32,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    /// Number of files with the tag
+    pub count: i64,
+}
+
+/// Trim the name and collapse inner whitespace. Returns `None` for empty names.
+#[must_use]
+pub fn normalize_tag_name(name: &str) -> Option<String> {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+impl FileWatcher {
+    /// All tags with their usage counts, ordered by name
+    pub async fn get_tags(&self) -> Result<Vec<TagWithCount>> {
+        let tags = tags_with_count_query()
+            .order_by_asc(SimpleExpr::from(Func::lower(Expr::col((
+                tags::Entity,
+                tags::Column::Name,
+            )))))
+            .into_model::<TagWithCount>()
+            .all(self.db())
+            .await?;
+
+        Ok(tags)
+    }
+
+    pub async fn get_tag(&self, id: i32) -> Result<Option<TagWithCount>> {
+        let tag = tags_with_count_query()
+            .filter(tags::Column::Id.eq(id))
+            .into_model::<TagWithCount>()
+            .one(self.db())
+            .await?;
+
+        Ok(tag)
+    }
+
+    /// Find a tag by its name, ignoring case
+    pub async fn find_tag_by_name(&self, name: &str) -> Result<Option<tags::Model>> {
+        let tag = tags::Entity::find()
+            .filter(Expr::expr(Func::lower(Expr::col(tags::Column::Name))).eq(name.to_lowercase()))
+            .one(self.db())
+            .await?;
+
+        Ok(tag)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn create_tag(&self, name: &str) -> Result<tags::Model> {
+        let name = normalize_tag_name(name).ok_or_else(|| anyhow!("Tag name is empty"))?;
+
+        let tag = tags::ActiveModel {
+            name: Set(name),
+            ..Default::default()
+        }
+        .insert(self.db())
+        .await?;
+
+        logger::debug!(?tag, "Created tag");
+
+        Ok(tag)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn rename_tag(&self, id: i32, name: &str) -> Result<Option<tags::Model>> {
+        let name = normalize_tag_name(name).ok_or_else(|| anyhow!("Tag name is empty"))?;
+
+        let Some(tag) = tags::Entity::find_by_id(id).one(self.db()).await? else {
+            return Ok(None);
+        };
+
+        let mut model: tags::ActiveModel = tag.into();
+        model.name = Set(name);
+        let tag = model.update(self.db()).await?;
+
+        logger::debug!(?tag, "Renamed tag");
+
+        Ok(Some(tag))
+    }
+
+    /// Delete the tag and remove it from all files. Returns whether the tag existed.
+    #[instrument(skip(self))]
+    pub async fn delete_tag(&self, id: i32) -> Result<bool> {
+        let res = tags::Entity::delete_by_id(id).exec(self.db()).await?;
+
+        Ok(res.rows_affected > 0)
+    }
+
+    pub async fn get_files_by_ulids(&self, ulids: &[String]) -> Result<Vec<files::Model>> {
+        let files = files::Entity::find()
+            .filter(files::Column::Ulid.is_in(ulids.iter().map(|x| x.to_uppercase())))
+            .all(self.db())
+            .await?;
+
+        Ok(files)
+    }
+
+    /// Tags of the given files, keyed by file id
+    pub async fn get_files_tags(&self, file_ids: &[i32]) -> Result<HashMap<i32, Vec<tags::Model>>> {
+        let files_tags = files_tags::Entity::find()
+            .filter(files_tags::Column::FileId.is_in(file_ids.iter().copied()))
+            .find_also_related(tags::Entity)
+            .order_by_asc(files_tags::Column::Id)
+            .all(self.db())
+            .await?
+            .into_iter()
+            .filter_map(|(file_tag, tag)| Some((file_tag.file_id, tag?)))
+            .fold(
+                HashMap::new(),
+                |mut acc: HashMap<i32, Vec<_>>, (file_id, tag)| {
+                    acc.entry(file_id).or_default().push(tag);
+                    acc
+                },
+            );
+
+        Ok(files_tags)
+    }
+
+    /// Add every tag to every file. Returns how many new assignments were made.
+    #[instrument(skip(self))]
+    pub async fn add_tags(&self, file_ids: &[i32], tag_ids: &[i32]) -> Result<u64> {
+        if file_ids.is_empty() || tag_ids.is_empty() {
+            return Ok(0);
+        }
+
+        // MySQL counts duplicates as affected rows, so leave them out beforehand
+        let existing = files_tags::Entity::find()
+            .filter(files_tags::Column::FileId.is_in(file_ids.iter().copied()))
+            .filter(files_tags::Column::TagId.is_in(tag_ids.iter().copied()))
+            .all(self.db())
+            .await?
+            .into_iter()
+            .map(|x| (x.file_id, x.tag_id))
+            .collect::<HashSet<_>>();
+
+        let models = file_ids
+            .iter()
+            .flat_map(|file_id| tag_ids.iter().map(move |tag_id| (*file_id, *tag_id)))
+            .filter(|x| !existing.contains(x))
+            .map(|(file_id, tag_id)| files_tags::ActiveModel {
+                file_id: Set(file_id),
+                tag_id: Set(tag_id),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        if models.is_empty() {
+            return Ok(0);
+        }
+
+        let mut on_conflict =
+            OnConflict::columns([files_tags::Column::FileId, files_tags::Column::TagId]);
+
+        // MySQL has no `DO NOTHING`, so there a duplicate sets a column to itself instead
+        match self.db().get_database_backend() {
+            DbBackend::MySql => on_conflict.update_column(files_tags::Column::TagId),
+            DbBackend::Sqlite | DbBackend::Postgres => on_conflict.do_nothing(),
+        };
+
+        let added = files_tags::Entity::insert_many(models)
+            .on_conflict(on_conflict)
+            .exec_without_returning(self.db())
+            .await?;
+
+        logger::debug!(added, "Added tags to files");
+
+        Ok(added)
+    }
+
+    /// Remove every tag from every file. Returns how many assignments were removed.
+    #[instrument(skip(self))]
+    pub async fn remove_tags(&self, file_ids: &[i32], tag_ids: &[i32]) -> Result<u64> {
+        if file_ids.is_empty() || tag_ids.is_empty() {
+            return Ok(0);
+        }
+
+        let res = files_tags::Entity::delete_many()
+            .filter(files_tags::Column::FileId.is_in(file_ids.iter().copied()))
+            .filter(files_tags::Column::TagId.is_in(tag_ids.iter().copied()))
+            .exec(self.db())
+            .await?;
+
+        logger::debug!(removed = res.rows_affected, "Removed tags from files");
+
+        Ok(res.rows_affected)
+    }
+}
+
+fn tags_with_count_query() -> Select<tags::Entity> {
+    tags::Entity::find()
+        .select_only()
+        .column(tags::Column::Id)
+        .column(tags::Column::Name)
+        .column(tags::Column::CreatedAt)
+        .column_as(files_tags::Column::Id.count(), "count")
+        .join(JoinType::LeftJoin, tags::Relation::FilesTags.def())
+        .group_by(tags::Column::Id)
+}
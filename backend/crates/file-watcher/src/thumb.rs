@@ -1,25 +1,38 @@
 use std::{
     fmt::{Debug, Display},
+    fs,
     path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
 };
 
 use anyhow::{anyhow, bail, Result};
 use chrono::{prelude::*, DateTime};
 use config::CONFIG;
 use entity::{file_data, files};
-use image::{io::Reader as ImageReader, DynamicImage, GenericImageView};
-use sea_orm::{prelude::*, Condition, Set};
+use image::{codecs::jpeg::JpegEncoder, DynamicImage, GenericImageView};
+use ravif::Img;
+use rgb::FromSlice;
+use sea_orm::{prelude::*, Condition};
 use serde::{Deserialize, Serialize};
 use tempfile::Builder as TempfileBuilder;
-use tokio::{process::Command, task};
+use tokio::{process::Command, task, time};
 use tracing::instrument;
+use typeshare::typeshare;
 use which::which;
 
 use crate::{
-    helpers::{date::parse_db_date, file::file_hash},
+    data::{FileData, ThumbData},
+    helpers::{file::file_hash, image::open_image},
+    jobs::{Job, JOB_PRIORITY_ON_DEMAND},
     FileWatcher,
 };
 
+/// How long an API request waits for an on-demand thumb to be generated.
+pub const THUMB_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Speed of the AVIF encoder (1-10), thumbs are small enough for the faster settings.
+const AVIF_SPEED: u8 = 8;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileThumb {
     pub path: PathBuf,
@@ -27,12 +40,24 @@ pub struct FileThumb {
     pub meta: FileThumbMeta,
 }
 
+/// How to make a thumb
+#[derive(Debug, Clone)]
+pub(crate) struct ThumbOptions {
+    /// Where to save the thumb, see [`thumb_file_path`]
+    pub path: PathBuf,
+    pub dimensions: ThumbDimensions,
+    pub format: ThumbFormat,
+    pub quality: u8,
+}
+
 #[derive(Debug, Clone)]
-struct ThumbGenerateResult {
+pub(crate) struct ThumbGenerateResult {
     pub width: u32,
     pub height: u32,
     pub hash: String,
     pub path: PathBuf,
+    pub format: ThumbFormat,
+    pub timestamp: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,6 +65,21 @@ pub struct FileThumbMeta {
     pub width: u32,
     pub height: u32,
     pub hash: String,
+    pub format: ThumbFormat,
+    /// Where in the video the frame was taken from, in seconds
+    pub timestamp: Option<f64>,
+}
+
+impl From<ThumbData> for FileThumbMeta {
+    fn from(data: ThumbData) -> Self {
+        Self {
+            width: data.width,
+            height: data.height,
+            hash: data.hash,
+            format: data.format,
+            timestamp: data.timestamp,
+        }
+    }
 }
 
 impl From<ThumbGenerateResult> for FileThumbMeta {
@@ -48,8 +88,52 @@ impl From<ThumbGenerateResult> for FileThumbMeta {
             width: result.width,
             height: result.height,
             hash: result.hash,
+            format: result.format,
+            timestamp: result.timestamp,
+        }
+    }
+}
+
+/// The image format a thumb is encoded in.
+///
+/// Every size can have a variant in each format. JPEG is the one the other thumb based data
+/// (blurhash, phash, ...) is made from, and what clients get when they accept nothing better.
+/// Only the [`ThumbSize::base_format`] is made when indexing, the others when first requested.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub enum ThumbFormat {
+    #[default]
+    Jpeg,
+    Webp,
+    Avif,
+}
+
+impl ThumbFormat {
+    /// Every format, the ones to prefer when a client accepts several first
+    pub const ALL: [Self; 3] = [Self::Avif, Self::Webp, Self::Jpeg];
+
+    #[must_use]
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpeg",
+            Self::Webp => "webp",
+            Self::Avif => "avif",
         }
     }
+
+    #[must_use]
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Webp => "image/webp",
+            Self::Avif => "image/avif",
+        }
+    }
+
+    fn from_extension(extension: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.extension() == extension)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -86,6 +170,8 @@ pub enum ThumbSize {
     Thumb,
     Poster,
     Specific(ThumbDimensions),
+    /// A short animation of a video or GIF
+    Preview,
 }
 
 impl Display for ThumbSize {
@@ -94,22 +180,198 @@ impl Display for ThumbSize {
             Self::Thumb => write!(f, "thumbnail"),
             Self::Poster => write!(f, "poster"),
             Self::Specific(dimensions) => write!(f, "thumbnail-{dimensions}"),
+            Self::Preview => write!(f, "preview"),
         }
     }
 }
 
+impl ThumbSize {
+    /// The encoding quality (1-100) of thumbs of the size
+    #[must_use]
+    pub fn quality(&self) -> u8 {
+        match self {
+            Self::Thumb => CONFIG.app.thumbnail_quality,
+            Self::Poster => CONFIG.app.poster_quality,
+            Self::Specific(_) => CONFIG.app.sized_thumbnail_quality,
+            Self::Preview => CONFIG.app.preview_quality,
+        }
+    }
+
+    /// Whether thumbs of the size can be made of files of the type
+    #[must_use]
+    pub fn supports(&self, file_type: &str) -> bool {
+        match self {
+            Self::Preview => file_type == "image/gif" || file_type.starts_with("video/"),
+            _ => file_type.starts_with("image/") || file_type.starts_with("video/"),
+        }
+    }
+
+    /// The formats thumbs of the size can be made in, the preferred ones first
+    #[must_use]
+    pub fn formats(&self) -> &'static [ThumbFormat] {
+        match self {
+            // The only one of them which can be animated
+            Self::Preview => &[ThumbFormat::Webp],
+            _ => &ThumbFormat::ALL,
+        }
+    }
+
+    /// The format made when indexing. Encoding every format up front is slow (AVIF in
+    /// particular) and most never get requested.
+    #[must_use]
+    pub fn base_format(&self) -> ThumbFormat {
+        match self {
+            Self::Preview => ThumbFormat::Webp,
+            _ => ThumbFormat::Jpeg,
+        }
+    }
+
+    /// The `file_data` key of the variant of the size in the format.
+    ///
+    /// JPEG variants use the plain size so thumbs from before other formats existed still match.
+    #[must_use]
+    pub fn key(&self, format: ThumbFormat) -> String {
+        thumb_key(&self.to_string(), format)
+    }
+}
+
+impl FromStr for ThumbSize {
+    type Err = anyhow::Error;
+
+    /// Parse the `file_data` key of the size, see [`Display`]
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "thumbnail" => Ok(Self::Thumb),
+            "poster" => Ok(Self::Poster),
+            "preview" => Ok(Self::Preview),
+            _ => {
+                let (width, height) = s
+                    .strip_prefix("thumbnail-")
+                    .and_then(|x| x.split_once('x'))
+                    .ok_or_else(|| anyhow!("Invalid thumb size: {:?}", s))?;
+
+                Ok(Self::Specific(ThumbDimensions::new(
+                    width.parse()?,
+                    height.parse()?,
+                )))
+            }
+        }
+    }
+}
+
+pub(crate) fn thumb_key(size: &str, format: ThumbFormat) -> String {
+    match format {
+        ThumbFormat::Jpeg => size.to_string(),
+        format => format!("{size}.{}", format.extension()),
+    }
+}
+
+/// The size and format of a thumb from its `file_data` key, see [`ThumbSize::key`]
+#[must_use]
+pub fn parse_thumb_key(key: &str) -> Option<(ThumbSize, ThumbFormat)> {
+    let (size, format) = match key.rsplit_once('.') {
+        Some((size, extension)) => (size, ThumbFormat::from_extension(extension)?),
+        None => (key, ThumbFormat::Jpeg),
+    };
+
+    Some((size.parse().ok()?, format))
+}
+
+/// Whether the `file_data` key is one used for storing a [`ThumbSize`]
+#[must_use]
+pub fn is_thumb_key(key: &str) -> bool {
+    parse_thumb_key(key).is_some()
+}
+
+/// Matches the `file_data` rows of thumbs, along with any other keys starting the same way.
+/// Filter the rows with [`is_thumb_key`].
+#[must_use]
+pub fn thumb_key_condition() -> Condition {
+    [ThumbSize::Thumb, ThumbSize::Poster, ThumbSize::Preview]
+        .iter()
+        .fold(Condition::any(), |acc, x| {
+            acc.add(file_data::Column::Key.starts_with(x.to_string()))
+        })
+}
+
+/// Where a file made of the file goes in the thumbs directory (thumbs, previews, sprites), eg.
+/// `<ulid>.poster.webp`. The name has to tell apart every kind of file made, sizes with the
+/// same dimensions included. Ids can be reused after a file is removed, ULIDs can't.
+pub(crate) fn thumb_file_path(db_file: &files::Model, name: &str) -> PathBuf {
+    CONFIG
+        .app
+        .thumbs_directory()
+        .join(format!("{}.{name}", db_file.ulid.to_lowercase()))
+}
+
 impl From<ThumbSize> for ThumbDimensions {
     fn from(size: ThumbSize) -> Self {
         match size {
             ThumbSize::Thumb => ThumbDimensions::default(),
             ThumbSize::Poster => ThumbDimensions::new(300, 300),
             ThumbSize::Specific(dimensions) => dimensions,
+            ThumbSize::Preview => ThumbDimensions::new(320, 320),
         }
     }
 }
 
 impl FileWatcher {
-    pub async fn get_or_generate_thumb(&self, ulid: &str, size: ThumbSize) -> Result<FileThumb> {
+    pub async fn get_or_generate_thumb(
+        &self,
+        ulid: &str,
+        size: ThumbSize,
+        format: ThumbFormat,
+    ) -> Result<FileThumb> {
+        let db_file = self.get_file_by_ulid(ulid).await?;
+
+        if let Some(thumb) = self.get_thumb(&db_file, &size, format).await? {
+            return Ok(thumb);
+        }
+
+        logger::debug!(file = ?db_file, "Thumb not found in db, generating...");
+
+        self.generate_thumbnail(&db_file, size, format).await
+    }
+
+    /// Get the thumb if it exists, otherwise queue its generation
+    /// ahead of any bulk work and wait for it to finish.
+    #[instrument(skip(self))]
+    pub async fn request_thumb(
+        &self,
+        ulid: &str,
+        size: ThumbSize,
+        format: ThumbFormat,
+    ) -> Result<FileThumb> {
+        let db_file = self.get_file_by_ulid(ulid).await?;
+
+        if let Some(thumb) = self.get_thumb(&db_file, &size, format).await? {
+            return Ok(thumb);
+        }
+
+        let file_type = db_file.file_type.as_deref().unwrap_or_default();
+        if !size.supports(file_type) {
+            bail!("Can't make a {} thumb of {:?} files", size, file_type);
+        }
+
+        let events = self.subscribe_jobs();
+        let job_id = self
+            .enqueue_job(
+                db_file.id,
+                &Job::Thumb(size.clone(), format),
+                JOB_PRIORITY_ON_DEMAND,
+            )
+            .await?;
+
+        time::timeout(THUMB_REQUEST_TIMEOUT, self.wait_for_job(events, job_id))
+            .await
+            .map_err(|_| anyhow!("Timed out waiting for thumb"))??;
+
+        self.get_thumb(&db_file, &size, format)
+            .await?
+            .ok_or_else(|| anyhow!("Thumb not found after generating it"))
+    }
+
+    pub(crate) async fn get_file_by_ulid(&self, ulid: &str) -> Result<files::Model> {
         let db_file = files::Entity::find()
             .filter(files::Column::Ulid.eq(ulid.to_uppercase()))
             .one(self.db())
@@ -118,72 +380,83 @@ impl FileWatcher {
 
         logger::trace!(file = ?db_file, "Found file in db");
 
-        let thumb_key = size.to_string();
-
-        let db_file_thumb = file_data::Entity::find()
-            .filter(
-                Condition::all()
-                    .add(file_data::Column::FileId.eq(db_file.id))
-                    .add(file_data::Column::Key.eq(&thumb_key)),
-            )
-            .one(self.db())
-            .await?;
-
-        if let Some(db_file_thumb) = db_file_thumb {
-            logger::trace!(model = ?db_file_thumb, "Found thumb in db");
-
-            let meta: FileThumbMeta = serde_json::from_str(&db_file_thumb.meta)?;
+        Ok(db_file)
+    }
 
-            let path = CONFIG.app.metadata_directory_absolute(&db_file_thumb.value);
+    async fn get_thumb(
+        &self,
+        db_file: &files::Model,
+        size: &ThumbSize,
+        format: ThumbFormat,
+    ) -> Result<Option<FileThumb>> {
+        let thumb_key = size.key(format);
+
+        let thumb = match self.get_file_data(db_file.id, &thumb_key).await? {
+            Some(FileData::Thumb(x)) => x,
+            _ => return Ok(None),
+        };
 
-            if path.exists() {
-                return Ok(FileThumb {
-                    path,
-                    meta,
-                    created_at: parse_db_date(&db_file.created_at)?,
-                });
-            }
+        logger::trace!(thumb = ?thumb, "Found thumb in db");
 
-            logger::warn!("Couldn't find thumb path from db. Deleting entry");
+        let path = CONFIG.app.metadata_directory_absolute(&thumb.path);
 
-            db_file_thumb.delete(self.db()).await?;
+        if path.exists() {
+            return Ok(Some(FileThumb {
+                path,
+                meta: thumb.into(),
+                created_at: db_file.created_at,
+            }));
         }
 
-        logger::debug!(file = ?db_file, "Thumb not found in db, generating...");
+        logger::warn!("Couldn't find thumb path from db. Deleting entry");
 
-        let file_path = CONFIG.app.directory_absolute(&db_file.path);
-        let file_type = db_file.file_type.unwrap_or_default();
+        self.remove_file_data(db_file.id, &thumb_key).await?;
 
-        self.generate_thumbnail(db_file.id, &file_path, &file_type, size)
-            .await
+        Ok(None)
     }
 
     #[instrument(skip(self))]
     pub(crate) async fn generate_thumbnail(
         &self,
-        file_id: i32,
-        file_path: &Path,
-        file_type: &str,
+        db_file: &files::Model,
         size: impl Into<ThumbSize> + Debug,
+        format: ThumbFormat,
     ) -> Result<FileThumb> {
         logger::trace!("Generating thumb");
 
         let size = size.into();
-        let thumb_key = size.to_string();
-        let dimensions = size.into();
+
+        let file_type = db_file.file_type.as_deref().unwrap_or_default();
+
+        if !size.supports(file_type) {
+            bail!("Can't make a {} thumb of {:?} files", size, file_type);
+        }
+
+        if !size.formats().contains(&format) {
+            bail!("{} thumbs can't be made as {:?}", size, format);
+        }
+
+        let options = ThumbOptions {
+            path: thumb_file_path(db_file, &format!("{size}.{}", format.extension())),
+            dimensions: size.clone().into(),
+            format,
+            quality: size.quality(),
+        };
+
+        let file_id = db_file.id;
+        let file_path = CONFIG.app.directory_absolute(&db_file.path);
 
         let thumb_meta = match file_type {
-            t if t.starts_with("image/") => {
-                self.generate_image_thumbnail(file_path, &format!("{file_id}"), dimensions)
-                    .await?
+            t if matches!(size, ThumbSize::Preview) => {
+                self.generate_preview(&file_path, t, options).await?
             }
             t if t.starts_with("video/") => {
-                self.generate_video_thumbnail(file_path, &format!("{file_id}"), dimensions)
+                let timestamp = self.poster_timestamp(file_id, &file_path).await?;
+
+                self.generate_video_thumbnail(&file_path, options, timestamp)
                     .await?
             }
-            _ => {
-                bail!("Unsupported file type");
-            }
+            _ => self.generate_image_thumbnail(&file_path, options).await?,
         };
 
         logger::trace!(thumb = ?thumb_meta, "Generated thumb");
@@ -191,22 +464,22 @@ impl FileWatcher {
         let thumb_path = thumb_meta.path.clone();
         let meta: FileThumbMeta = thumb_meta.into();
 
-        let thumb_model = file_data::ActiveModel {
-            file_id: Set(file_id),
-            key: Set(thumb_key),
-            value: Set(thumb_path.to_string_lossy().to_string()),
-            meta: Set(serde_json::to_string(&meta)?),
-            ..Default::default()
-        };
-
-        let thumb_model = thumb_model.save(self.db()).await?;
+        let thumb_data = FileData::Thumb(ThumbData {
+            size: size.to_string(),
+            format: meta.format,
+            path: thumb_path.to_string_lossy().to_string(),
+            width: meta.width,
+            height: meta.height,
+            hash: meta.hash.clone(),
+            timestamp: meta.timestamp,
+        });
 
-        logger::trace!(model = ?thumb_model, "Saved thumb to db");
+        let thumb_model = self.add_file_data(file_id, thumb_data).await?;
 
         Ok(FileThumb {
             path: thumb_path,
             meta,
-            created_at: parse_db_date(thumb_model.created_at.clone().as_ref())?,
+            created_at: thumb_model.created_at,
         })
     }
 
@@ -214,15 +487,14 @@ impl FileWatcher {
     pub(crate) async fn generate_image_thumbnail(
         &self,
         image_path: &Path,
-        image_ulid: &str,
-        dimensions: ThumbDimensions,
+        options: ThumbOptions,
     ) -> Result<ThumbGenerateResult> {
-        let thumb_path = CONFIG.app.thumbs_directory().join(format!(
-            "{id}.{w}x{h}.jpeg",
-            id = image_ulid,
-            w = dimensions.width,
-            h = dimensions.height,
-        ));
+        let ThumbOptions {
+            path: thumb_path,
+            dimensions,
+            format,
+            quality,
+        } = options;
 
         let image_path = PathBuf::from(&image_path);
 
@@ -230,15 +502,7 @@ impl FileWatcher {
 
         let img = {
             let image_path = image_path.clone();
-            task::spawn_blocking(move || -> Result<DynamicImage> {
-                ImageReader::open(image_path)
-                    .map_err(|e| anyhow!("Failed to open file: {}", e.to_string()))?
-                    .with_guessed_format()
-                    .map_err(|e| anyhow!("Failed to guess file format: {}", e.to_string()))?
-                    .decode()
-                    .map_err(|e| anyhow!("Failed to decode image: {}", e.to_string()))
-            })
-            .await??
+            task::spawn_blocking(move || open_image(&image_path)).await??
         };
 
         logger::trace!(path = ?image_path, "Parsed image from path");
@@ -252,9 +516,12 @@ impl FileWatcher {
 
         let (thumb_width, thumb_height) = thumb.dimensions();
 
-        let thumb_path = task::spawn_blocking(move || thumb.save(&thumb_path).map(|()| thumb_path))
-            .await?
-            .map_err(|e| anyhow!("Failed to save thumbnail: {}", e.to_string()))?;
+        let thumb_path = task::spawn_blocking(move || -> Result<PathBuf> {
+            fs::write(&thumb_path, encode_thumb(&thumb, format, quality)?)?;
+            Ok(thumb_path)
+        })
+        .await?
+        .map_err(|e| anyhow!("Failed to save thumbnail: {}", e.to_string()))?;
 
         logger::trace!(path = ?thumb_path, "Saved thumbnail");
 
@@ -267,6 +534,8 @@ impl FileWatcher {
             height: thumb_height,
             hash: thumb_hash,
             path: CONFIG.app.metadata_directory_relative(&thumb_path)?.into(),
+            format,
+            timestamp: None,
         })
     }
 
@@ -274,8 +543,8 @@ impl FileWatcher {
     pub(crate) async fn generate_video_thumbnail(
         &self,
         video_path: &Path,
-        video_ulid: &str,
-        dimensions: ThumbDimensions,
+        options: ThumbOptions,
+        timestamp: f64,
     ) -> Result<ThumbGenerateResult> {
         let tmp_file = TempfileBuilder::new()
             .suffix(".jpg")
@@ -283,18 +552,26 @@ impl FileWatcher {
             .map_err(|e| anyhow::anyhow!("Failed to create temporary file: {}", e.to_string()))?;
 
         let tmp_thumb_path = self
-            .extract_first_video_frame(video_path, tmp_file.path())
+            .extract_video_frame(video_path, tmp_file.path(), timestamp)
             .await?;
 
-        self.generate_image_thumbnail(&tmp_thumb_path, video_ulid, dimensions)
-            .await
+        let result = self
+            .generate_image_thumbnail(&tmp_thumb_path, options)
+            .await?;
+
+        Ok(ThumbGenerateResult {
+            timestamp: Some(timestamp),
+            ..result
+        })
     }
 
+    /// Save the frame at the timestamp (in seconds) as an image
     #[instrument(skip(self))]
-    pub(crate) async fn extract_first_video_frame(
+    pub(crate) async fn extract_video_frame(
         &self,
         video_path: &Path,
         extract_path: &Path,
+        timestamp: f64,
     ) -> Result<PathBuf> {
         let ffmpeg_path = which("ffmpeg")?;
 
@@ -302,8 +579,12 @@ impl FileWatcher {
         let cmd = cmd
             .arg("-hide_banner")
             .arg("-y")
+            .args(["-ss", &format!("{timestamp:.3}")])
             .args(["-i", video_path.to_string_lossy().to_string().as_str()])
             .args(["-vframes", "1"])
+            // ffmpeg already turns the frame by the rotation of the video, this makes the
+            // pixels square, as image viewers ignore the aspect ratio stored with the frame
+            .args(["-vf", "scale=iw*sar:ih,setsar=1"])
             .arg(extract_path);
 
         logger::trace!(cmd = ?cmd, "Running ffmpeg command");
@@ -316,9 +597,47 @@ impl FileWatcher {
         logger::trace!(output = ?output, "ffmpeg output");
 
         if !output.status.success() {
-            bail!("Failed to generate video thumbnail");
+            bail!("Failed to extract video frame");
+        }
+
+        // Seeking past the end isn't an error, it only doesn't write anything
+        if fs::metadata(extract_path).map_or(0, |x| x.len()) == 0 {
+            bail!("No video frame at {}s", timestamp);
         }
 
         Ok(extract_path.to_path_buf())
     }
 }
+
+/// Encode the thumb in the format
+fn encode_thumb(thumb: &DynamicImage, format: ThumbFormat, quality: u8) -> Result<Vec<u8>> {
+    let (width, height) = thumb.dimensions();
+
+    let bytes = match format {
+        // No alpha channel, transparent pixels get their color without it
+        ThumbFormat::Jpeg => {
+            let mut bytes = vec![];
+            JpegEncoder::new_with_quality(&mut bytes, quality).encode_image(&thumb.to_rgb8())?;
+            bytes
+        }
+        ThumbFormat::Webp => {
+            let rgba = thumb.to_rgba8();
+            webp::Encoder::from_rgba(&rgba, width, height)
+                .encode(f32::from(quality))
+                .to_vec()
+        }
+        ThumbFormat::Avif => {
+            let rgba = thumb.to_rgba8();
+            let pixels = Img::new(rgba.as_rgba(), width as usize, height as usize);
+
+            ravif::Encoder::new()
+                .with_quality(f32::from(quality))
+                .with_alpha_quality(f32::from(quality))
+                .with_speed(AVIF_SPEED)
+                .encode_rgba(pixels)?
+                .avif_file
+        }
+    };
+
+    Ok(bytes)
+}
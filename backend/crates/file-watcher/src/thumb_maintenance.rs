@@ -0,0 +1,231 @@
+//! Keeping the thumbs directory in line with the `file_data` table.
+//!
+//! Thumbs (and sprites) whose file changed on disk are regenerated, and files in the thumbs
+//! directory no row points to (eg. of removed files) are deleted.
+
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use config::CONFIG;
+use entity::file_data;
+use sea_orm::prelude::*;
+use serde::Serialize;
+use tokio::fs;
+use tracing::instrument;
+use typeshare::typeshare;
+
+use crate::{
+    data::{FileData, ThumbData},
+    helpers::file::file_hash,
+    jobs::{Job, JOB_PRIORITY_BULK},
+    thumb::{is_thumb_key, thumb_key_condition, ThumbSize},
+    FileWatcher,
+};
+
+/// How old an unreferenced thumb has to be before it's deleted, so thumbs which are still
+/// being generated or imported aren't deleted before their row is written.
+pub const THUMB_GC_GRACE_PERIOD: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[typeshare]
+pub struct ThumbMaintenanceReport {
+    /// Thumbs whose hash was checked
+    pub checked: u32,
+    /// Thumbs which were missing or changed, and were queued for generation
+    pub regenerated: u32,
+    /// Files in the thumbs directory no thumb pointed to
+    pub removed: u32,
+    #[typeshare(serialized_as = "number")]
+    pub reclaimed_bytes: u64,
+}
+
+impl FileWatcher {
+    /// Verify every thumb, then delete the files in the thumbs directory no thumb points to.
+    /// `None` if a maintenance is already running.
+    #[instrument(skip(self))]
+    pub async fn maintain_thumbs(&self) -> Result<Option<ThumbMaintenanceReport>> {
+        let Ok(_running) = self.thumb_maintenance.try_lock() else {
+            logger::info!("Thumb maintenance is already running");
+            return Ok(None);
+        };
+
+        let mut report = ThumbMaintenanceReport::default();
+
+        let mut referenced = self.verify_thumbs(&mut report).await?;
+        referenced.extend(self.verify_sprites(&mut report).await?);
+        self.collect_thumb_garbage(&referenced, &mut report).await?;
+
+        logger::info!(report = ?report, "Finished thumb maintenance");
+
+        Ok(Some(report))
+    }
+
+    /// Check the thumbs against their hashes, queueing the missing or changed ones for
+    /// generation. Returns the file names of the thumbs which are fine.
+    async fn verify_thumbs(
+        &self,
+        report: &mut ThumbMaintenanceReport,
+    ) -> Result<HashSet<OsString>> {
+        let mut referenced = HashSet::new();
+
+        let rows = file_data::Entity::find()
+            .filter(thumb_key_condition())
+            .all(self.db())
+            .await?;
+
+        for row in rows.into_iter().filter(|x| is_thumb_key(&x.key)) {
+            let (id, file_id) = (row.id, row.file_id);
+            let name = raw_file_name(&row);
+
+            let thumb = match FileData::try_from(row) {
+                Ok(FileData::Thumb(x)) => x,
+                other => {
+                    if let Err(e) = other {
+                        logger::warn!(err = ?e, "Skipping thumb");
+                    }
+
+                    referenced.extend(name);
+                    continue;
+                }
+            };
+
+            let path = CONFIG.app.metadata_directory_absolute(&thumb.path);
+            report.checked += 1;
+
+            if thumb_matches(&path, &thumb).await {
+                if let Some(name) = path.file_name() {
+                    referenced.insert(name.to_os_string());
+                }
+                continue;
+            }
+
+            logger::warn!(id, file_id, path = ?path, "Thumb is missing or changed, regenerating");
+
+            // The file is unreferenced now, the GC below removes it if it's still there
+            self.remove_file_data(file_id, &thumb.key()).await?;
+
+            let size = thumb.size.parse::<ThumbSize>()?;
+            self.enqueue_job(file_id, &Job::Thumb(size, thumb.format), JOB_PRIORITY_BULK)
+                .await?;
+
+            report.regenerated += 1;
+        }
+
+        Ok(referenced)
+    }
+
+    /// Check the sprites and their tracks like [`Self::verify_thumbs`], queueing the extractor
+    /// again for the missing or changed ones
+    async fn verify_sprites(
+        &self,
+        report: &mut ThumbMaintenanceReport,
+    ) -> Result<HashSet<OsString>> {
+        let mut referenced = HashSet::new();
+
+        let rows = file_data::Entity::find()
+            .filter(file_data::Column::Key.is_in([FileData::SPRITE, FileData::SPRITE_TRACK]))
+            .all(self.db())
+            .await?;
+
+        for row in rows {
+            let file_id = row.file_id;
+            let name = raw_file_name(&row);
+
+            let (path, hash) = match FileData::try_from(row) {
+                Ok(FileData::Sprite(x)) => (x.path, x.hash),
+                Ok(FileData::SpriteTrack(x)) => (x.path, x.hash),
+                other => {
+                    if let Err(e) = other {
+                        logger::warn!(err = ?e, "Skipping sprite");
+                    }
+
+                    referenced.extend(name);
+                    continue;
+                }
+            };
+
+            let path = CONFIG.app.metadata_directory_absolute(&path);
+            report.checked += 1;
+
+            if file_hash(&path).await.is_ok_and(|x| x == hash) {
+                if let Some(name) = path.file_name() {
+                    referenced.insert(name.to_os_string());
+                }
+                continue;
+            }
+
+            logger::warn!(file_id, path = ?path, "Sprite is missing or changed, regenerating");
+
+            // The sprite and its track are made together
+            self.remove_file_data(file_id, FileData::SPRITE).await?;
+            self.remove_file_data(file_id, FileData::SPRITE_TRACK)
+                .await?;
+
+            self.enqueue_job(
+                file_id,
+                &Job::Extract(FileData::SPRITE.to_string()),
+                JOB_PRIORITY_BULK,
+            )
+            .await?;
+
+            report.regenerated += 1;
+        }
+
+        Ok(referenced)
+    }
+
+    /// Delete the files in the thumbs directory which aren't referenced
+    async fn collect_thumb_garbage(
+        &self,
+        referenced: &HashSet<OsString>,
+        report: &mut ThumbMaintenanceReport,
+    ) -> Result<()> {
+        let mut entries = match fs::read_dir(CONFIG.app.thumbs_directory()).await {
+            Ok(x) => x,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let cutoff = SystemTime::now() - THUMB_GC_GRACE_PERIOD;
+
+        while let Some(entry) = entries.next_entry().await? {
+            if referenced.contains(&entry.file_name()) {
+                continue;
+            }
+
+            let meta = entry.metadata().await?;
+
+            if !meta.is_file() || meta.modified()? > cutoff {
+                continue;
+            }
+
+            logger::debug!(path = ?entry.path(), "Removing unreferenced thumb");
+
+            fs::remove_file(entry.path()).await?;
+
+            report.removed += 1;
+            report.reclaimed_bytes += meta.len();
+        }
+
+        Ok(())
+    }
+}
+
+/// The file name in the `value` of the row. Rows which can't be read (eg. written by a newer
+/// version) still keep their file.
+fn raw_file_name(row: &file_data::Model) -> Option<OsString> {
+    Path::new(&row.value).file_name().map(ToOwned::to_owned)
+}
+
+async fn thumb_matches(path: &Path, thumb: &ThumbData) -> bool {
+    match file_hash(path).await {
+        Ok(hash) => hash == thumb.hash,
+        Err(_) => false,
+    }
+}
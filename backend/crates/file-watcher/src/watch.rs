@@ -0,0 +1,271 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use config::CONFIG;
+use notify::{event::ModifyKind, Event, EventKind, RecursiveMode, Watcher};
+use tokio::{
+    fs,
+    sync::mpsc,
+    time::{self, Instant},
+};
+
+use crate::FileWatcher;
+
+/// How long to wait for the event stream to go quiet before handling the changes.
+pub const WATCH_DEBOUNCE: Duration = Duration::from_millis(250);
+/// Upper bound on how long changes can be held back while events keep coming in.
+pub const WATCH_DEBOUNCE_MAX: Duration = Duration::from_secs(1);
+
+impl FileWatcher {
+    /// Watch the configured directory for changes and index/prune the affected paths.
+    ///
+    /// Runs until the underlying watcher is dropped.
+    pub async fn watch(&self) -> Result<()> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let mut watcher =
+            notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+                Ok(event) => {
+                    let _ = tx.send(event);
+                }
+                Err(e) => {
+                    logger::warn!(err = ?e, "Filesystem watcher error");
+                }
+            })
+            .map_err(|e| anyhow!("Failed to create filesystem watcher: {}", e))?;
+
+        let mode = if self.recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+
+        watcher
+            .watch(&CONFIG.app.directory, mode)
+            .map_err(|e| anyhow!("Failed to watch directory: {}", e))?;
+
+        logger::debug!(dir = ?&CONFIG.app.directory, ?mode, "Watching directory");
+
+        while let Some(paths) = next_batch(&mut rx, &CONFIG.app.metadata_directory).await {
+            self.handle_changed_paths(paths).await;
+        }
+
+        Ok(())
+    }
+
+    async fn handle_changed_paths(&self, paths: HashSet<PathBuf>) {
+        logger::debug!(num_paths = paths.len(), "Handling changed paths");
+
+        let mut existing = HashSet::new();
+        let mut removed = Vec::new();
+
+        for path in paths {
+            match fs::metadata(&path).await {
+                Ok(meta) if meta.is_file() => {
+                    if self.remaining_scan_depth(&path).is_some() {
+                        existing.insert(path);
+                    }
+                }
+                Ok(meta) if meta.is_dir() => {
+                    if let Some(depth) = self.remaining_scan_depth(&path) {
+                        existing.extend(self.scan_path(&path, depth).await);
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => {
+                    removed.push(path);
+                }
+            }
+        }
+
+        let existing = existing.into_iter().collect::<Vec<_>>();
+
+        let removed = match self.get_indexed_under(&removed).await {
+            Ok(x) => x,
+            Err(e) => {
+                logger::error!(err = ?e, "Failed to get removed files");
+                vec![]
+            }
+        };
+
+        let (removed, existing) = match self.apply_moves(removed, existing).await {
+            Ok(x) => x,
+            Err(e) => {
+                logger::error!(err = ?e, "Failed to detect moved files");
+                return;
+            }
+        };
+
+        let pruned = match self.prune_files(&removed).await {
+            Ok(x) => x,
+            Err(e) => {
+                logger::error!(err = ?e, "Failed to prune removed files");
+                0
+            }
+        };
+
+        let indexed = self.index_paths(&existing).await;
+
+        logger::debug!(
+            num_removed = pruned,
+            num_indexed = indexed.len(),
+            "Handled changed paths",
+        );
+    }
+}
+
+/// Wait for the next batch of changed paths, ie. until no events came in for
+/// [`WATCH_DEBOUNCE`] or the first one is [`WATCH_DEBOUNCE_MAX`] old.
+///
+/// Returns `None` once the channel is closed and everything was handed out.
+async fn next_batch(
+    rx: &mut mpsc::UnboundedReceiver<Event>,
+    ignore: &Path,
+) -> Option<HashSet<PathBuf>> {
+    let mut pending = HashSet::new();
+    let mut first_event_at = Instant::now();
+    let mut last_event_at = Instant::now();
+
+    loop {
+        let event = if pending.is_empty() {
+            rx.recv().await
+        } else {
+            let deadline =
+                (last_event_at + WATCH_DEBOUNCE).min(first_event_at + WATCH_DEBOUNCE_MAX);
+
+            match time::timeout_at(deadline, rx.recv()).await {
+                Ok(event) => event,
+                Err(_) => return Some(pending),
+            }
+        };
+
+        let Some(event) = event else {
+            return (!pending.is_empty()).then_some(pending);
+        };
+
+        if !is_relevant_event(&event) {
+            continue;
+        }
+
+        logger::trace!(?event, "Got filesystem event");
+
+        if pending.is_empty() {
+            first_event_at = Instant::now();
+        }
+        last_event_at = Instant::now();
+
+        pending.extend(event.paths.into_iter().filter(|x| !x.starts_with(ignore)));
+    }
+}
+
+fn is_relevant_event(event: &Event) -> bool {
+    match event.kind {
+        EventKind::Create(_) | EventKind::Remove(_) => true,
+        EventKind::Modify(kind) => !matches!(kind, ModifyKind::Metadata(_)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{AccessKind, CreateKind, MetadataKind, RemoveKind};
+
+    use super::*;
+
+    fn event(kind: EventKind, path: &str) -> Event {
+        Event::new(kind).add_path(PathBuf::from(path))
+    }
+
+    fn created(path: &str) -> Event {
+        event(EventKind::Create(CreateKind::File), path)
+    }
+
+    fn paths(paths: &[&str]) -> HashSet<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batches_events_until_quiet() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let start = Instant::now();
+
+        tx.send(created("/a/1")).unwrap();
+        tx.send(created("/a/2")).unwrap();
+        tx.send(event(EventKind::Remove(RemoveKind::File), "/a/1"))
+            .unwrap();
+
+        let batch = next_batch(&mut rx, Path::new("/meta")).await;
+
+        assert_eq!(batch, Some(paths(&["/a/1", "/a/2"])));
+        assert_eq!(start.elapsed(), WATCH_DEBOUNCE);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn flushes_after_max_delay() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let start = Instant::now();
+
+        // Events keep coming in quicker than the debounce, every 150ms
+        tokio::spawn(async move {
+            for i in 0..10 {
+                if tx.send(created(&format!("/a/{i}"))).is_err() {
+                    break;
+                }
+                time::sleep(Duration::from_millis(150)).await;
+            }
+        });
+
+        let batch = next_batch(&mut rx, Path::new("/meta")).await.unwrap();
+
+        assert_eq!(start.elapsed(), WATCH_DEBOUNCE_MAX);
+        assert_eq!(
+            batch,
+            paths(&["/a/0", "/a/1", "/a/2", "/a/3", "/a/4", "/a/5", "/a/6"])
+        );
+
+        let batch = next_batch(&mut rx, Path::new("/meta")).await.unwrap();
+
+        assert_eq!(batch, paths(&["/a/7", "/a/8", "/a/9"]));
+        assert_eq!(next_batch(&mut rx, Path::new("/meta")).await, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn skips_irrelevant_events() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        tx.send(event(EventKind::Access(AccessKind::Any), "/a/1"))
+            .unwrap();
+        tx.send(event(
+            EventKind::Modify(ModifyKind::Metadata(MetadataKind::Any)),
+            "/a/2",
+        ))
+        .unwrap();
+        tx.send(created("/meta/thumbs/1.jpeg")).unwrap();
+        tx.send(created("/a/3")).unwrap();
+
+        let batch = next_batch(&mut rx, Path::new("/meta")).await;
+
+        assert_eq!(batch, Some(paths(&["/a/3"])));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hands_out_pending_paths_when_closed() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        tx.send(created("/a/1")).unwrap();
+        drop(tx);
+
+        let start = Instant::now();
+
+        assert_eq!(
+            next_batch(&mut rx, Path::new("/meta")).await,
+            Some(paths(&["/a/1"]))
+        );
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert_eq!(next_batch(&mut rx, Path::new("/meta")).await, None);
+    }
+}
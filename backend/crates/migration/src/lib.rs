@@ -1,13 +1,17 @@
 #![allow(clippy::too_many_lines)]
 
 pub use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::DbBackend;
 
 mod m20220101_000001_create_table;
 mod m20231121_171813_merge_file_metadata;
-
-lazy_static::lazy_static! {
-    pub static ref CURRENT_TIMESTAMP: SimpleExpr = SimpleExpr::Custom(r"(strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))".to_owned());
-}
+mod m20231202_143012_create_jobs_table;
+mod m20231204_101500_unique_tags;
+mod m20231206_120000_create_files_fts;
+mod m20231207_090000_typed_timestamps;
+mod m20231208_090000_search_text_keys;
+mod m20231209_090000_reorient_media;
+mod m20231210_090000_unique_file_data;
 
 pub struct Migrator;
 
@@ -17,6 +21,93 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(m20220101_000001_create_table::Migration),
             Box::new(m20231121_171813_merge_file_metadata::Migration),
+            Box::new(m20231202_143012_create_jobs_table::Migration),
+            Box::new(m20231204_101500_unique_tags::Migration),
+            Box::new(m20231206_120000_create_files_fts::Migration),
+            Box::new(m20231207_090000_typed_timestamps::Migration),
+            Box::new(m20231208_090000_search_text_keys::Migration),
+            Box::new(m20231209_090000_reorient_media::Migration),
+            Box::new(m20231210_090000_unique_file_data::Migration),
         ]
     }
 }
+
+/// The current time as RFC 3339 with milliseconds, eg. `2023-12-06T12:00:00.000Z`
+#[must_use]
+pub fn current_timestamp(backend: DbBackend) -> SimpleExpr {
+    let sql = match backend {
+        DbBackend::Sqlite => r"(strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))",
+        DbBackend::Postgres => {
+            r#"(to_char(now() AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS.MS"Z"'))"#
+        }
+        DbBackend::MySql => {
+            r"(CONCAT(LEFT(DATE_FORMAT(UTC_TIMESTAMP(3), '%Y-%m-%dT%H:%i:%s.%f'), 23), 'Z'))"
+        }
+    };
+
+    SimpleExpr::Custom(sql.to_owned())
+}
+
+/// Column types that differ between the supported databases.
+///
+/// The entities read JSON as strings, which only SQLite can decode from its `json` type, so the
+/// other databases store it as text. Timestamps were stored as text the same way until
+/// `m20231207_090000_typed_timestamps` gave them real types.
+pub trait ColumnDefExt {
+    /// Text that is compared case-insensitively.
+    ///
+    /// MySQL does that by default and Postgres has no collation for it, so there the values
+    /// have to be normalized before they are stored.
+    fn string_nocase(&mut self, backend: DbBackend) -> &mut Self;
+
+    /// A timestamp, see [`current_timestamp`] for the format
+    fn timestamp_text(&mut self, backend: DbBackend) -> &mut Self;
+
+    /// A required timestamp that defaults to the current time
+    fn created_timestamp(&mut self, backend: DbBackend) -> &mut Self;
+
+    /// A required JSON object that defaults to an empty one
+    fn json_object(&mut self, backend: DbBackend) -> &mut Self;
+}
+
+impl ColumnDefExt for ColumnDef {
+    fn string_nocase(&mut self, backend: DbBackend) -> &mut Self {
+        match backend {
+            DbBackend::Sqlite => self.string().extra("collate nocase"),
+            DbBackend::Postgres | DbBackend::MySql => self.string(),
+        }
+    }
+
+    fn timestamp_text(&mut self, backend: DbBackend) -> &mut Self {
+        match backend {
+            DbBackend::Sqlite => self.timestamp(),
+            DbBackend::Postgres | DbBackend::MySql => self.string(),
+        }
+    }
+
+    fn created_timestamp(&mut self, backend: DbBackend) -> &mut Self {
+        self.timestamp_text(backend)
+            .default(current_timestamp(backend))
+            .not_null()
+    }
+
+    fn json_object(&mut self, backend: DbBackend) -> &mut Self {
+        match backend {
+            DbBackend::Sqlite => self.json_binary().not_null().default("{}"),
+            DbBackend::Postgres => self.text().not_null().default("{}"),
+            // Text columns can only have expressions as defaults
+            DbBackend::MySql => self
+                .text()
+                .not_null()
+                .default(SimpleExpr::Custom("('{}')".to_owned())),
+        }
+    }
+}
+
+/// Raw SQL in the migrations quotes identifiers with `"`, which MySQL only accepts as backticks
+pub(crate) fn quote_identifiers(backend: DbBackend, sql: &str) -> String {
+    match backend {
+        DbBackend::MySql => sql.replace('"', "`"),
+        DbBackend::Sqlite | DbBackend::Postgres => sql.to_owned(),
+    }
+}
@@ -1,6 +1,6 @@
 use sea_orm_migration::prelude::*;
 
-use crate::CURRENT_TIMESTAMP;
+use crate::ColumnDefExt;
 
 #[derive(DeriveMigrationName)]
 pub struct Migration;
@@ -8,6 +8,8 @@ pub struct Migration;
 #[async_trait::async_trait]
 impl MigrationTrait for Migration {
     async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let backend = manager.get_database_backend();
+
         {
             let stmt = Table::create()
                 .table(Files::Table)
@@ -21,24 +23,17 @@ impl MigrationTrait for Migration {
                 )
                 .col(
                     ColumnDef::new(Files::Ulid)
-                        .string()
+                        .string_nocase(backend)
                         .not_null()
-                        .unique_key()
-                        .extra("collate nocase"),
+                        .unique_key(),
                 )
                 .col(ColumnDef::new(Files::Path).string().not_null().unique_key())
                 .col(
                     ColumnDef::new(Files::Hash)
-                        .string()
-                        .not_null()
-                        .extra("collate nocase"),
-                )
-                .col(
-                    ColumnDef::new(Files::CreatedAt)
-                        .timestamp()
-                        .default(CURRENT_TIMESTAMP.clone())
+                        .string_nocase(backend)
                         .not_null(),
                 )
+                .col(ColumnDef::new(Files::CreatedAt).created_timestamp(backend))
                 .to_owned();
 
             manager.create_table(stmt).await?;
@@ -69,12 +64,7 @@ impl MigrationTrait for Migration {
                         .primary_key(),
                 )
                 .col(ColumnDef::new(Tags::Name).string().not_null())
-                .col(
-                    ColumnDef::new(Tags::CreatedAt)
-                        .timestamp()
-                        .default(CURRENT_TIMESTAMP.clone())
-                        .not_null(),
-                )
+                .col(ColumnDef::new(Tags::CreatedAt).created_timestamp(backend))
                 .to_owned();
 
             manager.create_table(stmt).await?;
@@ -109,12 +99,7 @@ impl MigrationTrait for Migration {
                 )
                 .col(ColumnDef::new(FilesTags::FileId).integer().not_null())
                 .col(ColumnDef::new(FilesTags::TagId).integer().not_null())
-                .col(
-                    ColumnDef::new(FilesTags::CreatedAt)
-                        .timestamp()
-                        .default(CURRENT_TIMESTAMP.clone())
-                        .not_null(),
-                )
+                .col(ColumnDef::new(FilesTags::CreatedAt).created_timestamp(backend))
                 .foreign_key(&mut fk_file_id)
                 .foreign_key(&mut fk_tag_id)
                 .to_owned();
@@ -147,20 +132,11 @@ impl MigrationTrait for Migration {
                         .not_null()
                         .unique_key(),
                 )
-                .col(
-                    ColumnDef::new(FileMetadata::FileType)
-                        .string()
-                        .extra("collate nocase"),
-                )
+                .col(ColumnDef::new(FileMetadata::FileType).string_nocase(backend))
                 .col(ColumnDef::new(FileMetadata::FileSize).big_integer())
-                .col(ColumnDef::new(FileMetadata::FileCtime).timestamp())
-                .col(ColumnDef::new(FileMetadata::FileMtime).timestamp())
-                .col(
-                    ColumnDef::new(FileMetadata::CreatedAt)
-                        .timestamp()
-                        .default(CURRENT_TIMESTAMP.clone())
-                        .not_null(),
-                )
+                .col(ColumnDef::new(FileMetadata::FileCtime).timestamp_text(backend))
+                .col(ColumnDef::new(FileMetadata::FileMtime).timestamp_text(backend))
+                .col(ColumnDef::new(FileMetadata::CreatedAt).created_timestamp(backend))
                 .foreign_key(&mut fk_file_id)
                 .to_owned();
 
@@ -215,23 +191,12 @@ impl MigrationTrait for Migration {
                 .col(ColumnDef::new(FileData::FileId).integer().not_null())
                 .col(
                     ColumnDef::new(FileData::Key)
-                        .string()
-                        .not_null()
-                        .extra("collate nocase"),
-                )
-                .col(ColumnDef::new(FileData::Value).string().not_null())
-                .col(
-                    ColumnDef::new(FileData::Meta)
-                        .json_binary()
-                        .not_null()
-                        .default("{}"),
-                )
-                .col(
-                    ColumnDef::new(FileData::CreatedAt)
-                        .timestamp()
-                        .default(CURRENT_TIMESTAMP.clone())
+                        .string_nocase(backend)
                         .not_null(),
                 )
+                .col(ColumnDef::new(FileData::Value).string().not_null())
+                .col(ColumnDef::new(FileData::Meta).json_object(backend))
+                .col(ColumnDef::new(FileData::CreatedAt).created_timestamp(backend))
                 .foreign_key(&mut fk_file_id)
                 .to_owned();
 
@@ -268,12 +233,18 @@ impl MigrationTrait for Migration {
     }
 
     async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Tables with foreign keys go first
         manager
-            .drop_table(Table::drop().if_exists().table(Files::Table).to_owned())
+            .drop_table(Table::drop().if_exists().table(FileData::Table).to_owned())
             .await?;
 
         manager
-            .drop_table(Table::drop().if_exists().table(Tags::Table).to_owned())
+            .drop_table(
+                Table::drop()
+                    .if_exists()
+                    .table(FileMetadata::Table)
+                    .to_owned(),
+            )
             .await?;
 
         manager
@@ -281,16 +252,11 @@ impl MigrationTrait for Migration {
             .await?;
 
         manager
-            .drop_table(
-                Table::drop()
-                    .if_exists()
-                    .table(FileMetadata::Table)
-                    .to_owned(),
-            )
+            .drop_table(Table::drop().if_exists().table(Tags::Table).to_owned())
             .await?;
 
         manager
-            .drop_table(Table::drop().if_exists().table(FileData::Table).to_owned())
+            .drop_table(Table::drop().if_exists().table(Files::Table).to_owned())
             .await?;
 
         Ok(())
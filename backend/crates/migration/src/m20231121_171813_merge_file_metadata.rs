@@ -1,6 +1,6 @@
 use sea_orm_migration::prelude::*;
 
-use crate::CURRENT_TIMESTAMP;
+use crate::{quote_identifiers, ColumnDefExt};
 
 #[derive(DeriveMigrationName)]
 pub struct Migration;
@@ -8,14 +8,12 @@ pub struct Migration;
 #[async_trait::async_trait]
 impl MigrationTrait for Migration {
     async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let backend = manager.get_database_backend();
+
         {
             let stmt = Table::alter()
                 .table(Files::Table)
-                .add_column(
-                    ColumnDef::new(Files::FileType)
-                        .string()
-                        .extra("collate nocase"),
-                )
+                .add_column(ColumnDef::new(Files::FileType).string_nocase(backend))
                 .to_owned();
             manager.alter_table(stmt).await?;
 
@@ -27,29 +25,29 @@ impl MigrationTrait for Migration {
 
             let stmt = Table::alter()
                 .table(Files::Table)
-                .add_column(ColumnDef::new(Files::FileCtime).timestamp())
+                .add_column(ColumnDef::new(Files::FileCtime).timestamp_text(backend))
                 .to_owned();
             manager.alter_table(stmt).await?;
 
             let stmt = Table::alter()
                 .table(Files::Table)
-                .add_column(ColumnDef::new(Files::FileMtime).timestamp())
+                .add_column(ColumnDef::new(Files::FileMtime).timestamp_text(backend))
                 .to_owned();
             manager.alter_table(stmt).await?;
 
+            // Correlated subqueries instead of `UPDATE ... FROM`, which MySQL doesn't have
             manager
                 .get_connection()
-                .execute_unprepared(
+                .execute_unprepared(&quote_identifiers(
+                    backend,
                     r#"
                     UPDATE "files" SET
-                        "file_type" = "file_metadata"."file_type",
-                        "file_size" = "file_metadata"."file_size",
-                        "file_ctime" = "file_metadata"."file_ctime",
-                        "file_mtime" = "file_metadata"."file_mtime" 
-                    FROM "file_metadata"
-                    WHERE "files"."id" = "file_metadata"."file_id"
+                        "file_type" = (SELECT "file_type" FROM "file_metadata" WHERE "file_metadata"."file_id" = "files"."id"),
+                        "file_size" = (SELECT "file_size" FROM "file_metadata" WHERE "file_metadata"."file_id" = "files"."id"),
+                        "file_ctime" = (SELECT "file_ctime" FROM "file_metadata" WHERE "file_metadata"."file_id" = "files"."id"),
+                        "file_mtime" = (SELECT "file_mtime" FROM "file_metadata" WHERE "file_metadata"."file_id" = "files"."id")
                     "#,
-                )
+                ))
                 .await?;
         }
 
@@ -66,6 +64,8 @@ impl MigrationTrait for Migration {
     }
 
     async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let backend = manager.get_database_backend();
+
         {
             let mut fk_file_id = ForeignKey::create()
                 .from_tbl(FileMetadata::Table)
@@ -91,20 +91,11 @@ impl MigrationTrait for Migration {
                         .not_null()
                         .unique_key(),
                 )
-                .col(
-                    ColumnDef::new(FileMetadata::FileType)
-                        .string()
-                        .extra("collate nocase"),
-                )
+                .col(ColumnDef::new(FileMetadata::FileType).string_nocase(backend))
                 .col(ColumnDef::new(FileMetadata::FileSize).big_integer())
-                .col(ColumnDef::new(FileMetadata::FileCtime).timestamp())
-                .col(ColumnDef::new(FileMetadata::FileMtime).timestamp())
-                .col(
-                    ColumnDef::new(FileMetadata::CreatedAt)
-                        .timestamp()
-                        .default(CURRENT_TIMESTAMP.clone())
-                        .not_null(),
-                )
+                .col(ColumnDef::new(FileMetadata::FileCtime).timestamp_text(backend))
+                .col(ColumnDef::new(FileMetadata::FileMtime).timestamp_text(backend))
+                .col(ColumnDef::new(FileMetadata::CreatedAt).created_timestamp(backend))
                 .foreign_key(&mut fk_file_id)
                 .to_owned();
 
@@ -138,7 +129,8 @@ impl MigrationTrait for Migration {
 
             manager
                 .get_connection()
-                .execute_unprepared(
+                .execute_unprepared(&quote_identifiers(
+                    backend,
                     r#"
                     INSERT INTO "file_metadata"
                         ("file_id", "file_type", "file_size", "file_ctime", "file_mtime", "created_at")
@@ -146,7 +138,7 @@ impl MigrationTrait for Migration {
                         "id", "file_type", "file_size", "file_ctime", "file_mtime", "created_at"
                     FROM "files"
                     "#,
-                )
+                ))
                 .await?;
         }
 
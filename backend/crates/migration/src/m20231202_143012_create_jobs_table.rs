@@ -0,0 +1,120 @@
+use sea_orm_migration::prelude::*;
+
+use crate::ColumnDefExt;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let backend = manager.get_database_backend();
+
+        let mut fk_file_id = ForeignKey::create()
+            .from_tbl(Jobs::Table)
+            .from_col(Jobs::FileId)
+            .to_tbl(Files::Table)
+            .to_col(Files::Id)
+            .on_delete(ForeignKeyAction::Cascade)
+            .to_owned();
+
+        let stmt = Table::create()
+            .table(Jobs::Table)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(Jobs::Id)
+                    .integer()
+                    .not_null()
+                    .auto_increment()
+                    .primary_key(),
+            )
+            .col(ColumnDef::new(Jobs::FileId).integer().not_null())
+            .col(ColumnDef::new(Jobs::Kind).string_nocase(backend).not_null())
+            .col(ColumnDef::new(Jobs::Payload).json_object(backend))
+            .col(
+                ColumnDef::new(Jobs::Status)
+                    .string_nocase(backend)
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(Jobs::Priority)
+                    .integer()
+                    .not_null()
+                    .default(0),
+            )
+            .col(
+                ColumnDef::new(Jobs::Attempts)
+                    .integer()
+                    .not_null()
+                    .default(0),
+            )
+            .col(ColumnDef::new(Jobs::LastError).string())
+            .col(ColumnDef::new(Jobs::RunAt).created_timestamp(backend))
+            .col(ColumnDef::new(Jobs::CreatedAt).created_timestamp(backend))
+            .foreign_key(&mut fk_file_id)
+            .to_owned();
+
+        manager.create_table(stmt).await?;
+
+        let stmt = Index::create()
+            .if_not_exists()
+            .name(format!(
+                "{}__idx__{}",
+                Jobs::Table.to_string(),
+                Jobs::FileId.to_string(),
+            ))
+            .table(Jobs::Table)
+            .col(Jobs::FileId)
+            .to_owned();
+
+        manager.create_index(stmt).await?;
+
+        let stmt = Index::create()
+            .if_not_exists()
+            .name(format!(
+                "{}__idx__{}_{}_{}",
+                Jobs::Table.to_string(),
+                Jobs::Status.to_string(),
+                Jobs::Priority.to_string(),
+                Jobs::RunAt.to_string(),
+            ))
+            .table(Jobs::Table)
+            .col(Jobs::Status)
+            .col(Jobs::Priority)
+            .col(Jobs::RunAt)
+            .to_owned();
+
+        manager.create_index(stmt).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().if_exists().table(Jobs::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Files {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Jobs {
+    Table,
+    Id,
+    FileId,
+    Kind,
+    Payload,
+    Status,
+    Priority,
+    Attempts,
+    LastError,
+    RunAt,
+    CreatedAt,
+}
@@ -0,0 +1,212 @@
+use sea_orm_migration::{prelude::*, sea_orm::DbBackend};
+
+use crate::quote_identifiers;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let backend = manager.get_database_backend();
+
+        {
+            // Merge tags that only differ in case before making the names unique
+            manager
+                .get_connection()
+                .execute_unprepared(&quote_identifiers(
+                    backend,
+                    r#"
+                    UPDATE "files_tags" SET "tag_id" = (
+                        SELECT MIN("other"."id")
+                        FROM "tags" AS "tag"
+                        JOIN "tags" AS "other" ON LOWER("other"."name") = LOWER("tag"."name")
+                        WHERE "tag"."id" = "files_tags"."tag_id"
+                    )
+                    "#,
+                ))
+                .await?;
+
+            // MySQL can't select from the table it deletes from, except through a derived table
+            manager
+                .get_connection()
+                .execute_unprepared(&quote_identifiers(
+                    backend,
+                    r#"
+                    DELETE FROM "tags" WHERE "id" NOT IN (
+                        SELECT "id" FROM (
+                            SELECT MIN("id") AS "id" FROM "tags" GROUP BY LOWER("name")
+                        ) AS "keep"
+                    )
+                    "#,
+                ))
+                .await?;
+
+            let column = match backend {
+                DbBackend::Sqlite => r#""name" COLLATE NOCASE"#,
+                DbBackend::Postgres => r#"LOWER("name")"#,
+                // The default collation already ignores case
+                DbBackend::MySql => r#""name""#,
+            };
+
+            let if_not_exists = match backend {
+                DbBackend::Sqlite | DbBackend::Postgres => "IF NOT EXISTS ",
+                DbBackend::MySql => "",
+            };
+
+            manager
+                .get_connection()
+                .execute_unprepared(&quote_identifiers(
+                    backend,
+                    &format!(
+                        r#"CREATE UNIQUE INDEX {}"{}__idx__{}" ON "{}" ({})"#,
+                        if_not_exists,
+                        Tags::Table.to_string(),
+                        Tags::Name.to_string(),
+                        Tags::Table.to_string(),
+                        column,
+                    ),
+                ))
+                .await?;
+        }
+
+        {
+            manager
+                .get_connection()
+                .execute_unprepared(&quote_identifiers(
+                    backend,
+                    r#"
+                    DELETE FROM "files_tags" WHERE "id" NOT IN (
+                        SELECT "id" FROM (
+                            SELECT MIN("id") AS "id" FROM "files_tags" GROUP BY "file_id", "tag_id"
+                        ) AS "keep"
+                    )
+                    "#,
+                ))
+                .await?;
+
+            let stmt = Index::create()
+                .if_not_exists()
+                .unique()
+                .name(format!(
+                    "{}__idx__{}_{}",
+                    FilesTags::Table.to_string(),
+                    FilesTags::FileId.to_string(),
+                    FilesTags::TagId.to_string(),
+                ))
+                .table(FilesTags::Table)
+                .col(FilesTags::FileId)
+                .col(FilesTags::TagId)
+                .to_owned();
+
+            manager.create_index(stmt).await?;
+
+            let name = format!(
+                "{}__idx__{}",
+                FilesTags::Table.to_string(),
+                FilesTags::TagId.to_string(),
+            );
+
+            // Kept on MySQL by `down`, see there
+            if !manager
+                .has_index(FilesTags::Table.to_string(), &name)
+                .await?
+            {
+                let stmt = Index::create()
+                    .if_not_exists()
+                    .name(name)
+                    .table(FilesTags::Table)
+                    .col(FilesTags::TagId)
+                    .to_owned();
+
+                manager.create_index(stmt).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let backend = manager.get_database_backend();
+
+        // MySQL drops the indexes it made for foreign keys when another index covers them,
+        // and refuses to drop the last index of a foreign key. Give `file_id` its own index
+        // back, and keep the one on `tag_id`.
+        let file_id_index = format!(
+            "{}__idx__{}",
+            FilesTags::Table.to_string(),
+            FilesTags::FileId.to_string(),
+        );
+
+        if backend == DbBackend::MySql
+            && !manager
+                .has_index(FilesTags::Table.to_string(), &file_id_index)
+                .await?
+        {
+            let stmt = Index::create()
+                .name(file_id_index)
+                .table(FilesTags::Table)
+                .col(FilesTags::FileId)
+                .to_owned();
+
+            manager.create_index(stmt).await?;
+        }
+
+        let mut indexes = vec![
+            (
+                Tags::Table.to_string(),
+                format!(
+                    "{}__idx__{}",
+                    Tags::Table.to_string(),
+                    Tags::Name.to_string()
+                ),
+            ),
+            (
+                FilesTags::Table.to_string(),
+                format!(
+                    "{}__idx__{}_{}",
+                    FilesTags::Table.to_string(),
+                    FilesTags::FileId.to_string(),
+                    FilesTags::TagId.to_string(),
+                ),
+            ),
+        ];
+
+        if backend != DbBackend::MySql {
+            indexes.push((
+                FilesTags::Table.to_string(),
+                format!(
+                    "{}__idx__{}",
+                    FilesTags::Table.to_string(),
+                    FilesTags::TagId.to_string(),
+                ),
+            ));
+        }
+
+        for (table, name) in indexes {
+            let mut stmt = Index::drop().name(name).table(Alias::new(table)).to_owned();
+
+            // MySQL has no `IF EXISTS` for indexes
+            if backend != DbBackend::MySql {
+                stmt.if_exists();
+            }
+
+            manager.drop_index(stmt).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Tags {
+    Table,
+    Name,
+}
+
+#[derive(DeriveIden)]
+enum FilesTags {
+    Table,
+    FileId,
+    TagId,
+}
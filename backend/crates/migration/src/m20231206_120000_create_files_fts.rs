@@ -0,0 +1,493 @@
+use sea_orm_migration::{prelude::*, sea_orm::DbBackend};
+
+use crate::quote_identifiers;
+
+/// `file_data` keys whose values are searchable text.
+///
+/// The triggers are created with this list, so changing it needs a new migration.
+pub(crate) const TEXT_KEYS: &[&str] = &["ocr", "caption"];
+
+/// Triggers on the `files` table, the rest are on `file_data`
+const FILES_TRIGGERS: &[&str] = &[
+    "files__fts_insert",
+    "files__fts_update",
+    "files__fts_delete",
+];
+const FILE_DATA_TRIGGERS: &[&str] = &[
+    "file_data__fts_insert",
+    "file_data__fts_update",
+    "file_data__fts_delete",
+];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        match manager.get_database_backend() {
+            DbBackend::Sqlite => up_sqlite(manager).await,
+            DbBackend::Postgres => up_postgres(manager).await,
+            DbBackend::MySql => up_mysql(manager).await,
+        }
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let backend = manager.get_database_backend();
+
+        for trigger in FILES_TRIGGERS {
+            db.execute_unprepared(&drop_trigger(backend, trigger, "files"))
+                .await?;
+        }
+
+        drop_text_triggers(manager).await?;
+
+        if backend == DbBackend::Postgres {
+            db.execute_unprepared(r#"DROP FUNCTION IF EXISTS "files__fts_sync"()"#)
+                .await?;
+        }
+
+        db.execute_unprepared(&quote_identifiers(
+            backend,
+            r#"DROP TABLE IF EXISTS "files_fts""#,
+        ))
+        .await?;
+
+        Ok(())
+    }
+}
+
+fn drop_trigger(backend: DbBackend, trigger: &str, table: &str) -> String {
+    match backend {
+        DbBackend::Sqlite => format!(r#"DROP TRIGGER IF EXISTS "{trigger}""#),
+        DbBackend::Postgres => format!(r#"DROP TRIGGER IF EXISTS "{trigger}" ON "{table}""#),
+        DbBackend::MySql => format!("DROP TRIGGER IF EXISTS `{trigger}`"),
+    }
+}
+
+fn text_keys(keys: &[&str]) -> String {
+    keys.iter()
+        .map(|x| format!("'{x}'"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// SQL for the searchable text of a file
+fn file_text(backend: DbBackend, file_id: &str, keys: &[&str]) -> String {
+    let concat = match backend {
+        DbBackend::Sqlite => r#"GROUP_CONCAT("value", ' ')"#,
+        DbBackend::Postgres => r#"STRING_AGG("value", ' ')"#,
+        DbBackend::MySql => r#"GROUP_CONCAT("value" SEPARATOR ' ')"#,
+    };
+
+    format!(
+        r#"
+        SELECT COALESCE({concat}, '')
+        FROM "file_data"
+        WHERE "file_id" = {file_id} AND "key" IN ({})
+        "#,
+        text_keys(keys),
+    )
+}
+
+async fn up_sqlite(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let backend = DbBackend::Sqlite;
+
+    // The rowid of the search table is the id of the file
+    db.execute_unprepared(
+        r#"
+        CREATE VIRTUAL TABLE IF NOT EXISTS "files_fts" USING fts5(
+            "path",
+            "text",
+            prefix = '2 3',
+            tokenize = 'unicode61 remove_diacritics 2'
+        )
+        "#,
+    )
+    .await?;
+
+    db.execute_unprepared(&format!(
+        r#"
+        INSERT INTO "files_fts" ("rowid", "path", "text")
+        SELECT "id", "path", ({}) FROM "files"
+        "#,
+        file_text(backend, r#""files"."id""#, TEXT_KEYS),
+    ))
+    .await?;
+
+    db.execute_unprepared(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS "files__fts_insert" AFTER INSERT ON "files" BEGIN
+            INSERT INTO "files_fts" ("rowid", "path", "text") VALUES (new."id", new."path", '');
+        END
+        "#,
+    )
+    .await?;
+
+    db.execute_unprepared(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS "files__fts_update" AFTER UPDATE OF "path" ON "files" BEGIN
+            UPDATE "files_fts" SET "path" = new."path" WHERE "rowid" = new."id";
+        END
+        "#,
+    )
+    .await?;
+
+    db.execute_unprepared(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS "files__fts_delete" AFTER DELETE ON "files" BEGIN
+            DELETE FROM "files_fts" WHERE "rowid" = old."id";
+        END
+        "#,
+    )
+    .await?;
+
+    create_text_triggers_sqlite(manager, TEXT_KEYS).await
+}
+
+/// A table with a weighted `tsvector` of the path and the text. Rows are removed along with
+/// their file by the foreign key, the other changes are kept in sync by triggers.
+async fn up_postgres(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let backend = DbBackend::Postgres;
+
+    // The parser would take a whole path as a single token, so only the words of it are indexed
+    db.execute_unprepared(
+        r#"
+        CREATE TABLE IF NOT EXISTS "files_fts" (
+            "rowid" integer PRIMARY KEY REFERENCES "files" ("id") ON DELETE CASCADE,
+            "path" text NOT NULL,
+            "text" text NOT NULL DEFAULT '',
+            "document" tsvector GENERATED ALWAYS AS (
+                setweight(to_tsvector('simple', regexp_replace("path", '[^[:alnum:]]+', ' ', 'g')), 'A')
+                || setweight(to_tsvector('simple', "text"), 'B')
+            ) STORED
+        )
+        "#,
+    )
+    .await?;
+
+    db.execute_unprepared(
+        r#"CREATE INDEX IF NOT EXISTS "files_fts__idx__document" ON "files_fts" USING GIN ("document")"#,
+    )
+    .await?;
+
+    db.execute_unprepared(&format!(
+        r#"
+        INSERT INTO "files_fts" ("rowid", "path", "text")
+        SELECT "id", "path", ({}) FROM "files"
+        "#,
+        file_text(backend, r#""files"."id""#, TEXT_KEYS),
+    ))
+    .await?;
+
+    db.execute_unprepared(
+        r#"
+        CREATE OR REPLACE FUNCTION "files__fts_sync"() RETURNS trigger LANGUAGE plpgsql AS $$
+        BEGIN
+            IF TG_OP = 'INSERT' THEN
+                INSERT INTO "files_fts" ("rowid", "path") VALUES (NEW."id", NEW."path");
+            ELSE
+                UPDATE "files_fts" SET "path" = NEW."path" WHERE "rowid" = NEW."id";
+            END IF;
+
+            RETURN NULL;
+        END
+        $$
+        "#,
+    )
+    .await?;
+
+    for (trigger, event, table, function) in [
+        ("files__fts_insert", "INSERT", "files", "files__fts_sync"),
+        (
+            "files__fts_update",
+            r#"UPDATE OF "path""#,
+            "files",
+            "files__fts_sync",
+        ),
+    ] {
+        db.execute_unprepared(&drop_trigger(backend, trigger, table))
+            .await?;
+
+        db.execute_unprepared(&format!(
+            r#"
+            CREATE TRIGGER "{trigger}" AFTER {event} ON "{table}"
+            FOR EACH ROW EXECUTE FUNCTION "{function}"()
+            "#
+        ))
+        .await?;
+    }
+
+    create_text_triggers_postgres(manager, TEXT_KEYS).await
+}
+
+/// An InnoDB table with a `FULLTEXT` index over the path and the text. Rows are removed
+/// along with their file by the foreign key, the other changes are kept in sync by triggers.
+async fn up_mysql(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let backend = DbBackend::MySql;
+
+    let execute = |sql: String| async move {
+        db.execute_unprepared(&quote_identifiers(backend, &sql))
+            .await
+    };
+
+    execute(
+        r#"
+        CREATE TABLE IF NOT EXISTS "files_fts" (
+            "rowid" int NOT NULL PRIMARY KEY,
+            "path" text NOT NULL,
+            "text" longtext NOT NULL,
+            FULLTEXT INDEX "files_fts__idx__fulltext" ("path", "text"),
+            FOREIGN KEY ("rowid") REFERENCES "files" ("id") ON DELETE CASCADE
+        )
+        "#
+        .to_owned(),
+    )
+    .await?;
+
+    execute(format!(
+        r#"
+        INSERT INTO "files_fts" ("rowid", "path", "text")
+        SELECT "id", "path", ({}) FROM "files"
+        "#,
+        file_text(backend, r#""files"."id""#, TEXT_KEYS),
+    ))
+    .await?;
+
+    // MySQL only has `CREATE TRIGGER IF NOT EXISTS` since 8.0.29
+    for trigger in FILES_TRIGGERS {
+        execute(drop_trigger(backend, trigger, "")).await?;
+    }
+
+    execute(
+        r#"
+        CREATE TRIGGER "files__fts_insert" AFTER INSERT ON "files" FOR EACH ROW
+        INSERT INTO "files_fts" ("rowid", "path", "text") VALUES (NEW."id", NEW."path", '')
+        "#
+        .to_owned(),
+    )
+    .await?;
+
+    execute(
+        r#"
+        CREATE TRIGGER "files__fts_update" AFTER UPDATE ON "files" FOR EACH ROW
+        UPDATE "files_fts" SET "path" = NEW."path" WHERE "rowid" = NEW."id"
+        "#
+        .to_owned(),
+    )
+    .await?;
+
+    create_text_triggers_mysql(manager, TEXT_KEYS).await
+}
+
+/// Create the triggers keeping the text in the search index in sync with the `file_data` rows
+/// of the given keys
+pub(crate) async fn create_text_triggers(
+    manager: &SchemaManager<'_>,
+    keys: &[&str],
+) -> Result<(), DbErr> {
+    match manager.get_database_backend() {
+        DbBackend::Sqlite => create_text_triggers_sqlite(manager, keys).await,
+        DbBackend::Postgres => create_text_triggers_postgres(manager, keys).await,
+        DbBackend::MySql => create_text_triggers_mysql(manager, keys).await,
+    }
+}
+
+pub(crate) async fn drop_text_triggers(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let backend = manager.get_database_backend();
+
+    for trigger in FILE_DATA_TRIGGERS {
+        db.execute_unprepared(&drop_trigger(backend, trigger, "file_data"))
+            .await?;
+    }
+
+    if backend == DbBackend::Postgres {
+        db.execute_unprepared(r#"DROP FUNCTION IF EXISTS "file_data__fts_sync"()"#)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Set the text of every file in the search index from its `file_data` of the given keys again
+pub(crate) async fn refresh_text(manager: &SchemaManager<'_>, keys: &[&str]) -> Result<(), DbErr> {
+    let backend = manager.get_database_backend();
+
+    manager
+        .get_connection()
+        .execute_unprepared(&quote_identifiers(
+            backend,
+            &format!(
+                r#"UPDATE "files_fts" SET "text" = ({})"#,
+                file_text(backend, r#""files_fts"."rowid""#, keys),
+            ),
+        ))
+        .await?;
+
+    Ok(())
+}
+
+async fn create_text_triggers_sqlite(
+    manager: &SchemaManager<'_>,
+    keys: &[&str],
+) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let backend = DbBackend::Sqlite;
+    let text_keys = text_keys(keys);
+
+    db.execute_unprepared(&format!(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS "file_data__fts_insert" AFTER INSERT ON "file_data"
+        WHEN new."key" IN ({text_keys}) BEGIN
+            UPDATE "files_fts" SET "text" = ({}) WHERE "rowid" = new."file_id";
+        END
+        "#,
+        file_text(backend, r#"new."file_id""#, keys),
+    ))
+    .await?;
+
+    db.execute_unprepared(&format!(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS "file_data__fts_update" AFTER UPDATE ON "file_data"
+        WHEN new."key" IN ({text_keys}) OR old."key" IN ({text_keys}) BEGIN
+            UPDATE "files_fts" SET "text" = ({}) WHERE "rowid" = old."file_id";
+            UPDATE "files_fts" SET "text" = ({}) WHERE "rowid" = new."file_id";
+        END
+        "#,
+        file_text(backend, r#"old."file_id""#, keys),
+        file_text(backend, r#"new."file_id""#, keys),
+    ))
+    .await?;
+
+    db.execute_unprepared(&format!(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS "file_data__fts_delete" AFTER DELETE ON "file_data"
+        WHEN old."key" IN ({text_keys}) BEGIN
+            UPDATE "files_fts" SET "text" = ({}) WHERE "rowid" = old."file_id";
+        END
+        "#,
+        file_text(backend, r#"old."file_id""#, keys),
+    ))
+    .await?;
+
+    Ok(())
+}
+
+async fn create_text_triggers_postgres(
+    manager: &SchemaManager<'_>,
+    keys: &[&str],
+) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let backend = DbBackend::Postgres;
+    let text_keys = text_keys(keys);
+
+    db.execute_unprepared(&format!(
+        r#"
+        CREATE OR REPLACE FUNCTION "file_data__fts_sync"() RETURNS trigger LANGUAGE plpgsql AS $$
+        BEGIN
+            IF TG_OP <> 'INSERT' THEN
+                IF OLD."key" IN ({text_keys}) THEN
+                    UPDATE "files_fts" SET "text" = ({}) WHERE "rowid" = OLD."file_id";
+                END IF;
+            END IF;
+
+            IF TG_OP <> 'DELETE' THEN
+                IF NEW."key" IN ({text_keys}) THEN
+                    UPDATE "files_fts" SET "text" = ({}) WHERE "rowid" = NEW."file_id";
+                END IF;
+            END IF;
+
+            RETURN NULL;
+        END
+        $$
+        "#,
+        file_text(backend, r#"OLD."file_id""#, keys),
+        file_text(backend, r#"NEW."file_id""#, keys),
+    ))
+    .await?;
+
+    for (trigger, event) in [
+        ("file_data__fts_insert", "INSERT"),
+        ("file_data__fts_update", "UPDATE"),
+        ("file_data__fts_delete", "DELETE"),
+    ] {
+        db.execute_unprepared(&drop_trigger(backend, trigger, "file_data"))
+            .await?;
+
+        db.execute_unprepared(&format!(
+            r#"
+            CREATE TRIGGER "{trigger}" AFTER {event} ON "file_data"
+            FOR EACH ROW EXECUTE FUNCTION "file_data__fts_sync"()
+            "#
+        ))
+        .await?;
+    }
+
+    Ok(())
+}
+
+async fn create_text_triggers_mysql(
+    manager: &SchemaManager<'_>,
+    keys: &[&str],
+) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let backend = DbBackend::MySql;
+    let text_keys = text_keys(keys);
+
+    let execute = |sql: String| async move {
+        db.execute_unprepared(&quote_identifiers(backend, &sql))
+            .await
+    };
+
+    for trigger in FILE_DATA_TRIGGERS {
+        execute(drop_trigger(backend, trigger, "")).await?;
+    }
+
+    execute(format!(
+        r#"
+        CREATE TRIGGER "file_data__fts_insert" AFTER INSERT ON "file_data" FOR EACH ROW
+        BEGIN
+            IF NEW."key" IN ({text_keys}) THEN
+                UPDATE "files_fts" SET "text" = ({}) WHERE "rowid" = NEW."file_id";
+            END IF;
+        END
+        "#,
+        file_text(backend, r#"NEW."file_id""#, keys),
+    ))
+    .await?;
+
+    execute(format!(
+        r#"
+        CREATE TRIGGER "file_data__fts_update" AFTER UPDATE ON "file_data" FOR EACH ROW
+        BEGIN
+            IF NEW."key" IN ({text_keys}) OR OLD."key" IN ({text_keys}) THEN
+                UPDATE "files_fts" SET "text" = ({}) WHERE "rowid" = OLD."file_id";
+                UPDATE "files_fts" SET "text" = ({}) WHERE "rowid" = NEW."file_id";
+            END IF;
+        END
+        "#,
+        file_text(backend, r#"OLD."file_id""#, keys),
+        file_text(backend, r#"NEW."file_id""#, keys),
+    ))
+    .await?;
+
+    execute(format!(
+        r#"
+        CREATE TRIGGER "file_data__fts_delete" AFTER DELETE ON "file_data" FOR EACH ROW
+        BEGIN
+            IF OLD."key" IN ({text_keys}) THEN
+                UPDATE "files_fts" SET "text" = ({}) WHERE "rowid" = OLD."file_id";
+            END IF;
+        END
+        "#,
+        file_text(backend, r#"OLD."file_id""#, keys),
+    ))
+    .await?;
+
+    Ok(())
+}
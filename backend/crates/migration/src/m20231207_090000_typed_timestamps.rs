@@ -0,0 +1,209 @@
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{DbBackend, TransactionTrait},
+};
+
+use crate::{current_timestamp, quote_identifiers};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Every timestamp column as `(table, column, required)`
+const COLUMNS: &[(&str, &str, bool)] = &[
+    ("files", "created_at", true),
+    ("files", "file_ctime", false),
+    ("files", "file_mtime", false),
+    ("tags", "created_at", true),
+    ("files_tags", "created_at", true),
+    ("file_data", "created_at", true),
+    ("jobs", "run_at", true),
+    ("jobs", "created_at", true),
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let backend = manager.get_database_backend();
+
+        for &(table, column, required) in COLUMNS {
+            normalize(manager, table, column, required).await?;
+        }
+
+        // SQLite has no real timestamp type, there the normalized text is what the entities read
+        let statements: &[&str] = match backend {
+            DbBackend::Sqlite => &[],
+            DbBackend::Postgres => &[
+                r#"ALTER TABLE "{table}" ALTER COLUMN "{column}" DROP DEFAULT"#,
+                r#"ALTER TABLE "{table}" ALTER COLUMN "{column}" TYPE timestamptz USING "{column}"::timestamptz"#,
+            ],
+            DbBackend::MySql => &[r#"ALTER TABLE "{table}" MODIFY "{column}" datetime(6){null}"#],
+        };
+
+        alter_columns(manager, statements, &now(backend)).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let backend = manager.get_database_backend();
+
+        // The normalized values can still be read as text
+        let statements: &[&str] = match backend {
+            DbBackend::Sqlite => return Ok(()),
+            DbBackend::Postgres => &[
+                r#"ALTER TABLE "{table}" ALTER COLUMN "{column}" DROP DEFAULT"#,
+                r#"ALTER TABLE "{table}" ALTER COLUMN "{column}" TYPE varchar USING to_char("{column}" AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS.MS"Z"')"#,
+            ],
+            DbBackend::MySql => &[
+                r#"ALTER TABLE "{table}" MODIFY "{column}" varchar(255){null}"#,
+                r#"UPDATE "{table}" SET "{column}" = CONCAT(LEFT(DATE_FORMAT("{column}", '%Y-%m-%dT%H:%i:%s.%f'), 23), 'Z')"#,
+            ],
+        };
+
+        alter_columns(manager, statements, &current_timestamp(backend)).await
+    }
+}
+
+/// Run the statements for every column, with `{table}`, `{column}` and `{null}` filled in, then
+/// give the required ones the default
+async fn alter_columns(
+    manager: &SchemaManager<'_>,
+    statements: &[&str],
+    default: &SimpleExpr,
+) -> Result<(), DbErr> {
+    let backend = manager.get_database_backend();
+
+    for &(table, column, required) in COLUMNS {
+        for statement in statements {
+            let null = if required { " NOT NULL" } else { " NULL" };
+            let sql = statement
+                .replace("{table}", table)
+                .replace("{column}", column)
+                .replace("{null}", null);
+
+            manager
+                .get_connection()
+                .execute_unprepared(&quote_identifiers(backend, &sql))
+                .await?;
+        }
+
+        if required {
+            set_default(manager, table, column, default).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The default of required timestamp columns from now on
+fn now(backend: DbBackend) -> SimpleExpr {
+    match backend {
+        // The default can't change without rebuilding the table. Its RFC 3339 reads back fine,
+        // it only orders differently from written values within the same millisecond.
+        DbBackend::Sqlite => current_timestamp(backend),
+        DbBackend::Postgres => SimpleExpr::Custom("now()".to_owned()),
+        DbBackend::MySql => SimpleExpr::Custom("(UTC_TIMESTAMP(6))".to_owned()),
+    }
+}
+
+async fn set_default(
+    manager: &SchemaManager<'_>,
+    table: &str,
+    column: &str,
+    default: &SimpleExpr,
+) -> Result<(), DbErr> {
+    let backend = manager.get_database_backend();
+
+    let SimpleExpr::Custom(default) = default else {
+        unreachable!("defaults are raw SQL");
+    };
+
+    let sql = match backend {
+        DbBackend::Sqlite => return Ok(()),
+        DbBackend::Postgres | DbBackend::MySql => {
+            format!(r#"ALTER TABLE "{table}" ALTER COLUMN "{column}" SET DEFAULT {default}"#)
+        }
+    };
+
+    manager
+        .get_connection()
+        .execute_unprepared(&quote_identifiers(backend, &sql))
+        .await?;
+
+    Ok(())
+}
+
+/// Rewrite every value of the column in the same format.
+///
+/// Older rows have SQLite's `CURRENT_TIMESTAMP` format (`2023-12-06 12:00:00`), newer ones
+/// RFC 3339 with either `Z` or `+00:00`, which don't order correctly as text. Values that
+/// aren't timestamps at all are cleared if the column allows it.
+async fn normalize(
+    manager: &SchemaManager<'_>,
+    table: &str,
+    column: &str,
+    required: bool,
+) -> Result<(), DbErr> {
+    let backend = manager.get_database_backend();
+    let txn = manager.get_connection().begin().await?;
+
+    let rows = txn
+        .query_all(
+            backend.build(
+                Query::select()
+                    .columns([Alias::new("id"), Alias::new(column)])
+                    .from(Alias::new(table))
+                    .and_where(Expr::col(Alias::new(column)).is_not_null()),
+            ),
+        )
+        .await?;
+
+    for row in rows {
+        let id: i32 = row.try_get("", "id")?;
+        let value: String = row.try_get("", column)?;
+
+        let normalized = match parse(&value) {
+            Some(x) => Some(format(backend, x)),
+            None if required => {
+                return Err(DbErr::Migration(format!(
+                    "{table}.{column} of row {id} is not a timestamp: {value:?}"
+                )))
+            }
+            None => None,
+        };
+
+        if normalized.as_ref() == Some(&value) {
+            continue;
+        }
+
+        txn.execute(
+            backend.build(
+                Query::update()
+                    .table(Alias::new(table))
+                    .value(Alias::new(column), normalized)
+                    .and_where(Expr::col(Alias::new("id")).eq(id)),
+            ),
+        )
+        .await?;
+    }
+
+    txn.commit().await
+}
+
+fn parse(value: &str) -> Option<DateTime<Utc>> {
+    value
+        .parse::<DateTime<Utc>>()
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f").map(|x| x.and_utc())
+        })
+        .ok()
+}
+
+/// The format each database reads back as the timestamp
+fn format(backend: DbBackend, value: DateTime<Utc>) -> String {
+    match backend {
+        // What the SQLite driver writes, which orders correctly as text
+        DbBackend::Sqlite => value.to_rfc3339_opts(SecondsFormat::AutoSi, false),
+        DbBackend::Postgres => value.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        DbBackend::MySql => value.format("%Y-%m-%d %H:%M:%S%.6f").to_string(),
+    }
+}
@@ -0,0 +1,30 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20231206_120000_create_files_fts::{
+    self, create_text_triggers, drop_text_triggers, refresh_text,
+};
+
+/// `file_data` keys whose values are searchable text, which gained `description`
+const TEXT_KEYS: &[&str] = &["ocr", "caption", "description"];
+
+/// Creates the search index triggers again with the [`TEXT_KEYS`] and indexes the text of every
+/// file with them.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        drop_text_triggers(manager).await?;
+        create_text_triggers(manager, TEXT_KEYS).await?;
+        refresh_text(manager, TEXT_KEYS).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let keys = m20231206_120000_create_files_fts::TEXT_KEYS;
+
+        drop_text_triggers(manager).await?;
+        create_text_triggers(manager, keys).await?;
+        refresh_text(manager, keys).await
+    }
+}
@@ -0,0 +1,116 @@
+use sea_orm_migration::{prelude::*, sea_orm::prelude::Json};
+
+/// Dimensions, thumbnails and the data made from them now take the rotation of videos, their
+/// sample aspect ratio and the EXIF orientation of images into account. Throws away what was
+/// made before and queues the extractors again for every image and video.
+///
+/// The files of the removed thumbnails are overwritten when they're made again, or deleted by
+/// the thumb maintenance.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// `file_data` keys of the data to make again
+const KEYS: &[&str] = &[
+    "media-dimensions",
+    "thumbnail",
+    "thumbnail.webp",
+    "thumbnail.avif",
+    "poster",
+    "poster.webp",
+    "poster.avif",
+    // Previews are only made as WebP
+    "preview.webp",
+    "blurhash",
+    "phash",
+    "sprite",
+    "sprite-track",
+];
+
+/// How the jobs were stored when this was written, see `file_watcher::jobs`. Extract jobs have
+/// the key of the extractor as a JSON string for payload.
+const JOB_KIND: &str = "extract";
+const JOB_STATUS_PENDING: &str = "pending";
+const JOB_STATUS_RUNNING: &str = "running";
+const JOB_PRIORITY_BULK: i32 = 0;
+
+/// Extractors to queue, each makes the data of its key and the ones it depends on
+const EXTRACTORS: &[&str] = &[
+    "media-dimensions",
+    "poster",
+    "preview",
+    "blurhash",
+    "phash",
+    "sprite",
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let key = || Expr::col(Alias::new("key"));
+
+        // Specific sizes are `thumbnail-{width}x{height}`, in any format
+        let stmt = Query::delete()
+            .from_table(Alias::new("file_data"))
+            .cond_where(
+                Condition::any()
+                    .add(key().is_in(KEYS.iter().copied()))
+                    .add(key().like("thumbnail-%")),
+            )
+            .to_owned();
+
+        manager.exec_stmt(stmt).await?;
+
+        // Extractors skip the types they don't support
+        for extractor in EXTRACTORS {
+            let payload = Json::from(*extractor).to_string();
+
+            let queued = Query::select()
+                .expr(Expr::val(1))
+                .from(Alias::new("jobs"))
+                .and_where(
+                    Expr::col((Alias::new("jobs"), Alias::new("file_id")))
+                        .equals((Alias::new("files"), Alias::new("id"))),
+                )
+                .and_where(Expr::col(Alias::new("kind")).eq(JOB_KIND))
+                .and_where(Expr::col(Alias::new("payload")).eq(payload.as_str()))
+                .and_where(
+                    Expr::col(Alias::new("status")).is_in([JOB_STATUS_PENDING, JOB_STATUS_RUNNING]),
+                )
+                .to_owned();
+
+            let files = Query::select()
+                .column((Alias::new("files"), Alias::new("id")))
+                .exprs([
+                    Expr::val(JOB_KIND),
+                    Expr::val(payload.as_str()),
+                    Expr::val(JOB_STATUS_PENDING),
+                    Expr::val(JOB_PRIORITY_BULK),
+                ])
+                .from(Alias::new("files"))
+                .cond_where(
+                    Condition::any()
+                        .add(Expr::col(Alias::new("file_type")).like("image/%"))
+                        .add(Expr::col(Alias::new("file_type")).like("video/%")),
+                )
+                // Like enqueueing, which doesn't add a job that is already queued
+                .and_where(Expr::exists(queued).not())
+                .to_owned();
+
+            let stmt = Query::insert()
+                .into_table(Alias::new("jobs"))
+                .columns(["file_id", "kind", "payload", "status", "priority"].map(Alias::new))
+                .select_from(files)
+                .map_err(|e| DbErr::Migration(e.to_string()))?
+                .to_owned();
+
+            manager.exec_stmt(stmt).await?;
+        }
+
+        Ok(())
+    }
+
+    // The data is made again either way
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
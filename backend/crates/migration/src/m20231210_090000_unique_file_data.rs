@@ -0,0 +1,75 @@
+use sea_orm_migration::{prelude::*, sea_orm::DbBackend};
+
+use crate::quote_identifiers;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let backend = manager.get_database_backend();
+
+        // Keep the latest of the rows workers wrote for the same data at once. Their files are
+        // left to the thumb maintenance. MySQL can't select from the table it deletes from,
+        // except through a derived table.
+        manager
+            .get_connection()
+            .execute_unprepared(&quote_identifiers(
+                backend,
+                r#"
+                DELETE FROM "file_data" WHERE "id" NOT IN (
+                    SELECT "id" FROM (
+                        SELECT MAX("id") AS "id" FROM "file_data" GROUP BY "file_id", "key"
+                    ) AS "keep"
+                )
+                "#,
+            ))
+            .await?;
+
+        let stmt = Index::create()
+            .if_not_exists()
+            .unique()
+            .name(index_name())
+            .table(FileData::Table)
+            .col(FileData::FileId)
+            .col(FileData::Key)
+            .to_owned();
+
+        manager.create_index(stmt).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let mut stmt = Index::drop()
+            .name(index_name())
+            .table(FileData::Table)
+            .to_owned();
+
+        // MySQL has no `IF EXISTS` for indexes
+        if manager.get_database_backend() != DbBackend::MySql {
+            stmt.if_exists();
+        }
+
+        manager.drop_index(stmt).await?;
+
+        Ok(())
+    }
+}
+
+fn index_name() -> String {
+    format!(
+        "{}__idx__{}_{}",
+        FileData::Table.to_string(),
+        FileData::FileId.to_string(),
+        FileData::Key.to_string(),
+    )
+}
+
+#[derive(DeriveIden)]
+enum FileData {
+    Table,
+    FileId,
+    Key,
+}
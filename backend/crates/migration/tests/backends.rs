@@ -0,0 +1,379 @@
+//! Runs the migrations on every supported database and checks that the schema behaves the same.
+//!
+//! SQLite always runs in memory. Postgres and MySQL need a server, so their tests are ignored
+//! unless asked for, and then fail without its URL. Everything in those databases is dropped:
+//!
+//! ```sh
+//! TEST_POSTGRES_URL=postgres://postgres@localhost/memes_test \
+//! TEST_MYSQL_URL=mysql://root@localhost/memes_test \
+//! cargo test-backends
+//! ```
+
+use entity::{file_data, files, files_tags, jobs, search, tags};
+use migration::{Alias, Expr, Migrator, MigratorTrait, Query};
+use sea_orm_migration::sea_orm::{
+    self, prelude::*, ActiveValue::NotSet, ConnectionTrait, Database, FromQueryResult,
+    IntoActiveModel, QueryOrder, Set,
+};
+
+#[async_std::test]
+async fn sqlite() {
+    check("sqlite::memory:").await;
+}
+
+#[async_std::test]
+#[ignore = "needs a Postgres server in TEST_POSTGRES_URL, run with `cargo test-backends`"]
+async fn postgres() {
+    check_env("TEST_POSTGRES_URL").await;
+}
+
+#[async_std::test]
+#[ignore = "needs a MySQL server in TEST_MYSQL_URL, run with `cargo test-backends`"]
+async fn mysql() {
+    check_env("TEST_MYSQL_URL").await;
+}
+
+async fn check_env(var: &str) {
+    let url = std::env::var(var).unwrap_or_else(|_| panic!("{var} is not set"));
+    check(&url).await;
+}
+
+async fn check(url: &str) {
+    let db = Database::connect(url).await.unwrap();
+
+    Migrator::fresh(&db).await.unwrap();
+    check_schema(&db).await;
+
+    // All the way down and back up, with timestamps in the formats used before they had a type
+    Migrator::reset(&db).await.unwrap();
+    Migrator::up(&db, Some(5)).await.unwrap();
+    insert_text_timestamps(&db).await;
+    Migrator::up(&db, Some(2)).await.unwrap();
+    let file_id = insert_derived_data(&db).await;
+    Migrator::up(&db, None).await.unwrap();
+    check_text_timestamps(&db).await;
+    check_derived_data(&db, file_id).await;
+    check_schema(&db).await;
+}
+
+async fn insert_text_timestamps(db: &DatabaseConnection) {
+    let stmt = Query::insert()
+        .into_table(Alias::new("files"))
+        .columns(
+            [
+                "ulid",
+                "path",
+                "hash",
+                "created_at",
+                "file_ctime",
+                "file_mtime",
+            ]
+            .map(Alias::new),
+        )
+        .values_panic([
+            "01HGZ6CR3AYQ5W0XSCBJ1K3SV8".into(),
+            "memes/old_cat.png".into(),
+            "def456".into(),
+            "2023-12-06 12:00:00".into(),
+            "2023-12-06T12:00:00.123Z".into(),
+            "2023-12-06T12:00:00.123456+00:00".into(),
+        ])
+        .to_owned();
+
+    db.execute(db.get_database_backend().build(&stmt))
+        .await
+        .unwrap();
+}
+
+async fn check_text_timestamps(db: &DatabaseConnection) {
+    let file = files::Entity::find()
+        .filter(files::Column::Path.eq("memes/old_cat.png"))
+        .one(db)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let date = |x: &str| x.parse::<ChronoDateTimeUtc>().unwrap();
+    assert_eq!(file.created_at, date("2023-12-06T12:00:00Z"));
+    assert_eq!(file.file_ctime, Some(date("2023-12-06T12:00:00.123Z")));
+    assert_eq!(file.file_mtime, Some(date("2023-12-06T12:00:00.123456Z")));
+
+    // Typed timestamps compare as such, whatever they were stored as
+    let later = files::Entity::find()
+        .filter(files::Column::CreatedAt.gt(date("2023-12-06T11:59:59.5Z")))
+        .filter(files::Column::FileMtime.lt(date("2023-12-06T12:00:00.2Z")))
+        .count(db)
+        .await
+        .unwrap();
+    assert_eq!(later, 1);
+
+    file.delete(db).await.unwrap();
+}
+
+/// A video with data from before dimensions and thumbnails were oriented, and from before each
+/// key could only be stored once
+async fn insert_derived_data(db: &DatabaseConnection) -> i32 {
+    let file = files::ActiveModel {
+        ulid: Set("01HGZ6CR3AYQ5W0XSCBJ1K3SV9".to_string()),
+        path: Set("memes/sideways.mp4".to_string()),
+        hash: Set("ghi789".to_string()),
+        file_type: Set(Some("video/mp4".to_string())),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .unwrap();
+
+    for key in [
+        "media-dimensions",
+        "media-info",
+        "poster",
+        "poster.webp",
+        "poster-frame",
+        "thumbnail-100x100.avif",
+        "blurhash",
+        "sprite-track",
+        "media-info",
+    ] {
+        file_data::ActiveModel {
+            file_id: Set(file.id),
+            key: Set(key.to_string()),
+            value: Set(String::new()),
+            meta: Set("{}".to_string()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap();
+    }
+
+    jobs::ActiveModel {
+        file_id: Set(file.id),
+        kind: Set("extract".to_string()),
+        payload: Set(r#""poster""#.to_string()),
+        status: Set("pending".to_string()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .unwrap();
+
+    file.id
+}
+
+/// The data made from the video frames is gone and queued to be made again, and only the latest
+/// of the duplicates is left
+async fn check_derived_data(db: &DatabaseConnection, file_id: i32) {
+    let keys = file_data::Entity::find()
+        .filter(file_data::Column::FileId.eq(file_id))
+        .order_by_asc(file_data::Column::Id)
+        .all(db)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|x| x.key)
+        .collect::<Vec<_>>();
+    assert_eq!(keys, ["poster-frame", "media-info"]);
+
+    let jobs = jobs::Entity::find()
+        .filter(jobs::Column::FileId.eq(file_id))
+        .order_by_asc(jobs::Column::Id)
+        .all(db)
+        .await
+        .unwrap();
+    assert!(jobs
+        .iter()
+        .all(|x| x.kind == "extract" && x.status == "pending"));
+    // The poster was already queued
+    assert_eq!(
+        jobs.iter().map(|x| x.payload.as_str()).collect::<Vec<_>>(),
+        [
+            r#""poster""#,
+            r#""media-dimensions""#,
+            r#""preview""#,
+            r#""blurhash""#,
+            r#""phash""#,
+            r#""sprite""#,
+        ]
+    );
+
+    files::Entity::delete_by_id(file_id).exec(db).await.unwrap();
+}
+
+#[derive(Debug, FromQueryResult)]
+struct FtsRow {
+    path: String,
+    text: String,
+}
+
+async fn fts_row(db: &DatabaseConnection, file_id: i32) -> Option<FtsRow> {
+    let stmt = Query::select()
+        .columns([Alias::new("path"), Alias::new("text")])
+        .from(Alias::new("files_fts"))
+        .and_where(Expr::col(Alias::new("rowid")).eq(file_id))
+        .to_owned();
+
+    FtsRow::find_by_statement(db.get_database_backend().build(&stmt))
+        .one(db)
+        .await
+        .unwrap()
+}
+
+/// The default timestamps are the current time
+fn assert_recent(value: ChronoDateTimeUtc) {
+    let age = chrono::Utc::now() - value;
+    assert!(
+        age.num_seconds().abs() < 60,
+        "not the current time: {value:?}"
+    );
+}
+
+async fn check_schema(db: &DatabaseConnection) {
+    let file = files::ActiveModel {
+        ulid: Set("01HGZ6CR3AYQ5W0XSCBJ1K3SV7".to_string()),
+        path: Set("memes/funny_cat.png".to_string()),
+        hash: Set("abc123".to_string()),
+        file_type: Set(Some("image/png".to_string())),
+        file_size: Set(Some(5_000_000_000)),
+        file_mtime: Set(Some("2023-12-06T12:00:00.123456Z".parse().unwrap())),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .unwrap();
+
+    assert_recent(file.created_at);
+    assert_eq!(file.file_size, Some(5_000_000_000));
+
+    let found = files::Entity::find_by_id(file.id)
+        .one(db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.created_at, file.created_at);
+    assert_eq!(found.file_mtime, file.file_mtime);
+
+    // Tag names are unique regardless of case
+    let tag = tags::ActiveModel {
+        name: Set("Cat".to_string()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .unwrap();
+
+    assert_recent(tag.created_at);
+
+    let duplicate = tags::ActiveModel {
+        name: Set("cat".to_string()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await;
+
+    assert!(
+        duplicate.is_err(),
+        "tag names differing in case: {duplicate:?}"
+    );
+
+    let file_tag = files_tags::ActiveModel {
+        file_id: Set(file.id),
+        tag_id: Set(tag.id),
+        ..Default::default()
+    };
+
+    file_tag.clone().insert(db).await.unwrap();
+    assert!(
+        file_tag.insert(db).await.is_err(),
+        "duplicate tag on a file"
+    );
+
+    check_text_search(db, file.id).await;
+
+    let mut file = file.into_active_model();
+    file.path = Set("memes/serious_cat.png".to_string());
+    let file = file.update(db).await.unwrap();
+
+    let row = fts_row(db, file.id).await.unwrap();
+    assert_eq!(row.path, "memes/serious_cat.png");
+
+    // Deleting the file takes everything about it along
+    let file_id = file.id;
+    file.delete(db).await.unwrap();
+
+    assert!(fts_row(db, file_id).await.is_none());
+    assert_eq!(files_tags::Entity::find().count(db).await.unwrap(), 0);
+    assert_eq!(file_data::Entity::find().count(db).await.unwrap(), 0);
+
+    tags::Entity::delete_many().exec(db).await.unwrap();
+}
+
+/// Text data is searchable, other data is not
+async fn check_text_search(db: &DatabaseConnection, file_id: i32) {
+    let data = file_data::ActiveModel {
+        file_id: Set(file_id),
+        key: Set(search::OCR.to_string()),
+        value: Set("hello world".to_string()),
+        meta: NotSet,
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .unwrap();
+
+    assert_eq!(data.meta, "{}");
+    assert_recent(data.created_at);
+
+    let dimensions = file_data::ActiveModel {
+        file_id: Set(file_id),
+        key: Set("media-dimensions".to_string()),
+        value: Set(String::new()),
+        meta: Set(r#"{"width":640,"height":480}"#.to_string()),
+        ..Default::default()
+    };
+
+    dimensions.clone().insert(db).await.unwrap();
+    assert!(
+        dimensions.insert(db).await.is_err(),
+        "duplicate data key on a file"
+    );
+
+    let row = fts_row(db, file_id).await.expect("no search row for file");
+    assert_eq!(row.path, "memes/funny_cat.png");
+    assert_eq!(row.text, "hello world");
+
+    let mut data = data.into_active_model();
+    data.value = Set("goodbye world".to_string());
+    let data = data.update(db).await.unwrap();
+
+    let row = fts_row(db, file_id).await.unwrap();
+    assert_eq!(row.text, "goodbye world");
+
+    data.delete(db).await.unwrap();
+
+    let row = fts_row(db, file_id).await.unwrap();
+    assert_eq!(row.text, "");
+
+    // As is every other kind of text
+    file_data::ActiveModel {
+        file_id: Set(file_id),
+        key: Set(search::DESCRIPTION.to_string()),
+        value: Set("a very serious cat".to_string()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .unwrap();
+
+    let row = fts_row(db, file_id).await.unwrap();
+    assert_eq!(row.text, "a very serious cat");
+
+    // Until the migration which added it is reverted
+    Migrator::down(db, Some(3)).await.unwrap();
+    let row = fts_row(db, file_id).await.unwrap();
+    assert_eq!(row.text, "");
+
+    Migrator::up(db, None).await.unwrap();
+    let row = fts_row(db, file_id).await.unwrap();
+    assert_eq!(row.text, "a very serious cat");
+}
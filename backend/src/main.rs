@@ -1,4 +1,4 @@
-use config::CONFIG;
+use config::{Command, CONFIG};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     match dotenvy::dotenv_override() {
@@ -15,7 +15,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     logger::init();
     logger::debug!(config = ?*CONFIG, "loaded config");
 
-    api::run().expect("api::run failed");
+    match &CONFIG.command {
+        Some(Command::Export {
+            path,
+            archive,
+            thumbnails,
+        }) => api::export(path, *archive, *thumbnails).expect("api::export failed"),
+        Some(Command::Import { path }) => api::import(path).expect("api::import failed"),
+        None => api::run().expect("api::run failed"),
+    }
 
     Ok(())
 }
@@ -1,4 +1,5 @@
 import { fetchApi, paginationToQuery } from "~/lib/server/api";
+import { fileData } from "~/lib/util/fileData";
 import {
   type PageDataIndex,
   type PageDataIndexItem,
@@ -15,14 +16,8 @@ import {
 const MediaItem = ({ item }: { item: PageDataIndexItem }) => {
   const itemUrl = `/api/file/serve/${item.id}`;
   const itemMimeType = item.fileType;
-  const dimensions = item.data.find((x) => x.key === "media-dimensions")
-    ?.meta as
-    | {
-        width: number;
-        height: number;
-      }
-    | undefined;
-  const blurHash = item.data.find((x) => x.key === "blurhash")?.value;
+  const dimensions = fileData(item.data, "mediaDimensions");
+  const blurHash = fileData(item.data, "blurhash")?.hash;
 
   const aspectRatio =
     dimensions?.width && dimensions?.height
@@ -0,0 +1,9 @@
+import { type FileData } from "@gen-types/backend/api";
+
+/** The payload of the first data of the kind, eg. `fileData(item.data, "blurhash")?.hash` */
+export const fileData = <K extends FileData["kind"]>(
+  data: FileData[],
+  kind: K,
+) =>
+  data.find((x): x is Extract<FileData, { kind: K }> => x.kind === kind)
+    ?.data;
@@ -12,6 +12,60 @@ Types are automatically generated from structs annotated with `#[typeshare]`.
  Generated by typeshare 1.7.0
 */
 
+export enum TagMode {
+	/** Files need to have all of the tags */
+	All = "All",
+	/** Files need to have at least one of the tags */
+	Any = "Any",
+}
+
+/**
+ * Filters for file listings. All of the given filters have to match.
+ * 
+ * Dates can be given as `2023`, `2023-06`, `2023-06-01` or a full RFC 3339 timestamp
+ * and cover the whole period, so `modified_to=2023` includes all of 2023.
+ */
+export interface Filter {
+	/** IDs of tags the files need to have */
+	tags: number[];
+	tagMode?: TagMode;
+	/** IDs of tags the files must not have */
+	excludeTags: number[];
+	/** MIME types or type prefixes (eg. `video/`). Files need to match one of them. */
+	types: string[];
+	minSize?: string;
+	maxSize?: string;
+	modifiedFrom?: string;
+	modifiedTo?: string;
+	createdFrom?: string;
+	createdTo?: string;
+	minWidth?: number;
+	maxWidth?: number;
+	minHeight?: number;
+	maxHeight?: number;
+	/** In seconds */
+	minDuration?: number;
+	/** In seconds */
+	maxDuration?: number;
+	/** Container format names (eg. `mp4`). Files need to match one of them. */
+	containers: string[];
+	/** Codec names as reported by ffprobe (eg. `h264`). Files need to match one of them. */
+	videoCodecs: string[];
+	/** Codec names as reported by ffprobe (eg. `aac`). Files need to match one of them. */
+	audioCodecs: string[];
+	minFrameRate?: number;
+	maxFrameRate?: number;
+	/** In bits per second */
+	minBitRate?: string;
+	/** In bits per second */
+	maxBitRate?: string;
+	/** Degrees the videos are turned by (0, 90, 180 or 270). Files need to match one of them. */
+	rotations: number[];
+	/** Pixel formats as reported by ffprobe (eg. `yuv420p`). Files need to match one of them. */
+	pixelFormats: string[];
+	hasAudio?: boolean;
+}
+
 export enum Direction {
 	Desc = "desc",
 	Asc = "asc",
@@ -22,10 +76,19 @@ export interface Order<T> {
 	direction?: Direction;
 }
 
+/**
+ * Either page based (`page`) or cursor based (`cursor`) pagination.
+ * 
+ * Responses include `next` and `prev` cursors in both modes, so a client can start
+ * on a page and continue with cursors. With a cursor the total is not counted.
+ */
 export interface Pagination {
 	page?: number;
 	perPage?: number;
 	totalPages?: number;
+	cursor?: string;
+	next?: string;
+	prev?: string;
 }
 
 export interface PaginationResponse<T> {
@@ -33,12 +96,98 @@ export interface PaginationResponse<T> {
 	pagination: Pagination;
 }
 
-export interface PageDataIndexItemDataItem {
-	key: string;
-	value: string;
-	meta: unknown;
+export interface DuplicatesIndexFile {
+	id: string;
+	name: string;
+	path: string;
+	fileSize?: string;
+	modified?: string;
+	linked: boolean;
+}
+
+export interface DuplicatesIndexGroup {
+	hash: string;
+	/** How much space would be freed by resolving the group */
+	reclaimableSize: string;
+	files: DuplicatesIndexFile[];
+}
+
+export interface DuplicatesIndex {
+	items: DuplicatesIndexGroup[];
+	pagination: Pagination;
+}
+
+export enum DuplicateResolution {
+	/** Delete the other files from disk */
+	Delete = "delete",
+	/** Replace the other files with hard links to the kept file */
+	Hardlink = "hardlink",
+}
+
+export interface DuplicatesResolveRequest {
+	/** ULID of the file to keep */
+	keep: string;
+	action: DuplicateResolution;
 }
 
+export interface PosterRequest {
+	/** Seconds into the video to make the thumbs from, or none to select a frame automatically */
+	timestamp?: number;
+}
+
+export interface SimilarFileItem {
+	id: string;
+	name: string;
+	fileType?: string;
+	/** Hamming distance between the perceptual hashes. Lower is more similar. */
+	distance: number;
+}
+
+export interface JobsIndexCount {
+	kind: string;
+	status: string;
+	count: number;
+}
+
+export interface JobsIndexDeadItem {
+	id: number;
+	fileId: number;
+	kind: string;
+	attempts: number;
+	lastError?: string;
+	createdAt: string;
+}
+
+export interface JobsIndex {
+	counts: JobsIndexCount[];
+	dead: JobsIndexDeadItem[];
+}
+
+export interface PageDataFileThumbnail {
+	/** The size to request from `/file/serve/<ulid>/<size>` */
+	size: string;
+	width: number;
+	height: number;
+}
+
+export interface PageDataIndexItemTag {
+	id: number;
+	name: string;
+}
+
+export type FileData = 
+	| { kind: "thumb", data: ThumbData }
+	| { kind: "blurhash", data: BlurhashData }
+	| { kind: "mediaDimensions", data: MediaDimensions }
+	| { kind: "mediaInfo", data: MediaInfo }
+	| { kind: "phash", data: PhashData }
+	| { kind: "posterFrame", data: PosterFrameData }
+	| { kind: "sprite", data: SpriteData }
+	| { kind: "spriteTrack", data: SpriteTrackData }
+	| { kind: "description", data: DescriptionData }
+	/** Data of extractors this crate doesn't know, eg. text for the search index */
+	| { kind: "other", data: OtherData };
+
 export interface PageDataIndexItem {
 	id: string;
 	name: string;
@@ -46,8 +195,22 @@ export interface PageDataIndexItem {
 	fileType?: string;
 	created?: string;
 	modified?: string;
-	tags: number[];
-	data: PageDataIndexItemDataItem[];
+	tags: PageDataIndexItemTag[];
+	data: FileData[];
+	/** Matching part of the file with the matches wrapped in `<mark>`. Only set for search results. */
+	snippet?: string;
+}
+
+export interface PageDataFile {
+	file: PageDataIndexItem;
+	/** Path relative to the watched directory */
+	path: string;
+	hash: string;
+	thumbnails: PageDataFileThumbnail[];
+	/** Id of the previous file in the index with the same order and filter */
+	prev?: string;
+	/** Id of the next file in the index with the same order and filter */
+	next?: string;
 }
 
 export interface PageDataIndex {
@@ -55,10 +218,161 @@ export interface PageDataIndex {
 	pagination: Pagination;
 }
 
+export interface SearchIndex {
+	items: PageDataIndexItem[];
+	pagination: Pagination;
+}
+
+export interface TagsIndexItem {
+	id: number;
+	name: string;
+	/** Number of files with the tag */
+	count: number;
+	createdAt: string;
+}
+
+export interface TagsIndex {
+	items: TagsIndexItem[];
+}
+
+export interface TagRequest {
+	name: string;
+}
+
+export interface TagsBulkRequest {
+	/** ULIDs of the files to change */
+	files: string[];
+	/** IDs of the tags to add to every file */
+	add?: number[];
+	/** IDs of the tags to remove from every file */
+	remove?: number[];
+}
+
+/**
+ * The image format a thumb is encoded in.
+ * 
+ * Every size can have a variant in each format. JPEG is the one the other thumb based data
+ * (blurhash, phash, ...) is made from, and what clients get when they accept nothing better.
+ * Only the [`ThumbSize::base_format`] is made when indexing, the others when first requested.
+ */
+export enum ThumbFormat {
+	Jpeg = "jpeg",
+	Webp = "webp",
+	Avif = "avif",
+}
+
+export interface ThumbData {
+	/** The `ThumbSize` it was made for, eg. `poster` */
+	size: string;
+	/** Thumbs from before other formats were supported are all JPEG */
+	format?: ThumbFormat;
+	/** Relative to the metadata directory */
+	path: string;
+	width: number;
+	height: number;
+	hash: string;
+	/** Where in the video the frame was taken from, in seconds */
+	timestamp?: number;
+}
+
+export interface BlurhashData {
+	hash: string;
+}
+
+export interface MediaDimensions {
+	width: number;
+	height: number;
+}
+
+/** What ffprobe knows about a video or audio file */
+export interface MediaInfo {
+	/** In seconds */
+	duration?: number;
+	/** Names of the container format, comma separated, eg. `mov,mp4,m4a,3gp,3g2,mj2` */
+	container?: string;
+	videoCodec?: string;
+	audioCodec?: string;
+	/** Average frames per second */
+	frameRate?: number;
+	/** Bits per second of the whole file */
+	bitRate?: number;
+	/**
+	 * Degrees the video has to be turned clockwise to display upright, one of 0, 90, 180
+	 * and 270
+	 */
+	rotation: number;
+	pixelFormat?: string;
+	hasAudio: boolean;
+}
+
+export interface PhashData {
+	/** The 64 bit hash as 16 hex digits */
+	hash: string;
+}
+
+/** The frame of a video its thumbs are made from */
+export interface PosterFrameData {
+	/** In seconds from the start */
+	timestamp: number;
+	/** Whether it was picked by a user instead of selected automatically */
+	manual: boolean;
+}
+
+/** A sheet of evenly spaced frames of a video, for previews while scrubbing */
+export interface SpriteData {
+	/** Relative to the metadata directory */
+	path: string;
+	hash: string;
+	frames: number;
+	columns: number;
+	rows: number;
+	tileWidth: number;
+	tileHeight: number;
+	/** Seconds between two frames */
+	interval: number;
+}
+
+/** The WebVTT thumbnails track mapping the time ranges of a video to regions of its sprite */
+export interface SpriteTrackData {
+	/** Relative to the metadata directory */
+	path: string;
+	hash: string;
+}
+
+/** Titles, descriptions and comments embedded in the file, one per line. Searchable. */
+export interface DescriptionData {
+	text: string;
+}
+
+export interface OtherData {
+	key: string;
+	value: string;
+	meta: unknown;
+}
+
+export interface ThumbMaintenanceReport {
+	/** Thumbs whose hash was checked */
+	checked: number;
+	/** Thumbs which were missing or changed, and were queued for generation */
+	regenerated: number;
+	/** Files in the thumbs directory no thumb pointed to */
+	removed: number;
+	reclaimedBytes: number;
+}
+
 export enum PageDataIndexOrderBy {
 	Modified = "Modified",
 	Created = "Created",
 	Size = "Size",
 	Id = "Id",
+	Duration = "Duration",
+	Container = "Container",
+	VideoCodec = "VideoCodec",
+	AudioCodec = "AudioCodec",
+	FrameRate = "FrameRate",
+	BitRate = "BitRate",
+	Rotation = "Rotation",
+	PixelFormat = "PixelFormat",
+	HasAudio = "HasAudio",
 }
 